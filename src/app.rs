@@ -1,23 +1,94 @@
+use crate::bulk::{BulkJob, BulkProgress, BulkResult, BulkRun};
+use crate::bundle::BundleProgress;
+use crate::changelog::{ChangelogView, FetchedChangelog, PackChangelog};
+use crate::configs::ConfigChange;
+use crate::mods::health::{Inspection, ModProblem};
+use crate::pack::{OptionalChoices, OptionalGroup, Side};
+use crate::update::{AvailableRelease, SelfUpdateProgress};
+use crate::worlds::{WorldBackup, WorldProgress};
 use anyhow::Result;
 use ratatui::widgets::ListState;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
 use tui_input::Input;
 
 pub mod history;
+pub mod packs;
+pub mod settings;
+
+use history::HistoryEntry;
+use packs::Pack;
+use settings::Settings;
 
 #[derive(Debug)]
 pub enum GitProgress {
     Update(String, f64),
-    Success(String),
+    Success(Box<UpdateReport>),
     Failure(String),
+    /// The pack has optional groups; the worker waits for the player's choices, or `None` to cancel.
+    ChooseOptional { groups: Vec<OptionalGroup>, choices: OptionalChoices, reply: Sender<Option<OptionalChoices>> },
+}
+
+/// What a successful update did, for the finished screen and headless output.
+#[derive(Debug)]
+pub struct UpdateReport {
+    pub message: String,
+    /// What the update brought in, unless it was a fresh install.
+    pub changelog: Option<PackChangelog>,
+    /// Problems found in the updated `mods/` folder.
+    pub mod_problems: Vec<ModProblem>,
+    /// The player's config files the pack's defaults changed.
+    pub config_changes: Vec<ConfigChange>,
+    /// Protected files the pack would have overwritten or deleted, kept as they were.
+    pub protected_files: Vec<String>,
+}
+
+/// Where an instance from the history stands compared to its pack's remote branch.
+#[derive(Debug, Clone)]
+pub enum InstanceStatus {
+    Pinned,
+    UpToDate { dirty: usize },
+    /// `commits` is unknown until the new commits have been fetched into the instance.
+    Behind { commits: Option<usize>, dirty: usize },
+    Unknown(String),
+    /// The pack's server couldn't be reached; the reason is shown once on the start screen.
+    Offline,
+}
+
+impl InstanceStatus {
+    pub fn label(&self) -> String {
+        let dirty_suffix = |dirty: &usize| match dirty {
+            0 => String::new(),
+            1 => ", 1 local change".to_string(),
+            n => format!(", {} local changes", n),
+        };
+        match self {
+            InstanceStatus::Pinned => "pinned".to_string(),
+            InstanceStatus::UpToDate { dirty: 0 } => "up to date".to_string(),
+            InstanceStatus::UpToDate { dirty } => format!("dirty{}", dirty_suffix(dirty)),
+            InstanceStatus::Behind { commits: Some(1), dirty } => format!("1 commit behind{}", dirty_suffix(dirty)),
+            InstanceStatus::Behind { commits: Some(n), dirty } => format!("{} commits behind{}", n, dirty_suffix(dirty)),
+            InstanceStatus::Behind { commits: None, dirty } => format!("update available{}", dirty_suffix(dirty)),
+            InstanceStatus::Offline => "offline".to_string(),
+            InstanceStatus::Unknown(reason) => {
+                let reason: String = reason.lines().next().unwrap_or_default().chars().take(60).collect();
+                format!("status unknown ({})", reason)
+            }
+        }
+    }
+
+    pub fn is_behind(&self) -> bool {
+        matches!(self, InstanceStatus::Behind { .. })
+    }
 }
 
 #[derive(Debug)]
 pub enum UpdateStatus {
     UpToDate,
-    UpdateAvailable(String),
+    UpdateAvailable(AvailableRelease),
     Error,
 }
 
@@ -32,20 +103,45 @@ pub enum RunMode {
 pub enum AppState {
     Browsing,
     AwaitingInput,
+    PackSelection { list_state: ListState },
+    FetchingIncoming,
+    ReviewIncoming { changelog: PackChangelog, error: Option<String>, scroll: u16 },
     ConfirmReinit,
+    RenamingInstance { index: usize },
+    ConfirmRemoveInstance { index: usize },
     ConfirmInvalidFolder { path: PathBuf },
+    /// `error` says which duplicates couldn't be removed, shown once the mods are reread.
+    ReadingMods { error: Option<String> },
+    ViewingMods { inspection: Box<Inspection>, list_state: ListState, error: Option<String> },
+    ConfirmUpdateAll { jobs: Vec<BulkJob>, skipped: Vec<BulkResult> },
+    UpdatingAll { message: String, progress: f64 },
+    UpdateAllSummary { results: Vec<BulkResult>, scroll: u16 },
+    /// `outcome` is how the last backup or restore went.
+    WorldBackups { instance: PathBuf, backups: Vec<WorldBackup>, list_state: ListState, outcome: Option<Result<String, String>> },
+    ConfirmRestoreWorld { instance: PathBuf, backup: WorldBackup },
+    WorkingOnWorlds { instance: PathBuf, message: String, progress: f64 },
     InsideInstanceFolderError,
-    ConfirmUpdate { version: String },
+    ConfirmUpdate { release: AvailableRelease },
     FetchingChangelog,
-    ViewingChangelog { content: String, scroll: u16 },
+    ViewingChangelog(Box<ChangelogView>),
     FetchingBranches,
     BranchSelection {
         branches: Vec<String>,
         list_state: ListState,
         selected_branch: Option<String>,
     },
+    InstanceInUse { message: String, branch: String },
     Processing { message: String, progress: f64 },
+    ChoosingOptional { groups: Vec<OptionalGroup>, choices: OptionalChoices, list_state: ListState, reply: Sender<Option<OptionalChoices>> },
     Finished(String),
+    /// `message` is the finished screen to return to; without one the Mods screen is reread.
+    ConfirmRemoveDuplicates { jars: Vec<PathBuf>, instance: PathBuf, message: Option<String> },
+    /// `editing` holds the text of a setting being typed in.
+    Settings { list_state: ListState, editing: Option<Input> },
+    /// Asks where the bundle goes with `create`, or which bundle to install otherwise. `index` is the
+    /// highlighted instance; installing without one asks for the folder next.
+    EnteringBundlePath { create: bool, index: Option<usize> },
+    CreatingBundle { message: String, progress: f64 },
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -61,7 +157,7 @@ pub enum TutorialState {
 
 pub struct App {
     pub mode: RunMode,
-    pub history: Vec<PathBuf>,
+    pub history: Vec<HistoryEntry>,
     pub history_state: ListState,
     pub current_dir: PathBuf,
     pub initial_dir: PathBuf,
@@ -70,24 +166,62 @@ pub struct App {
     pub list_state: ListState,
     pub selected_path: Option<PathBuf>,
     pub confirmed_path: Option<PathBuf>,
+    pub confirmed_branch: Option<String>,
     pub state: AppState,
     pub input: Input,
     pub input_error: Option<String>,
     pub progress_rx: Option<Receiver<GitProgress>>,
     pub update_rx: Option<Receiver<UpdateStatus>>,
-    pub changelog_rx: Option<Receiver<Result<String>>>,
+    pub changelog_rx: Option<Receiver<Result<FetchedChangelog>>>,
+    /// Set after a self-update so the changelog for this version opens once at startup.
+    pub whats_new_version: Option<String>,
     pub branch_rx: Option<Receiver<Result<Vec<String>>>>,
-    pub pending_update: Option<String>,
-    pub should_perform_update: bool,
+    pub status_rx: Option<Receiver<(PathBuf, InstanceStatus)>>,
+    pub connection_rx: Option<Receiver<Option<String>>>,
+    /// Why the packs' servers can't be reached, shown once on the start screen.
+    pub offline: Option<String>,
+    pub incoming_rx: Option<Receiver<Result<PackChangelog>>>,
+    /// What the pending update brings in, shown on the confirmation and finished screens.
+    pub pack_changelog: Option<PackChangelog>,
+    /// Config keys the last update reset, shown on the finished screen.
+    pub config_changes: Vec<ConfigChange>,
+    /// The player's protected files the last update kept, shown on the finished screen.
+    pub protected_files: Vec<String>,
+    /// Warnings about the mods after the last update, shown on the finished screen.
+    pub mod_problems: Vec<ModProblem>,
+    /// Stray copies among `mod_problems` the pack doesn't track, offered for removal.
+    pub removable_duplicates: Vec<PathBuf>,
+    /// Optional group choices made for the running update, saved to the history once it succeeds.
+    pub optional_choices: Option<OptionalChoices>,
+    pub bulk_rx: Option<Receiver<BulkProgress>>,
+    pub mods_rx: Option<Receiver<Inspection>>,
+    pub worlds_rx: Option<Receiver<WorldProgress>>,
+    pub bundle_rx: Option<Receiver<BundleProgress>>,
+    /// The offline bundle the confirmed instance is updated from instead of the pack's remote.
+    pub bundle: Option<PathBuf>,
+    /// Whether picking `bundle` added its pack to `packs.toml`.
+    pub bundle_added_pack: bool,
+    pub self_update_rx: Option<Receiver<SelfUpdateProgress>>,
+    pub bulk_run: Option<BulkRun>,
+    pub instance_statuses: HashMap<PathBuf, InstanceStatus>,
+    pub pending_update: Option<AvailableRelease>,
+    /// Set once a self-update is installed; the new version is started after the TUI closes.
+    pub relaunch: bool,
+    pub settings: Settings,
+    /// Why `settings.toml` couldn't be loaded, shown on the Settings screen until it's saved again.
+    pub settings_error: Option<String>,
     pub gosling_mode: bool,
     pub tutorial: Option<TutorialState>,
     pub tutorial_interactive: bool,
     pub tutorial_paused: bool,
     pub tutorial_step1_expanded: bool,
+    pub side_override: Option<Side>,
+    pub packs: Vec<Pack>,
+    pub selected_pack: Option<Pack>,
 }
 
 impl App {
-    pub fn new(history: Vec<PathBuf>) -> Result<Self> {
+    pub fn new(history: Vec<HistoryEntry>, packs: Vec<Pack>) -> Result<Self> {
         let mut history_state = ListState::default();
         if !history.is_empty() {
             history_state.select(Some(0));
@@ -110,23 +244,112 @@ impl App {
             list_state: ListState::default(),
             selected_path: None,
             confirmed_path: None,
+            confirmed_branch: None,
             state: AppState::Browsing,
             input: Input::default(),
             input_error: None,
             progress_rx: None,
             update_rx: None,
             changelog_rx: None,
+            whats_new_version: None,
             branch_rx: None,
+            status_rx: None,
+            connection_rx: None,
+            offline: None,
+            incoming_rx: None,
+            pack_changelog: None,
+            config_changes: Vec::new(),
+            protected_files: Vec::new(),
+            mod_problems: Vec::new(),
+            removable_duplicates: Vec::new(),
+            optional_choices: None,
+            bulk_rx: None,
+            mods_rx: None,
+            worlds_rx: None,
+            bundle_rx: None,
+            bundle: None,
+            bundle_added_pack: false,
+            self_update_rx: None,
+            bulk_run: None,
+            instance_statuses: HashMap::new(),
             pending_update: None,
-            should_perform_update: false,
+            relaunch: false,
+            settings: Settings::default(),
+            settings_error: None,
             gosling_mode: false,
             tutorial,
             tutorial_interactive,
             tutorial_paused: false,
             tutorial_step1_expanded: false,
+            side_override: None,
+            packs,
+            selected_pack: None,
         })
     }
     // ... rest of the file is unchanged ...
+    /// The side to update the confirmed instance as, unless overridden on the command line.
+    pub fn confirmed_side(&self) -> Side {
+        match (&self.side_override, &self.confirmed_path) {
+            (Some(side), _) => *side,
+            (None, Some(path)) => Side::detect(path),
+            (None, None) => Side::Client,
+        }
+    }
+
+    /// Checks in the background whether any registered pack's server or mirror can be reached.
+    pub fn check_connection(&mut self) {
+        let remotes: Vec<String> = self.packs.iter().flat_map(|pack| pack.remotes()).collect();
+        let (tx, rx) = mpsc::channel();
+        self.connection_rx = Some(rx);
+        thread::spawn(move || {
+            tx.send(crate::net::check_connection(&remotes).err().map(|e| e.to_string())).ok();
+        });
+    }
+
+    /// Checks every remembered instance against its pack's remote in the background.
+    pub fn refresh_instance_statuses(&mut self) {
+        self.check_connection();
+        let targets: Vec<crate::git::StatusTarget> = self.history.iter().filter(|e| e.is_available()).map(|entry| {
+            let pack = self.pack_for(&entry.path);
+            crate::git::StatusTarget {
+                path: entry.path.clone(),
+                side: self.side_override.unwrap_or_else(|| Side::detect(&entry.path)),
+                remotes: pack.as_ref().map(|p| p.remotes()).unwrap_or_default(),
+                branch: entry.branch.clone().or_else(|| pack.map(|p| p.default_branch)),
+                pinned: entry.pinned,
+                optional: entry.optional.clone(),
+                protected: entry.protected.clone(),
+            }
+        }).collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        self.status_rx = Some(rx);
+        self.instance_statuses.clear();
+        thread::spawn(move || {
+            crate::git::check_instance_statuses_threaded(targets, tx);
+        });
+    }
+
+    /// Fetches the updater's changelog in the background to show what's new in this version.
+    pub fn show_whats_new(&mut self, version: String) {
+        let (tx, rx) = mpsc::channel();
+        self.changelog_rx = Some(rx);
+        self.whats_new_version = Some(version);
+        let url = self.settings.self_update.changelog_url.clone();
+        thread::spawn(move || {
+            crate::changelog::fetch_changelog_background(url, tx);
+        });
+    }
+
+    /// The pack an instance was last updated from, if it's still registered.
+    pub fn pack_for(&self, path: &Path) -> Option<Pack> {
+        let entry = self.history.iter().find(|e| e.path == path)?;
+        packs::find(&self.packs, entry.pack.as_deref()?).cloned()
+    }
+
     pub fn init_file_browser(&mut self, path: PathBuf) -> Result<()> {
         let items = Self::read_dir(&path)?;
         let mut list_state = ListState::default();
@@ -159,6 +382,18 @@ impl App {
         self.history_state.select(Some(i));
     }
 
+    /// Moves the highlighted history entry up or down and keeps it highlighted.
+    pub fn move_history_entry(&mut self, up: bool) {
+        if let Some(i) = self.history_state.selected() {
+            let target = if up { i.checked_sub(1) } else { Some(i + 1) };
+            if let Some(j) = target.filter(|j| *j < self.history.len() && i < self.history.len()) {
+                self.history.swap(i, j);
+                self.history_state.select(Some(j));
+                history::save(&self.history).ok();
+            }
+        }
+    }
+
     pub fn read_dir(dir: &Path) -> Result<Vec<PathBuf>> {
         let mut folders: Vec<_> = fs::read_dir(dir)?
             .filter_map(Result::ok)
@@ -1,10 +1,15 @@
+use crate::configs::ConfigMode;
+use crate::pack::OptionalChoices;
+use crate::worlds;
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // Helper to get the base config directory, avoiding code duplication.
-fn get_config_dir() -> Result<PathBuf> {
+pub fn get_config_dir() -> Result<PathBuf> {
     let proj_dirs = ProjectDirs::from("com", "vodkapocalypse", "ModpackUpdater")
         .context("Could not find a valid configuration directory")?;
     let config_dir = proj_dirs.config_dir();
@@ -14,6 +19,11 @@ fn get_config_dir() -> Result<PathBuf> {
 
 // Gets the path to the history file.
 fn get_history_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("history.json"))
+}
+
+// The plain-text history used by earlier versions, migrated on first load.
+fn get_legacy_history_path() -> Result<PathBuf> {
     Ok(get_config_dir()?.join("history.txt"))
 }
 
@@ -47,30 +57,246 @@ pub fn should_start_tutorial() -> bool {
     history_is_empty && !flag_exists
 }
 
-pub fn load() -> Result<Vec<PathBuf>> {
+fn get_last_version_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("last_version"))
+}
+
+/// Records the version that is running now and returns the one that ran before it,
+/// or `None` on the first run.
+pub fn record_running_version(current: &str) -> Option<String> {
+    let path = get_last_version_path().ok()?;
+    let previous = fs::read_to_string(&path).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    if previous.as_deref() != Some(current) {
+        fs::write(&path, current).ok();
+    }
+    previous
+}
+
+/// How the last update of an instance ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UpdateResult {
+    Success,
+    Failed { message: String },
+}
+
+/// An instance the user has updated before, with what we know about its last update.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub pack: Option<String>,
+    #[serde(default)]
+    pub branch: Option<String>,
+    #[serde(default)]
+    pub last_commit: Option<String>,
+    /// Unix timestamp in seconds.
+    #[serde(default)]
+    pub last_updated: Option<u64>,
+    #[serde(default)]
+    pub last_result: Option<UpdateResult>,
+    /// Pinned instances are held at their current version and skipped by bulk actions.
+    #[serde(default)]
+    pub pinned: bool,
+    /// The player's choices for the pack's optional groups. Groups not listed use the pack's default.
+    #[serde(default, skip_serializing_if = "OptionalChoices::is_empty")]
+    pub optional: OptionalChoices,
+    /// Whether the pack's default configs are merged into the player's or overwrite them.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub config_mode: ConfigMode,
+    /// Paths the updater keeps as they are, on top of the built-in saves, screenshots and options.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protected: Vec<String>,
+    /// Zip the instance's worlds before updates that change `mods/`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub backup_worlds: bool,
+    /// How many backups of each world to keep.
+    #[serde(default = "default_keep_world_backups", skip_serializing_if = "is_default_keep_world_backups")]
+    pub keep_world_backups: usize,
+}
+
+fn default_keep_world_backups() -> usize {
+    worlds::DEFAULT_KEEP
+}
+
+fn is_default_keep_world_backups(keep: &usize) -> bool {
+    *keep == worlds::DEFAULT_KEEP
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl HistoryEntry {
+    pub fn new(path: PathBuf, pack: Option<String>) -> Self {
+        Self {
+            path,
+            name: None,
+            pack,
+            branch: None,
+            last_commit: None,
+            last_updated: None,
+            last_result: None,
+            pinned: false,
+            optional: OptionalChoices::new(),
+            config_mode: ConfigMode::default(),
+            protected: Vec::new(),
+            backup_worlds: false,
+            keep_world_backups: worlds::DEFAULT_KEEP,
+        }
+    }
+
+    /// The custom name, or the folder name when the user hasn't set one.
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| self.path.display().to_string())
+        })
+    }
+
+    /// Entries stay in the history when their folder disappears (e.g. an unplugged drive).
+    pub fn is_available(&self) -> bool {
+        self.path.is_dir()
+    }
+}
+
+/// Loads the history. A file that can't be read is moved to `history.json.corrupt` first, so
+/// starting over with an empty history after an error doesn't overwrite it on the next save.
+pub fn load() -> Result<Vec<HistoryEntry>> {
     let path = get_history_path()?;
     if !path.exists() {
+        return migrate_legacy_history();
+    }
+    let parsed = fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(serde_json::from_str(&content)?));
+    parsed.or_else(|e| {
+        let corrupt = path.with_extension("json.corrupt");
+        fs::rename(&path, &corrupt).context(format!("Failed to move the unreadable '{}' aside", path.display()))?;
+        Err(e.context(format!("Failed to read '{}'. It was moved to '{}' and the history starts empty", path.display(), corrupt.display())))
+    })
+}
+
+pub fn save(history: &[HistoryEntry]) -> Result<()> {
+    let path = get_history_path()?;
+    let content = serde_json::to_string_pretty(history)?;
+    // Renamed into place so a crash mid-write can't leave a truncated history behind.
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, &path)?;
+    Ok(())
+}
+
+// Converts `history.txt` (one path per line, optionally followed by a tab and the pack name)
+// and keeps the old file around as a backup.
+fn migrate_legacy_history() -> Result<Vec<HistoryEntry>> {
+    let legacy_path = get_legacy_history_path()?;
+    if !legacy_path.exists() {
         return Ok(Vec::new());
     }
-    let content = fs::read_to_string(path)?;
-    // --- THE FIX: Filter out paths that no longer exist ---
-    let valid_history = content
+    let content = fs::read_to_string(&legacy_path)?;
+    let history: Vec<HistoryEntry> = content
         .lines()
-        .filter(|l| !l.is_empty())
-        .map(PathBuf::from)
-        .filter(|p| p.exists() && p.is_dir()) // Check that the path still exists and is a directory
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| match line.split_once('\t') {
+            Some((path, pack)) => HistoryEntry::new(PathBuf::from(path), Some(pack.to_string())),
+            None => HistoryEntry::new(PathBuf::from(line), None),
+        })
         .collect();
 
-    Ok(valid_history)
+    save(&history)?;
+    fs::rename(&legacy_path, legacy_path.with_extension("txt.bak")).ok();
+    Ok(history)
 }
 
-pub fn save(history: &[PathBuf]) -> Result<()> {
-    let path = get_history_path()?;
-    let content: String = history
-        .iter()
-        .map(|p| p.to_string_lossy().into_owned())
-        .collect::<Vec<String>>()
-        .join("\n");
-    fs::write(path, content)?;
-    Ok(())
-}
\ No newline at end of file
+/// Records a successful update, adding the instance to the history if it's new.
+pub fn record_success(history: &mut Vec<HistoryEntry>, path: &Path, pack: &str, branch: &str, commit: Option<String>) {
+    let index = match history.iter().position(|e| e.path == path) {
+        Some(index) => index,
+        None => {
+            history.push(HistoryEntry::new(path.to_path_buf(), None));
+            history.len() - 1
+        }
+    };
+    let entry = &mut history[index];
+    entry.pack = Some(pack.to_string());
+    entry.branch = Some(branch.to_string());
+    entry.last_commit = commit;
+    entry.last_updated = Some(now());
+    entry.last_result = Some(UpdateResult::Success);
+}
+
+/// Saves the optional group choices made for an instance.
+pub fn record_optional_choices(history: &mut [HistoryEntry], path: &Path, choices: OptionalChoices) {
+    if let Some(entry) = history.iter_mut().find(|e| e.path == path) {
+        entry.optional = choices;
+    }
+}
+
+/// Records a failed update. Instances that were never updated successfully aren't added.
+pub fn record_failure(history: &mut [HistoryEntry], path: &Path, message: &str) {
+    if let Some(entry) = history.iter_mut().find(|e| e.path == path) {
+        entry.last_updated = Some(now());
+        entry.last_result = Some(UpdateResult::Failed { message: message.to_string() });
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Formats a timestamp relative to now, e.g. "3 days ago".
+pub fn format_age(timestamp: u64) -> String {
+    let elapsed = now().saturating_sub(timestamp);
+    let (amount, unit) = match elapsed {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (elapsed / 60, "minute"),
+        3600..=86399 => (elapsed / 3600, "hour"),
+        86400..=2591999 => (elapsed / 86400, "day"),
+        2592000..=31535999 => (elapsed / 2592000, "month"),
+        _ => (elapsed / 31536000, "year"),
+    };
+    format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+/// Formats a timestamp as a UTC date and time, e.g. "2024-05-01 18:30".
+pub fn format_date(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let (hour, minute) = (timestamp % 86400 / 3600, timestamp % 3600 / 60);
+    // Howard Hinnant's days-to-civil conversion.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, minute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_date_handles_leap_years_and_day_boundaries() {
+        assert_eq!(format_date(0), "1970-01-01 00:00");
+        assert_eq!(format_date(951782400), "2000-02-29 00:00");
+        assert_eq!(format_date(1704067199), "2023-12-31 23:59");
+        assert_eq!(format_date(1714588200), "2024-05-01 18:30");
+        // 2100 isn't a leap year.
+        assert_eq!(format_date(4107542340), "2100-02-28 23:59");
+        assert_eq!(format_date(4107542400), "2100-03-01 00:00");
+    }
+
+    #[test]
+    fn format_age_rounds_down_to_the_largest_unit() {
+        assert_eq!(format_age(now()), "just now");
+        assert_eq!(format_age(now() - 60), "1 minute ago");
+        assert_eq!(format_age(now() - 3 * 3600 - 59), "3 hours ago");
+        assert_eq!(format_age(now() - 400 * 86400), "1 year ago");
+    }
+}
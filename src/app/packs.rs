@@ -0,0 +1,124 @@
+use super::history::get_config_dir;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const DEFAULT_PACK_NAME: &str = "Twinkcraft";
+const DEFAULT_REMOTE_URL: &str = "https://github.com/minecraftwithtwink/Twinkcraft-Modpack.git";
+const DEFAULT_BRANCH: &str = "main";
+
+/// A modpack repository the updater can install into an instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pack {
+    pub name: String,
+    pub remote: String,
+    #[serde(default = "default_branch")]
+    pub default_branch: String,
+    /// Raw URL of a changelog to show instead of the updater's own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog_url: Option<String>,
+    /// Git remotes with the same history, tried in order when `remote` can't be used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// Extra Git LFS endpoints, tried in order after the one that belongs to `remote`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lfs_mirrors: Vec<String>,
+}
+
+fn default_branch() -> String {
+    DEFAULT_BRANCH.to_string()
+}
+
+impl Default for Pack {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_PACK_NAME.to_string(),
+            remote: DEFAULT_REMOTE_URL.to_string(),
+            default_branch: default_branch(),
+            changelog_url: None,
+            mirrors: Vec::new(),
+            lfs_mirrors: Vec::new(),
+        }
+    }
+}
+
+impl Pack {
+    /// The Git LFS endpoint that belongs to the pack's remote, following the LFS server discovery rules.
+    pub fn lfs_url(&self) -> String {
+        lfs_url_for(&self.remote)
+    }
+
+    /// The pack's remote followed by its mirrors, in the order they're tried.
+    pub fn remotes(&self) -> Vec<String> {
+        std::iter::once(&self.remote).chain(&self.mirrors).cloned().collect()
+    }
+
+    /// Every LFS endpoint to try, in order: the remote's own, the explicit LFS mirrors, then the
+    /// ones that belong to the Git mirrors.
+    pub fn lfs_urls(&self) -> Vec<String> {
+        let mut urls = vec![self.lfs_url()];
+        let candidates = self.lfs_mirrors.iter().map(|url| url.trim_end_matches('/').to_string()).chain(self.mirrors.iter().map(|m| lfs_url_for(m)));
+        for url in candidates {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+}
+
+fn lfs_url_for(remote: &str) -> String {
+    let remote = remote.trim_end_matches('/');
+    if remote.ends_with(".git") {
+        format!("{}/info/lfs", remote)
+    } else {
+        format!("{}.git/info/lfs", remote)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PacksFile {
+    #[serde(rename = "pack", default)]
+    packs: Vec<Pack>,
+}
+
+pub fn get_packs_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("packs.toml"))
+}
+
+/// Loads the registered packs. The file is created with the built-in pack on first use
+/// so users can find it and add their own.
+pub fn load() -> Result<Vec<Pack>> {
+    let path = get_packs_path()?;
+    if !path.exists() {
+        let packs = vec![Pack::default()];
+        save(&packs)?;
+        return Ok(packs);
+    }
+
+    let content = fs::read_to_string(&path)?;
+    let file: PacksFile = toml::from_str(&content).context(format!("Failed to parse '{}'.", path.display()))?;
+    if file.packs.is_empty() {
+        bail!("'{}' does not define any [[pack]] entries.", path.display());
+    }
+    for pack in &file.packs {
+        if pack.name.trim().is_empty() || pack.remote.trim().is_empty() {
+            bail!("Every pack in '{}' needs a name and a remote.", path.display());
+        }
+        if pack.mirrors.iter().chain(&pack.lfs_mirrors).any(|url| url.trim().is_empty()) {
+            bail!("Pack '{}' in '{}' has an empty mirror.", pack.name, path.display());
+        }
+    }
+    Ok(file.packs)
+}
+
+pub fn save(packs: &[Pack]) -> Result<()> {
+    let content = toml::to_string_pretty(&PacksFile { packs: packs.to_vec() })?;
+    fs::write(get_packs_path()?, content)?;
+    Ok(())
+}
+
+pub fn find<'a>(packs: &'a [Pack], name: &str) -> Option<&'a Pack> {
+    packs.iter().find(|pack| pack.name == name)
+}
@@ -0,0 +1,480 @@
+use super::history::get_config_dir;
+use crate::bulk::DEFAULT_PARALLEL_JOBS;
+use crate::update::config::UpdateConfig;
+use crate::update::UpdateChannel;
+use anyhow::{bail, Context, Result};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use toml_edit::{DocumentMut, Item, Table};
+
+pub const MAX_CONCURRENCY: usize = 16;
+pub const MAX_TIMEOUT: u64 = 300;
+pub const MAX_RETRIES: u32 = 10;
+
+/// Written on first use, so the file explains itself to people editing it by hand.
+const TEMPLATE: &str = r#"# Modpack Updater settings. Edit them here or on the Settings screen (S on the start screen).
+
+[audio]
+# Play music while the updater is open.
+music = true
+# Music volume, from 0 to 100.
+volume = 10
+# Play the menu sounds.
+sound_effects = true
+
+[appearance]
+# "default", "high_contrast" or "monochrome".
+theme = "default"
+
+[updates]
+# "stable", or "beta" to be offered prereleases of the updater too.
+channel = "stable"
+# How many instances Update All updates at the same time, from 1 to 16.
+concurrency = 2
+# Remove files the pack doesn't track from the folders it owns (mods, kubejs, resourcepacks, ...).
+clean_managed_dirs = true
+# Branch to preselect when installing a pack that has it, instead of the pack's default.
+# default_branch = "beta"
+
+[self_update]
+# When to look for a new version of the updater: "launch", "daily" or "never".
+check = "launch"
+# Raw URL of the updater's own changelog, shown with C and after a self-update.
+changelog_url = "https://raw.githubusercontent.com/minecraftwithtwink/Modpack-Updater/main/CHANGELOG.md"
+
+[self_update.source]
+# Where new versions come from: a GitHub repository's releases, or kind = "manifest" with the
+# url of a JSON file listing them.
+kind = "github"
+owner = "minecraftwithtwink"
+repo = "Modpack-Updater"
+api = "https://api.github.com"
+
+[network]
+# Proxy for every download, e.g. "http://proxy.example:8080". Without one the system proxy is used.
+# proxy = "http://proxy.example:8080"
+# PEM file with an extra certificate authority to trust, e.g. for a proxy that inspects HTTPS.
+# ca_certificate = "/path/to/company-ca.pem"
+# Seconds to wait for a server to answer before giving up, from 1 to 300.
+timeout = 10
+# How many times to try again after a timeout or a dropped connection, from 0 to 10.
+retries = 2
+"#;
+
+/// The player's preferences, from `settings.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub appearance: AppearanceSettings,
+    pub updates: UpdateSettings,
+    pub self_update: UpdateConfig,
+    pub network: NetworkSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioSettings {
+    pub music: bool,
+    /// Percent.
+    pub volume: u8,
+    pub sound_effects: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self { music: true, volume: 10, sound_effects: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppearanceSettings {
+    pub theme: Theme,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Default,
+    /// Brighter colors, for terminals where the dim ones are hard to read.
+    HighContrast,
+    /// No colors at all; highlights are shown reversed.
+    Monochrome,
+}
+
+impl Theme {
+    pub fn next(self) -> Self {
+        match self {
+            Theme::Default => Theme::HighContrast,
+            Theme::HighContrast => Theme::Monochrome,
+            Theme::Monochrome => Theme::Default,
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Theme::Default => write!(f, "default"),
+            Theme::HighContrast => write!(f, "high contrast"),
+            Theme::Monochrome => write!(f, "monochrome"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateSettings {
+    pub channel: UpdateChannel,
+    /// How many instances Update All runs at once.
+    pub concurrency: usize,
+    pub clean_managed_dirs: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self { channel: UpdateChannel::default(), concurrency: DEFAULT_PARALLEL_JOBS, clean_managed_dirs: true, default_branch: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_certificate: Option<PathBuf>,
+    /// Seconds.
+    pub timeout: u64,
+    pub retries: u32,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self { proxy: None, ca_certificate: None, timeout: 10, retries: 2 }
+    }
+}
+
+/// The rows of the Settings screen, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Music,
+    Volume,
+    SoundEffects,
+    Theme,
+    Channel,
+    UpdateCheck,
+    Concurrency,
+    CleanManagedDirs,
+    DefaultBranch,
+    Proxy,
+    CaCertificate,
+    Timeout,
+    Retries,
+}
+
+impl Field {
+    pub const ALL: [Field; 13] = [
+        Field::Music,
+        Field::Volume,
+        Field::SoundEffects,
+        Field::Theme,
+        Field::Channel,
+        Field::UpdateCheck,
+        Field::Concurrency,
+        Field::CleanManagedDirs,
+        Field::DefaultBranch,
+        Field::Proxy,
+        Field::CaCertificate,
+        Field::Timeout,
+        Field::Retries,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::Music => "Music",
+            Field::Volume => "Music volume",
+            Field::SoundEffects => "Sound effects",
+            Field::Theme => "Theme",
+            Field::Channel => "Update channel",
+            Field::UpdateCheck => "Check for updates",
+            Field::Concurrency => "Update All at once",
+            Field::CleanManagedDirs => "Clean pack folders",
+            Field::DefaultBranch => "Preferred branch",
+            Field::Proxy => "Proxy",
+            Field::CaCertificate => "CA certificate",
+            Field::Timeout => "Network timeout",
+            Field::Retries => "Network retries",
+        }
+    }
+
+    pub fn hint(self) -> &'static str {
+        match self {
+            Field::Music => "Play music while the updater is open.",
+            Field::Volume => "How loud the music plays.",
+            Field::SoundEffects => "Play the menu sounds.",
+            Field::Theme => "High contrast brightens dim text; monochrome drops colors entirely.",
+            Field::Channel => "Beta also offers prereleases of the updater.",
+            Field::UpdateCheck => "When to look for a new version of the updater. Daily skips the check for a day after finding nothing.",
+            Field::Concurrency => "How many instances Update All updates at the same time.",
+            Field::CleanManagedDirs => "Remove files the pack doesn't track from mods, kubejs, resourcepacks and the like.",
+            Field::DefaultBranch => "Preselected when installing a pack that has this branch. Empty uses the pack's default.",
+            Field::Proxy => "Used for every download, e.g. http://proxy:8080. Empty uses the system proxy.",
+            Field::CaCertificate => "A PEM file with an extra certificate authority to trust, e.g. for a proxy that inspects HTTPS.",
+            Field::Timeout => "How long to wait for a server to answer before giving up.",
+            Field::Retries => "How many times to try again after a timeout or a dropped connection.",
+        }
+    }
+
+    pub fn value(self, settings: &Settings) -> String {
+        let on_off = |on: bool| if on { "on".to_string() } else { "off".to_string() };
+        match self {
+            Field::Music => on_off(settings.audio.music),
+            Field::Volume => format!("{}%", settings.audio.volume),
+            Field::SoundEffects => on_off(settings.audio.sound_effects),
+            Field::Theme => settings.appearance.theme.to_string(),
+            Field::Channel => settings.updates.channel.to_string(),
+            Field::UpdateCheck => settings.self_update.check.to_string(),
+            Field::Concurrency => settings.updates.concurrency.to_string(),
+            Field::CleanManagedDirs => on_off(settings.updates.clean_managed_dirs),
+            Field::DefaultBranch => settings.updates.default_branch.clone().unwrap_or_else(|| "pack default".to_string()),
+            Field::Proxy => settings.network.proxy.clone().unwrap_or_else(|| "system".to_string()),
+            Field::CaCertificate => settings.network.ca_certificate.as_ref().map_or_else(|| "system".to_string(), |path| path.display().to_string()),
+            Field::Timeout => format!("{} s", settings.network.timeout),
+            Field::Retries => settings.network.retries.to_string(),
+        }
+    }
+
+    /// Typed in rather than stepped through.
+    pub fn is_text(self) -> bool {
+        matches!(self, Field::DefaultBranch | Field::Proxy | Field::CaCertificate)
+    }
+
+    pub fn text(self, settings: &Settings) -> String {
+        match self {
+            Field::DefaultBranch => settings.updates.default_branch.clone().unwrap_or_default(),
+            Field::Proxy => settings.network.proxy.clone().unwrap_or_default(),
+            Field::CaCertificate => settings.network.ca_certificate.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    /// Sets a text field; empty text unsets it.
+    pub fn set_text(self, settings: &mut Settings, text: &str) {
+        let text = Some(text.trim().to_string()).filter(|text| !text.is_empty());
+        match self {
+            Field::DefaultBranch => settings.updates.default_branch = text,
+            Field::Proxy => settings.network.proxy = text,
+            Field::CaCertificate => settings.network.ca_certificate = text.map(PathBuf::from),
+            _ => {}
+        }
+    }
+
+    /// Moves the setting one step forward or back, toggling the on/off ones.
+    pub fn step(self, settings: &mut Settings, forward: bool) {
+        match self {
+            Field::Music => settings.audio.music = !settings.audio.music,
+            Field::Volume => {
+                settings.audio.volume = if forward { settings.audio.volume.saturating_add(5).min(100) } else { settings.audio.volume.saturating_sub(5) };
+            }
+            Field::SoundEffects => settings.audio.sound_effects = !settings.audio.sound_effects,
+            Field::Theme => {
+                settings.appearance.theme = if forward { settings.appearance.theme.next() } else { settings.appearance.theme.next().next() };
+            }
+            Field::Channel => settings.updates.channel = settings.updates.channel.toggled(),
+            Field::UpdateCheck => {
+                settings.self_update.check = if forward { settings.self_update.check.next() } else { settings.self_update.check.next().next() };
+            }
+            Field::Concurrency => {
+                let concurrency = settings.updates.concurrency;
+                settings.updates.concurrency = if forward { (concurrency + 1).min(MAX_CONCURRENCY) } else { concurrency.saturating_sub(1).max(1) };
+            }
+            Field::CleanManagedDirs => settings.updates.clean_managed_dirs = !settings.updates.clean_managed_dirs,
+            Field::Timeout => {
+                let timeout = settings.network.timeout;
+                settings.network.timeout = if forward { (timeout + 5).min(MAX_TIMEOUT) } else { timeout.saturating_sub(5).max(5) };
+            }
+            Field::Retries => {
+                let retries = settings.network.retries;
+                settings.network.retries = if forward { (retries + 1).min(MAX_RETRIES) } else { retries.saturating_sub(1) };
+            }
+            Field::DefaultBranch | Field::Proxy | Field::CaCertificate => {}
+        }
+    }
+}
+
+lazy_static! {
+    // The saved settings, for the background threads that download things.
+    static ref CURRENT: RwLock<Settings> = RwLock::new(Settings::default());
+}
+
+/// The settings as last loaded or saved.
+pub fn current() -> Settings {
+    CURRENT.read().map(|settings| settings.clone()).unwrap_or_default()
+}
+
+fn set_current(settings: &Settings) {
+    if let Ok(mut current) = CURRENT.write() {
+        *current = settings.clone();
+    }
+}
+
+pub fn get_settings_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("settings.toml"))
+}
+
+// The update channel used to be stored on its own.
+fn get_legacy_channel_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("update_channel"))
+}
+
+// The self-update settings used to be in a file of their own too.
+fn get_legacy_update_config_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("update.toml"))
+}
+
+// Takes over the `update.toml` of earlier versions, which is kept as `update.toml.bak` once its
+// settings are saved here. One that can't be used is moved to `update.toml.corrupt` instead, so it
+// isn't tried again on every launch.
+fn migrate_update_config(settings: &mut Settings) -> Result<()> {
+    let legacy = get_legacy_update_config_path()?;
+    let Ok(content) = fs::read_to_string(&legacy) else { return Ok(()) };
+    let parsed = toml::from_str::<UpdateConfig>(&content)
+        .map_err(anyhow::Error::from)
+        .and_then(|config| config.validate().map(|_| config));
+    settings.self_update = match parsed {
+        Ok(config) => config,
+        Err(e) => {
+            let corrupt = legacy.with_extension("toml.corrupt");
+            fs::rename(&legacy, &corrupt).context(format!("Failed to move the unreadable '{}' aside", legacy.display()))?;
+            return Err(e.context(format!(
+                "Failed to read '{}'. It was moved to '{}' and the default self-update settings are in use",
+                legacy.display(),
+                corrupt.display()
+            )));
+        }
+    };
+    save(settings).context(format!("Failed to move the settings in '{}'", legacy.display()))?;
+    fs::rename(&legacy, legacy.with_extension("toml.bak")).ok();
+    Ok(())
+}
+
+/// Loads and checks the settings. The file is created from the commented template on first use,
+/// taking over the update channel and `update.toml` earlier versions kept in files of their own.
+/// Taking over `update.toml` doesn't fail the load; its error comes back as a warning instead.
+pub fn load() -> Result<(Settings, Option<anyhow::Error>)> {
+    let path = get_settings_path()?;
+    if !path.exists() {
+        let mut settings = Settings::default();
+        let legacy = get_legacy_channel_path()?;
+        if let Ok(channel) = fs::read_to_string(&legacy) {
+            if channel.trim() == "beta" {
+                settings.updates.channel = UpdateChannel::Beta;
+            }
+        }
+        fs::write(&path, TEMPLATE)?;
+        save(&settings)?;
+        fs::remove_file(&legacy).ok();
+        let warning = migrate_update_config(&mut settings).err();
+        return Ok((settings, warning));
+    }
+
+    let content = fs::read_to_string(&path)?;
+    let mut settings: Settings = toml::from_str(&content).context(format!("Failed to parse '{}'", path.display()))?;
+    validate(&settings).context(format!("Invalid setting in '{}'", path.display()))?;
+    set_current(&settings);
+    let warning = migrate_update_config(&mut settings).err();
+    Ok((settings, warning))
+}
+
+pub fn validate(settings: &Settings) -> Result<()> {
+    if settings.audio.volume > 100 {
+        bail!("audio.volume must be between 0 and 100, not {}.", settings.audio.volume);
+    }
+    if !(1..=MAX_CONCURRENCY).contains(&settings.updates.concurrency) {
+        bail!("updates.concurrency must be between 1 and {}, not {}.", MAX_CONCURRENCY, settings.updates.concurrency);
+    }
+    if settings.updates.default_branch.as_deref().is_some_and(|branch| branch.trim().is_empty()) {
+        bail!("updates.default_branch can't be empty; leave it out to use each pack's default.");
+    }
+    settings.self_update.validate()?;
+    if let Some(proxy) = &settings.network.proxy {
+        let scheme = proxy.split_once("://").map(|(scheme, _)| scheme);
+        // libgit2 only talks HTTP to a proxy, and reqwest is built without its `socks` feature, so
+        // neither fetches nor downloads could use one.
+        if matches!(scheme, Some("socks4" | "socks4a" | "socks5" | "socks5h")) {
+            bail!("network.proxy '{}' is a SOCKS proxy, which isn't supported. Use an http:// or https:// proxy.", proxy);
+        }
+        if !matches!(scheme, Some("http" | "https")) {
+            bail!("network.proxy must be an http:// or https:// URL, not '{}'.", proxy);
+        }
+    }
+    if let Some(path) = settings.network.ca_certificate.as_deref().filter(|path| !Path::is_file(path)) {
+        bail!("network.ca_certificate '{}' is not a file.", path.display());
+    }
+    if !(1..=MAX_TIMEOUT).contains(&settings.network.timeout) {
+        bail!("network.timeout must be between 1 and {} seconds, not {}.", MAX_TIMEOUT, settings.network.timeout);
+    }
+    if settings.network.retries > MAX_RETRIES {
+        bail!("network.retries must be between 0 and {}, not {}.", MAX_RETRIES, settings.network.retries);
+    }
+    Ok(())
+}
+
+/// Writes the settings, keeping the comments and layout of the file as it is.
+pub fn save(settings: &Settings) -> Result<()> {
+    validate(settings)?;
+    let path = get_settings_path()?;
+    let mut doc: DocumentMut = fs::read_to_string(&path).ok()
+        .and_then(|content| content.parse().ok())
+        .unwrap_or_else(|| TEMPLATE.parse().expect("the settings template is valid TOML"));
+    let values: DocumentMut = toml::to_string(settings)?.parse()?;
+
+    for (section, table) in values.iter() {
+        let Some(table) = table.as_table() else { continue };
+        if !doc.contains_table(section) {
+            doc[section] = toml_edit::table();
+        }
+        let target = doc[section].as_table_mut().context(format!("[{}] in '{}' is not a table.", section, path.display()))?;
+        merge_table(target, table, section)?;
+    }
+    fs::write(&path, doc.to_string())?;
+    set_current(settings);
+    Ok(())
+}
+
+// Copies `values` into `target`, keeping the comments around the values that are already there.
+fn merge_table(target: &mut Table, values: &Table, section: &str) -> Result<()> {
+    // Optional keys that are unset are removed; commented-out examples stay.
+    let stale: Vec<String> = target.iter().map(|(key, _)| key.to_string()).filter(|key| !values.contains_key(key)).collect();
+    for key in stale {
+        target.remove(&key);
+    }
+    for (key, value) in values.iter() {
+        if let Some(table) = value.as_table() {
+            if !target.get(key).is_some_and(Item::is_table) {
+                target[key] = toml_edit::table();
+            }
+            let subsection = format!("{}.{}", section, key);
+            merge_table(target[key].as_table_mut().context(format!("[{}] is not a table.", subsection))?, table, &subsection)?;
+            continue;
+        }
+        match target.get_mut(key).and_then(Item::as_value_mut) {
+            Some(existing) => {
+                let decor = existing.decor().clone();
+                *existing = value.as_value().cloned().context(format!("{}.{} is not a value.", section, key))?;
+                *existing.decor_mut() = decor;
+            }
+            None => target[key] = value.clone(),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use crate::app::history::HistoryEntry;
+use crate::app::packs::{self, Pack};
+use crate::app::settings;
+use crate::app::GitProgress;
+use crate::git::{self, UpdateOptions};
+use crate::pack::Side;
+use crate::running;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// How many instances are updated at the same time unless told otherwise.
+pub const DEFAULT_PARALLEL_JOBS: usize = 2;
+
+/// One instance to update as part of "Update all".
+#[derive(Debug, Clone)]
+pub struct BulkJob {
+    pub path: PathBuf,
+    pub name: String,
+    pub branch: String,
+    pub options: UpdateOptions,
+}
+
+#[derive(Debug, Clone)]
+pub enum BulkOutcome {
+    Updated { commit: Option<String> },
+    Failed(String),
+    Skipped(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct BulkResult {
+    pub name: String,
+    pub path: PathBuf,
+    pub pack: Option<String>,
+    pub branch: Option<String>,
+    pub outcome: BulkOutcome,
+}
+
+#[derive(Debug)]
+pub enum BulkProgress {
+    Update { index: usize, message: String, ratio: f64 },
+    Finished { index: usize, result: BulkResult },
+    AllDone,
+}
+
+/// Turns the history into jobs. Pinned, missing and pack-less instances are skipped up front.
+pub fn plan(history: &[HistoryEntry], registered: &[Pack], side_override: Option<Side>) -> (Vec<BulkJob>, Vec<BulkResult>) {
+    let mut jobs = Vec::new();
+    let mut skipped = Vec::new();
+
+    for entry in history {
+        let pack = entry.pack.as_deref().and_then(|name| packs::find(registered, name)).cloned();
+        let skip = |reason: &str| BulkResult {
+            name: entry.display_name(),
+            path: entry.path.clone(),
+            pack: entry.pack.clone(),
+            branch: entry.branch.clone(),
+            outcome: BulkOutcome::Skipped(reason.to_string()),
+        };
+
+        if entry.pinned {
+            skipped.push(skip("pinned"));
+        } else if !entry.is_available() {
+            skipped.push(skip("folder missing"));
+        } else if let Some(pack) = pack {
+            jobs.push(BulkJob {
+                path: entry.path.clone(),
+                name: entry.display_name(),
+                branch: entry.branch.clone().unwrap_or_else(|| pack.default_branch.clone()),
+                options: UpdateOptions {
+                    side: side_override.unwrap_or_else(|| Side::detect(&entry.path)),
+                    pack,
+                    optional: entry.optional.clone(),
+                    choose_optional: false,
+                    config_mode: entry.config_mode,
+                    protected: entry.protected.clone(),
+                    world_backups: entry.backup_worlds.then_some(entry.keep_world_backups),
+                    clean_managed_dirs: settings::current().updates.clean_managed_dirs,
+                    bundle: None,
+                    added_pack: false,
+                },
+            });
+        } else {
+            skipped.push(skip("no registered pack"));
+        }
+    }
+    (jobs, skipped)
+}
+
+/// Updates every job with at most `max_parallel` running at once. A failing instance
+/// is reported and the rest carry on.
+pub fn run_threaded(jobs: Vec<BulkJob>, max_parallel: usize, tx: Sender<BulkProgress>) {
+    let workers = max_parallel.clamp(1, jobs.len().max(1));
+    let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate().collect::<VecDeque<_>>()));
+
+    let handles: Vec<_> = (0..workers).map(|_| {
+        let queue = Arc::clone(&queue);
+        let tx = tx.clone();
+        thread::spawn(move || loop {
+            let next = queue.lock().unwrap().pop_front();
+            let Some((index, job)) = next else { break };
+            let result = run_job(index, job, &tx);
+            if tx.send(BulkProgress::Finished { index, result }).is_err() {
+                break;
+            }
+        })
+    }).collect();
+
+    for handle in handles {
+        handle.join().ok();
+    }
+    tx.send(BulkProgress::AllDone).ok();
+}
+
+fn run_job(index: usize, job: BulkJob, tx: &Sender<BulkProgress>) -> BulkResult {
+    let mut result = BulkResult {
+        name: job.name.clone(),
+        path: job.path.clone(),
+        pack: Some(job.options.pack.name.clone()),
+        branch: Some(job.branch.clone()),
+        outcome: BulkOutcome::Failed("The update worker stopped unexpectedly.".to_string()),
+    };
+
+    if let Some(in_use) = running::find_running(&job.path, job.options.side) {
+        result.outcome = BulkOutcome::Failed(in_use.to_string().replace('\n', " "));
+        return result;
+    }
+
+    let (progress_tx, progress_rx) = mpsc::channel();
+    let worker_path = job.path.clone();
+    let options = job.options.clone();
+    let branch = job.branch.clone();
+    thread::spawn(move || {
+        git::perform_git_operations_threaded(worker_path, branch, options, progress_tx);
+    });
+
+    for progress in progress_rx {
+        match progress {
+            GitProgress::Update(message, ratio) => {
+                tx.send(BulkProgress::Update { index, message, ratio }).ok();
+            }
+            GitProgress::Success(..) => {
+                result.outcome = BulkOutcome::Updated { commit: git::head_commit(&job.path) };
+                break;
+            }
+            // Bulk updates don't ask; the instance keeps its saved choices.
+            GitProgress::ChooseOptional { choices, reply, .. } => {
+                reply.send(Some(choices)).ok();
+            }
+            GitProgress::Failure(message) => {
+                let summary = message.lines().rfind(|l| !l.trim().is_empty()).unwrap_or_default();
+                result.outcome = BulkOutcome::Failed(summary.to_string());
+                break;
+            }
+        }
+    }
+    result
+}
+
+/// Short label for the result column of the summary table.
+pub fn outcome_label(outcome: &BulkOutcome) -> String {
+    match outcome {
+        BulkOutcome::Updated { commit: Some(commit) } => format!("updated to {}", commit),
+        BulkOutcome::Updated { commit: None } => "updated".to_string(),
+        BulkOutcome::Failed(message) => format!("failed: {}", message),
+        BulkOutcome::Skipped(reason) => format!("skipped ({})", reason),
+    }
+}
+
+/// Tracks an "Update all" run in the TUI while results come in.
+pub struct BulkRun {
+    pub names: Vec<String>,
+    pub ratios: Vec<f64>,
+    pub results: Vec<Option<BulkResult>>,
+    pub skipped: Vec<BulkResult>,
+}
+
+impl BulkRun {
+    pub fn new(jobs: &[BulkJob], skipped: Vec<BulkResult>) -> Self {
+        Self {
+            names: jobs.iter().map(|job| job.name.clone()).collect(),
+            ratios: vec![0.0; jobs.len()],
+            results: vec![None; jobs.len()],
+            skipped,
+        }
+    }
+
+    pub fn finished(&self) -> usize {
+        self.results.iter().filter(|r| r.is_some()).count()
+    }
+
+    /// Overall progress, counting finished instances as complete.
+    pub fn progress(&self) -> f64 {
+        if self.ratios.is_empty() {
+            return 1.0;
+        }
+        let done: f64 = self.results.iter().zip(&self.ratios).map(|(result, ratio)| if result.is_some() { 1.0 } else { *ratio }).sum();
+        (done / self.ratios.len() as f64).clamp(0.0, 1.0)
+    }
+
+    /// Updated and failed instances in history order, followed by the skipped ones.
+    pub fn into_results(self) -> Vec<BulkResult> {
+        let mut results: Vec<BulkResult> = self.results.into_iter().flatten().collect();
+        results.extend(self.skipped);
+        results
+    }
+}
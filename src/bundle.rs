@@ -0,0 +1,396 @@
+use crate::app::history;
+use crate::app::packs::{self, Pack};
+use crate::app::GitProgress;
+use crate::git;
+use anyhow::{bail, Context, Result};
+use git2::{Oid, Repository};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// File extension of offline bundles.
+pub const EXTENSION: &str = "mpbundle";
+
+const MANIFEST: &str = "bundle.json";
+/// A regular `git bundle` (v2) of the branch, so `git clone` can read it too once unzipped.
+const GIT_BUNDLE: &str = "pack.bundle";
+const LFS_DIR: &str = "lfs";
+const BUNDLE_SIGNATURE: &str = "# v2 git bundle";
+
+/// What a bundle holds, stored beside the Git bundle and the LFS objects.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub pack: String,
+    pub remote: String,
+    pub branch: String,
+    pub commit: String,
+    pub created: u64,
+    pub lfs_objects: usize,
+}
+
+/// Progress of making a bundle from the start screen.
+pub enum BundleProgress {
+    Update(String, f64),
+    Done(Result<String, String>),
+}
+
+/// Where a bundle of the instance is saved unless another path is given: beside the instance,
+/// named after the pack and the installed commit.
+pub fn default_path(instance: &Path, pack: &str) -> PathBuf {
+    let commit = git::head_commit(instance).unwrap_or_else(|| "unknown".to_string());
+    let name: String = format!("{}-{}", pack, commit)
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    instance.parent().unwrap_or(instance).join(format!("{}.{}", name, EXTENSION))
+}
+
+pub fn create_threaded(instance: PathBuf, dest: PathBuf, pack: String, tx: Sender<BundleProgress>) {
+    let progress = |message: String, ratio: f64| {
+        tx.send(BundleProgress::Update(message, ratio)).ok();
+    };
+    let result = create(&instance, &dest, &pack, &progress).map(|manifest| {
+        format!(
+            "Saved a bundle of {} ({} at {}, {} LFS files) to:\n\n{}\n\nCopy it to the other computer and press I there to install it.",
+            manifest.pack, manifest.branch, manifest.commit, manifest.lfs_objects, dest.display()
+        )
+    });
+    tx.send(BundleProgress::Done(result.map_err(|e| format!("{:#}", e)))).ok();
+}
+
+/// Packs the instance's current commit, its whole history and the LFS files it checked out into
+/// one file that another instance can be updated from without a connection.
+pub fn create(instance: &Path, dest: &Path, pack: &str, progress: &dyn Fn(String, f64)) -> Result<BundleManifest> {
+    let repo = Repository::open(instance).context(format!("'{}' has not been updated by the updater yet", instance.display()))?;
+    let head_ref = repo.head().context("The instance has no commit to bundle")?;
+    let head = head_ref.peel_to_commit()?;
+    // Updates always check out a local branch named after the pack's branch.
+    let branch = head_ref.shorthand().filter(|_| head_ref.is_branch()).context("The instance isn't on a branch of the pack. Update it first.")?.to_string();
+    let remote = repo.find_remote("origin").ok().and_then(|r| r.url().map(str::to_string)).unwrap_or_default();
+
+    // Written under another name until it's complete, so a half-written bundle is never copied.
+    let partial = dest.with_extension(format!("{}.partial", EXTENSION));
+    let manifest = BundleManifest {
+        pack: pack.to_string(),
+        remote,
+        branch,
+        commit: head.id().to_string()[..7].to_string(),
+        created: history::now(),
+        lfs_objects: 0,
+    };
+    let written = write_bundle(&repo, instance, &head, manifest, &partial, progress);
+    if written.is_err() {
+        // Unlike an interrupted world backup, a failed bundle is usually big and never useful.
+        fs::remove_file(&partial).ok();
+    }
+    let manifest = written?;
+    fs::rename(&partial, dest)?;
+    Ok(manifest)
+}
+
+fn write_bundle(repo: &Repository, instance: &Path, head: &git2::Commit, mut manifest: BundleManifest, partial: &Path, progress: &dyn Fn(String, f64)) -> Result<BundleManifest> {
+    let mut zip = ZipWriter::new(File::create(partial).context(format!("Failed to create '{}'", partial.display()))?);
+    // Packs and mod jars are compressed already.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored).large_file(true);
+
+    progress("Packing the pack's history...".to_string(), 0.0);
+    zip.start_file(GIT_BUNDLE, stored)?;
+    write!(zip, "{}\n{} refs/heads/{}\n\n", BUNDLE_SIGNATURE, head.id(), manifest.branch)?;
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    let mut builder = repo.packbuilder()?;
+    builder.insert_walk(&mut walk)?;
+    let mut written = Ok(());
+    builder.foreach(|chunk| {
+        written = zip.write_all(chunk);
+        written.is_ok()
+    })?;
+    written?;
+
+    let lfs_files = lfs_files_in_tree(repo, head)?;
+    for (i, (file, oid)) in lfs_files.iter().enumerate() {
+        progress(format!("Adding LFS file: {}", file), (i + 1) as f64 / lfs_files.len() as f64);
+        let path = instance.join(file);
+        // Server instances don't have the client-only files; the other side will say if it needs them.
+        if !path.is_file() {
+            continue;
+        }
+        if sha256_file(&path)? != *oid {
+            bail!("'{}' isn't the version of the file the pack expects. Update the instance before making a bundle from it.", file);
+        }
+        zip.start_file(format!("{}/{}", LFS_DIR, oid), stored)?;
+        io::copy(&mut File::open(&path)?, &mut zip)?;
+        manifest.lfs_objects += 1;
+    }
+
+    zip.start_file(MANIFEST, SimpleFileOptions::default())?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    zip.finish()?;
+    Ok(manifest)
+}
+
+// The LFS pointers in the commit's tree, as (path, oid).
+fn lfs_files_in_tree(repo: &Repository, commit: &git2::Commit) -> Result<Vec<(String, String)>> {
+    let odb = repo.odb()?;
+    let mut files = Vec::new();
+    commit.tree()?.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() != Some(git2::ObjectType::Blob) {
+            return git2::TreeWalkResult::Ok;
+        }
+        // Pointer files are tiny; anything bigger already holds real content and isn't read at all.
+        if !odb.read_header(entry.id()).is_ok_and(|(size, _)| size <= 1024) {
+            return git2::TreeWalkResult::Ok;
+        }
+        if let Ok(blob) = repo.find_blob(entry.id()) {
+            if let Some((oid, _)) = std::str::from_utf8(blob.content()).ok().and_then(git::is_lfs_pointer_file) {
+                files.push((format!("{}{}", dir, entry.name().unwrap_or_default()), oid));
+            }
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+    Ok(files)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut file = File::open(path)?;
+    let mut chunk = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&chunk[..read]);
+    }
+    Ok(context.finish().as_ref().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn open(bundle: &Path) -> Result<ZipArchive<File>> {
+    let file = File::open(bundle).context(format!("Failed to open '{}'", bundle.display()))?;
+    ZipArchive::new(file).context(format!("'{}' is not a modpack bundle", bundle.display()))
+}
+
+impl BundleManifest {
+    /// The registered pack the bundle was made from, and whether it had to be registered. A pack
+    /// the player doesn't have yet is added to `packs.toml`, so the instance can update from the
+    /// pack's remote once it's online.
+    pub fn register_pack(&self) -> Result<(Pack, bool)> {
+        let mut registered = packs::load()?;
+        if let Some(pack) = packs::find(&registered, &self.pack) {
+            return Ok((pack.clone(), false));
+        }
+        if self.pack.trim().is_empty() || self.remote.trim().is_empty() {
+            bail!("The bundle doesn't say which pack it holds.");
+        }
+        let pack = Pack { name: self.pack.clone(), remote: self.remote.clone(), default_branch: self.branch.clone(), ..Pack::default() };
+        registered.push(pack.clone());
+        packs::save(&registered)?;
+        Ok((pack, true))
+    }
+}
+
+pub fn read_manifest(bundle: &Path) -> Result<BundleManifest> {
+    let mut archive = open(bundle)?;
+    let mut content = String::new();
+    archive.by_name(MANIFEST).context(format!("'{}' is not a modpack bundle", bundle.display()))?.read_to_string(&mut content)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// The bundle's counterpart of a fetch: adds its commits to the repository and points
+/// `refs/remotes/origin/<branch>` at the bundled tip.
+pub fn fetch(repo: &Repository, bundle: &Path, branch: &str) -> Result<()> {
+    let mut archive = open(bundle)?;
+    let mut reader = BufReader::new(archive.by_name(GIT_BUNDLE).context(format!("'{}' holds no Git bundle", bundle.display()))?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != BUNDLE_SIGNATURE {
+        bail!("The Git bundle in '{}' is not a v2 bundle.", bundle.display());
+    }
+    let mut tips = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        let line = line.trim_end();
+        // Thin bundles name commits the receiving repository must already have.
+        if let Some(required) = line.strip_prefix('-') {
+            let oid = Oid::from_str(required.split(' ').next().unwrap_or_default())?;
+            if repo.find_commit(oid).is_err() {
+                bail!("The bundle builds on commit {} which this instance doesn't have.", &oid.to_string()[..7]);
+            }
+            continue;
+        }
+        if let Some((oid, name)) = line.split_once(' ') {
+            tips.push((Oid::from_str(oid)?, name.to_string()));
+        }
+    }
+    let wanted = format!("refs/heads/{}", branch);
+    let Some((tip, _)) = tips.iter().find(|(_, name)| *name == wanted) else {
+        let names: Vec<&str> = tips.iter().map(|(_, name)| name.trim_start_matches("refs/heads/")).collect();
+        bail!("The bundle doesn't have branch '{}', only: {}.", branch, names.join(", "));
+    };
+
+    let odb = repo.odb()?;
+    let mut writer = odb.packwriter()?;
+    io::copy(&mut reader, &mut writer).context("Failed to read the bundled commits")?;
+    writer.commit()?;
+    repo.find_commit(*tip).context("The bundle is incomplete: its tip commit is missing")?;
+    repo.reference(&format!("refs/remotes/origin/{}", branch), *tip, true, "modpack-updater: fetch from bundle")?;
+    Ok(())
+}
+
+/// Fills the LFS pointer files the checkout left from the objects in the bundle.
+pub fn restore_lfs_files(repo: &Repository, repo_path: &Path, bundle: &Path, progress_tx: &Sender<GitProgress>) -> Result<()> {
+    progress_tx.send(GitProgress::Update("Scanning for LFS files...".to_string(), 0.0)).ok();
+    let lfs_files = git::scan_for_lfs_files(repo, repo_path)?;
+    if lfs_files.is_empty() {
+        progress_tx.send(GitProgress::Update("No LFS files found.".to_string(), 1.0)).ok();
+        return Ok(());
+    }
+
+    let mut archive = open(bundle)?;
+    for (i, (file, oid, _)) in lfs_files.iter().enumerate() {
+        progress_tx.send(GitProgress::Update(format!("Copying LFS file from the bundle: {}", file), (i + 1) as f64 / lfs_files.len() as f64)).ok();
+        let mut object = archive
+            .by_name(&format!("{}/{}", LFS_DIR, oid))
+            .context(format!("The bundle doesn't include '{}'. It may have been made from a server; make it from a client instance", file))?;
+        // Copied beside the pointer, which is only replaced once the copy matches its oid.
+        let path = repo_path.join(file);
+        let temp_path = path.with_file_name(format!("{}.lfs-partial", path.file_name().unwrap_or_default().to_string_lossy()));
+        let copied = (|| -> Result<bool> {
+            io::copy(&mut object, &mut File::create(&temp_path)?)?;
+            Ok(sha256_file(&temp_path)? == *oid)
+        })();
+        if !matches!(copied, Ok(true)) {
+            fs::remove_file(&temp_path).ok();
+        }
+        if !copied? {
+            bail!("'{}' in the bundle is damaged.", file);
+        }
+        fs::rename(&temp_path, &path)?;
+    }
+    progress_tx.send(GitProgress::Update("LFS files copied from the bundle.".to_string(), 1.0)).ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self_update::TempDir;
+
+    fn commit(repo: &Repository, parent: Option<Oid>, content: &str) -> Oid {
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert("options.txt", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let signature = git2::Signature::now("Pack", "pack@example.com").unwrap();
+        let parents: Vec<git2::Commit> = parent.into_iter().map(|oid| repo.find_commit(oid).unwrap()).collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(None, &signature, &signature, content, &tree, &parents).unwrap()
+    }
+
+    // A bundle file with the given header, holding the commits up to `tip` but not those up to `hide`.
+    fn write_bundle_file(path: &Path, header: &str, repo: &Repository, tip: Oid, hide: Option<Oid>) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        zip.start_file(GIT_BUNDLE, SimpleFileOptions::default()).unwrap();
+        zip.write_all(header.as_bytes()).unwrap();
+        let mut walk = repo.revwalk().unwrap();
+        walk.push(tip).unwrap();
+        if let Some(hide) = hide {
+            walk.hide(hide).unwrap();
+        }
+        let mut builder = repo.packbuilder().unwrap();
+        builder.insert_walk(&mut walk).unwrap();
+        let mut pack = git2::Buf::new();
+        builder.write_buf(&mut pack).unwrap();
+        zip.write_all(&pack).unwrap();
+        zip.finish().unwrap();
+    }
+
+    struct Setup {
+        dir: TempDir,
+        pack: Repository,
+        instance: Repository,
+        first: Oid,
+        second: Oid,
+    }
+
+    fn setup() -> Setup {
+        let dir = TempDir::new().unwrap();
+        let pack = Repository::init_bare(dir.path().join("pack.git")).unwrap();
+        let first = commit(&pack, None, "fov:70");
+        let second = commit(&pack, Some(first), "fov:90");
+        let instance = Repository::init(dir.path().join("instance")).unwrap();
+        Setup { dir, pack, instance, first, second }
+    }
+
+    fn fetch_with_header(setup: &Setup, header: &str, hide: Option<Oid>, branch: &str) -> Result<()> {
+        let path = setup.dir.path().join("test.mpbundle");
+        write_bundle_file(&path, header, &setup.pack, setup.second, hide);
+        fetch(&setup.instance, &path, branch)
+    }
+
+    #[test]
+    fn fetch_points_the_remote_branch_at_the_bundled_tip() {
+        let setup = setup();
+        let header = format!("{}\n{} refs/heads/main\n{} refs/heads/beta\n\n", BUNDLE_SIGNATURE, setup.second, setup.first);
+        fetch_with_header(&setup, &header, None, "main").unwrap();
+        let reference = setup.instance.find_reference("refs/remotes/origin/main").unwrap();
+        assert_eq!(reference.target(), Some(setup.second));
+        assert!(setup.instance.find_commit(setup.first).is_ok());
+    }
+
+    #[test]
+    fn fetch_accepts_a_thin_bundle_on_top_of_commits_the_instance_has() {
+        let setup = setup();
+        let full = format!("{}\n{} refs/heads/main\n\n", BUNDLE_SIGNATURE, setup.first);
+        let path = setup.dir.path().join("first.mpbundle");
+        write_bundle_file(&path, &full, &setup.pack, setup.first, None);
+        fetch(&setup.instance, &path, "main").unwrap();
+
+        let thin = format!("{}\n-{} fov:70\n{} refs/heads/main\n\n", BUNDLE_SIGNATURE, setup.first, setup.second);
+        fetch_with_header(&setup, &thin, Some(setup.first), "main").unwrap();
+        assert_eq!(setup.instance.find_reference("refs/remotes/origin/main").unwrap().target(), Some(setup.second));
+    }
+
+    #[test]
+    fn fetch_rejects_a_thin_bundle_missing_its_base() {
+        let setup = setup();
+        let thin = format!("{}\n-{} fov:70\n{} refs/heads/main\n\n", BUNDLE_SIGNATURE, setup.first, setup.second);
+        let error = fetch_with_header(&setup, &thin, Some(setup.first), "main").unwrap_err();
+        assert!(error.to_string().contains(&setup.first.to_string()[..7]), "{}", error);
+        assert!(setup.instance.find_reference("refs/remotes/origin/main").is_err());
+    }
+
+    #[test]
+    fn fetch_lists_the_bundled_branches_when_the_wanted_one_is_missing() {
+        let setup = setup();
+        let header = format!("{}\n{} refs/heads/main\n{} refs/heads/beta\n\n", BUNDLE_SIGNATURE, setup.second, setup.first);
+        let error = fetch_with_header(&setup, &header, None, "release").unwrap_err();
+        assert_eq!(error.to_string(), "The bundle doesn't have branch 'release', only: main, beta.");
+    }
+
+    #[test]
+    fn fetch_rejects_other_bundle_versions() {
+        let setup = setup();
+        let header = format!("# v3 git bundle\n@object-format=sha1\n{} refs/heads/main\n\n", setup.second);
+        let error = fetch_with_header(&setup, &header, None, "main").unwrap_err();
+        assert!(error.to_string().contains("is not a v2 bundle"), "{}", error);
+    }
+
+    #[test]
+    fn fetch_rejects_a_bundle_without_a_tip_commit() {
+        let setup = setup();
+        let other = commit(&setup.pack, None, "unrelated");
+        let header = format!("{}\n{} refs/heads/main\n\n", BUNDLE_SIGNATURE, other);
+        let error = fetch_with_header(&setup, &header, None, "main").unwrap_err();
+        assert!(error.to_string().contains("tip commit is missing"), "{}", error);
+    }
+}
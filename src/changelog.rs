@@ -1,14 +1,425 @@
+use crate::app::history::{self, get_config_dir};
+use crate::markdown::{self, Document, Heading};
+use crate::mods;
+use crate::net;
 use anyhow::Result;
+use git2::{Delta, Diff, DiffFile, Oid, Repository};
+use ratatui::text::Line;
+use ratatui::widgets::ListState;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::mpsc::Sender;
+use tui_input::Input;
 
-const CHANGELOG_URL: &str = "https://raw.githubusercontent.com/minecraftwithtwink/Modpack-Updater/main/CHANGELOG.md";
+/// The updater's own changelog, unless `settings.toml` names another.
+pub const CHANGELOG_URL: &str = "https://raw.githubusercontent.com/minecraftwithtwink/Modpack-Updater/main/CHANGELOG.md";
 
-/// Fetches the changelog content from GitHub in a background thread.
-pub fn fetch_changelog_background(tx: Sender<Result<String>>) {
-    let result = (|| -> Result<String> {
-        let response = reqwest::blocking::get(CHANGELOG_URL)?;
-        let content = response.text()?;
-        Ok(content)
+const MAX_PACK_COMMITS: usize = 100;
+
+/// A mod jar in the pack, named from its metadata or else from its file name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModEntry {
+    pub name: String,
+    pub version: String,
+    /// The mod id, when the jar's metadata could be read.
+    pub mod_id: Option<String>,
+}
+
+impl ModEntry {
+    /// Prefers the jar's own metadata, which LFS pointers and broken jars don't have.
+    fn from_blob(repo: &Repository, id: Oid, file_name: &str) -> Self {
+        let info = repo.find_blob(id).ok()
+            .filter(|blob| blob.content().starts_with(b"PK"))
+            .and_then(|blob| mods::read_jar_bytes(blob.content()).ok())
+            .and_then(|mods| mods.into_iter().next());
+        match info {
+            Some(info) => Self { name: info.name, version: info.version, mod_id: Some(info.mod_id) },
+            None => Self::from_file_name(file_name),
+        }
+    }
+
+    /// Splits a jar file name into a mod name and version. The version starts at the first
+    /// `-`/`_`-separated part that begins with a digit (or `v`/`mc` followed by one).
+    pub fn from_file_name(file_name: &str) -> Self {
+        let stem = file_name.strip_suffix(".jar").unwrap_or(file_name);
+        let is_version = |part: &str| {
+            let part = part.strip_prefix("mc").or_else(|| part.strip_prefix('v')).unwrap_or(part);
+            part.starts_with(|c: char| c.is_ascii_digit())
+        };
+
+        let mut split_at = None;
+        let mut offset = 0;
+        for part in stem.split(['-', '_']) {
+            if offset > 0 && is_version(part) {
+                split_at = Some(offset);
+                break;
+            }
+            offset += part.len() + 1;
+        }
+        match split_at {
+            Some(at) => Self { name: stem[..at - 1].to_string(), version: stem[at..].to_string(), mod_id: None },
+            None => Self { name: stem.to_string(), version: String::new(), mod_id: None },
+        }
+    }
+
+    fn key(&self) -> String {
+        match &self.mod_id {
+            Some(mod_id) => mod_id.clone(),
+            None => self.name.to_lowercase().replace('_', "-"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModChanges {
+    pub added: Vec<ModEntry>,
+    pub removed: Vec<ModEntry>,
+    /// Old and new jar of a mod whose version changed.
+    pub updated: Vec<(ModEntry, ModEntry)>,
+}
+
+impl ModChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+}
+
+/// What an update brings into an instance: the pack's commits and the mods they change.
+#[derive(Debug, Clone, Default)]
+pub struct PackChangelog {
+    /// `abc1234 Commit summary`, newest first.
+    pub commits: Vec<String>,
+    /// Set when there were more commits than are listed.
+    pub truncated: bool,
+    pub mods: ModChanges,
+}
+
+impl PackChangelog {
+    /// Compares two commits of the pack. Without an `old` commit (a fresh install) only the
+    /// new commit is listed and every mod counts as added.
+    pub fn between(repo: &Repository, old: Option<Oid>, new: Oid) -> Result<Self> {
+        let mut changelog = PackChangelog::default();
+        if old == Some(new) {
+            return Ok(changelog);
+        }
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push(new)?;
+        if let Some(old) = old {
+            revwalk.hide(old)?;
+        }
+        let walk_limit = if old.is_some() { MAX_PACK_COMMITS } else { 1 };
+        for oid in revwalk.by_ref().take(walk_limit) {
+            let commit = repo.find_commit(oid?)?;
+            changelog.commits.push(format!("{} {}", &commit.id().to_string()[..7], commit.summary().unwrap_or_default()));
+        }
+        changelog.truncated = old.is_some() && revwalk.next().is_some();
+
+        let old_tree = old.map(|oid| repo.find_commit(oid).and_then(|c| c.tree())).transpose()?;
+        let new_tree = repo.find_commit(new)?.tree()?;
+        let diff = repo.diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), None)?;
+        changelog.mods = mod_changes(repo, &diff);
+        Ok(changelog)
+    }
+
+    /// One line like `3 commits · 2 mods added, 1 removed, 4 updated`.
+    pub fn summary(&self) -> String {
+        let commits = match (self.commits.len(), self.truncated) {
+            (1, _) => "1 commit".to_string(),
+            (n, true) => format!("{}+ commits", n),
+            (n, false) => format!("{} commits", n),
+        };
+        if self.mods.is_empty() {
+            return commits;
+        }
+        let mut parts = Vec::new();
+        if !self.mods.added.is_empty() {
+            parts.push(format!("{} added", self.mods.added.len()));
+        }
+        if !self.mods.removed.is_empty() {
+            parts.push(format!("{} removed", self.mods.removed.len()));
+        }
+        if !self.mods.updated.is_empty() {
+            parts.push(format!("{} updated", self.mods.updated.len()));
+        }
+        format!("{} · mods: {}", commits, parts.join(", "))
+    }
+
+    /// The mod changes as `+ name version`, `- name version` and `~ name old → new` lines.
+    pub fn mod_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.mods.added.iter().map(|m| format!("+ {} {}", m.name, m.version)).collect();
+        lines.extend(self.mods.removed.iter().map(|m| format!("- {} {}", m.name, m.version)));
+        lines.extend(self.mods.updated.iter().map(|(old, new)| {
+            if old.version == new.version {
+                format!("~ {} {} (rebuilt)", new.name, new.version)
+            } else {
+                format!("~ {} {} → {}", new.name, old.version, new.version)
+            }
+        }));
+        lines.iter().map(|line| line.trim_end().to_string()).collect()
+    }
+}
+
+// Pairs removed and added jars of the same mod into updates.
+fn mod_changes(repo: &Repository, diff: &Diff) -> ModChanges {
+    let jar_in_mods = |file: DiffFile| {
+        file.path()
+            .filter(|p| p.starts_with("mods") && p.extension().is_some_and(|ext| ext == "jar"))
+            .and_then(|p| p.file_name())
+            .map(|name| ModEntry::from_blob(repo, file.id(), &name.to_string_lossy()))
+    };
+
+    let mut changes = ModChanges::default();
+    for delta in diff.deltas() {
+        let old = jar_in_mods(delta.old_file());
+        let new = jar_in_mods(delta.new_file());
+        match (delta.status(), old, new) {
+            (Delta::Added, _, Some(new)) => changes.added.push(new),
+            (Delta::Deleted, Some(old), _) => changes.removed.push(old),
+            (Delta::Modified, Some(old), Some(new)) => changes.updated.push((old, new)),
+            (Delta::Renamed, Some(old), Some(new)) => changes.updated.push((old, new)),
+            _ => {}
+        }
+    }
+
+    let mut added = Vec::new();
+    for jar in changes.added {
+        match changes.removed.iter().position(|old| old.key() == jar.key()) {
+            Some(index) => changes.updated.push((changes.removed.remove(index), jar)),
+            None => added.push(jar),
+        }
+    }
+    changes.added = added;
+    changes.added.sort_by_key(ModEntry::key);
+    changes.removed.sort_by_key(ModEntry::key);
+    changes.updated.sort_by_key(|(_, new)| new.key());
+    changes
+}
+
+/// A changelog as last downloaded, kept so it can be revalidated cheaply and shown offline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedChangelog {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    fetched_at: u64,
+    content: String,
+}
+
+pub struct FetchedChangelog {
+    pub content: String,
+    /// When the cached copy being shown was downloaded, if the server couldn't be reached.
+    pub stale_since: Option<u64>,
+}
+
+fn get_cache_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("changelog-cache.json"))
+}
+
+fn load_cache() -> HashMap<String, CachedChangelog> {
+    get_cache_path()
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(cache: &HashMap<String, CachedChangelog>) -> Result<()> {
+    fs::write(get_cache_path()?, serde_json::to_string_pretty(cache)?)?;
+    Ok(())
+}
+
+/// Fetches the changelog in a background thread, revalidating the cached copy and
+/// falling back to it when the server can't be reached.
+pub fn fetch_changelog_background(url: String, tx: Sender<Result<FetchedChangelog>>) {
+    tx.send(fetch_changelog(&url)).ok();
+}
+
+fn fetch_changelog(url: &str) -> Result<FetchedChangelog> {
+    let mut cache = load_cache();
+    let cached = cache.get(url).cloned();
+
+    // `None` means the cached copy is still current.
+    let response = (|| -> Result<Option<CachedChangelog>> {
+        net::ensure_online(url)?;
+        let client = net::client()?;
+        let response = net::retry(|| {
+            let mut request = client.get(url);
+            if let Some(cached) = &cached {
+                if let Some(etag) = &cached.etag {
+                    request = request.header(header::IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &cached.last_modified {
+                    request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            Ok(request.send()?)
+        })?;
+        if response.status() == StatusCode::NOT_MODIFIED && cached.is_some() {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let header_value = |name| response.headers().get(name).and_then(|v: &header::HeaderValue| v.to_str().ok()).map(str::to_string);
+        let etag = header_value(header::ETAG);
+        let last_modified = header_value(header::LAST_MODIFIED);
+        Ok(Some(CachedChangelog { etag, last_modified, fetched_at: history::now(), content: response.text()? }))
     })();
-    tx.send(result).ok();
-}
\ No newline at end of file
+
+    let fresh = match (response, cached) {
+        (Ok(Some(fresh)), _) => fresh,
+        (Ok(None), Some(mut cached)) => {
+            cached.fetched_at = history::now();
+            cached
+        }
+        (Err(_), Some(cached)) => {
+            return Ok(FetchedChangelog { content: cached.content, stale_since: Some(cached.fetched_at) });
+        }
+        (Err(e), None) => return Err(e),
+        (Ok(None), None) => unreachable!("a 304 is only accepted with a cached copy"),
+    };
+    cache.insert(url.to_string(), fresh.clone());
+    save_cache(&cache).ok();
+    Ok(FetchedChangelog { content: fresh.content, stale_since: None })
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChangelogFocus {
+    Reading,
+    /// Picking a release section from the version index.
+    Index,
+    /// Typing a search query.
+    Searching,
+}
+
+/// Scroll position, version index and search state of the changelog viewer.
+/// Scrolling counts wrapped rows; the UI clamps it and records the page size while drawing.
+pub struct ChangelogView {
+    pub title: String,
+    /// When the copy being shown was downloaded, if it's an offline fallback.
+    pub stale_since: Option<u64>,
+    pub document: Document,
+    pub scroll: u16,
+    pub page_height: u16,
+    /// The document wrapped to `wrapped_width`, and the first row of every document line.
+    pub rows: Vec<Line<'static>>,
+    pub line_rows: Vec<usize>,
+    pub wrapped_width: u16,
+    /// A document line to scroll to once the document has been wrapped.
+    pending_jump: Option<usize>,
+    pub focus: ChangelogFocus,
+    pub index_state: ListState,
+    pub search: Input,
+    pub query: Option<String>,
+    pub matches: Vec<usize>,
+    pub current_match: usize,
+}
+
+impl ChangelogView {
+    pub fn new(fetched: FetchedChangelog) -> Self {
+        let document = markdown::render(&fetched.content);
+        let mut index_state = ListState::default();
+        if !document.headings.is_empty() {
+            index_state.select(Some(0));
+        }
+        Self {
+            title: "Changelog".to_string(),
+            stale_since: fetched.stale_since,
+            document,
+            scroll: 0,
+            page_height: 0,
+            rows: Vec::new(),
+            line_rows: Vec::new(),
+            wrapped_width: 0,
+            pending_jump: None,
+            focus: ChangelogFocus::Reading,
+            index_state,
+            search: Input::default(),
+            query: None,
+            matches: Vec::new(),
+            current_match: 0,
+        }
+    }
+
+    /// Headings that start a release section, listed in the version index. A single
+    /// top-level heading is taken as the document title rather than a release.
+    pub fn sections(&self) -> Vec<&Heading> {
+        let headings = &self.document.headings;
+        let level = if headings.iter().filter(|h| h.level == 1).count() > 1 {
+            1
+        } else {
+            headings.iter().map(|h| h.level).filter(|level| *level > 1).min().unwrap_or(1)
+        };
+        headings.iter().filter(|h| h.level == level).collect()
+    }
+
+    /// Re-wraps the document when the available width changes and keeps the scroll in range.
+    pub fn layout(&mut self, width: u16, height: u16) {
+        if width != self.wrapped_width {
+            // Keep the same document line at the top across resizes.
+            let top_line = self.line_rows.iter().rposition(|row| *row <= self.scroll as usize);
+            (self.rows, self.line_rows) = markdown::wrap(&self.document, width as usize);
+            self.wrapped_width = width;
+            if let Some(line) = self.pending_jump.take().or(top_line) {
+                self.jump_to_line(line);
+            }
+        }
+        self.page_height = height;
+        let max_scroll = self.rows.len().saturating_sub(height as usize).min(u16::MAX as usize) as u16;
+        self.scroll = self.scroll.min(max_scroll);
+    }
+
+    /// Opens the viewer at the release section whose heading mentions `version`.
+    pub fn show_version(&mut self, version: &str) {
+        self.pending_jump = self.sections().iter().find(|h| h.title.contains(version)).map(|h| h.line);
+    }
+
+    pub fn scroll_by(&mut self, rows: i32) {
+        self.scroll = (self.scroll as i32 + rows).clamp(0, u16::MAX as i32) as u16;
+    }
+
+    pub fn page(&self) -> i32 {
+        self.page_height.saturating_sub(1).max(1) as i32
+    }
+
+    pub fn jump_to_line(&mut self, line: usize) {
+        let row = self.line_rows.get(line).copied().unwrap_or(line);
+        self.scroll = row.min(u16::MAX as usize) as u16;
+    }
+
+    /// Finds every line containing the query (case-insensitive) and jumps to the first one
+    /// at or below the current position.
+    pub fn apply_search(&mut self) {
+        let query = self.search.value().trim().to_lowercase();
+        self.focus = ChangelogFocus::Reading;
+        if query.is_empty() {
+            self.query = None;
+            self.matches.clear();
+            return;
+        }
+        self.matches = (0..self.document.lines.len())
+            .filter(|i| self.document.line_text(*i).to_lowercase().contains(&query))
+            .collect();
+        self.query = Some(query);
+
+        let current_row = self.scroll as usize;
+        self.current_match = self.matches.iter()
+            .position(|line| self.line_rows.get(*line).copied().unwrap_or(*line) >= current_row)
+            .unwrap_or(0);
+        if let Some(line) = self.matches.get(self.current_match).copied() {
+            self.jump_to_line(line);
+        }
+    }
+
+    pub fn next_match(&mut self, forward: bool) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let count = self.matches.len();
+        self.current_match = if forward { (self.current_match + 1) % count } else { (self.current_match + count - 1) % count };
+        self.jump_to_line(self.matches[self.current_match]);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::pack::Side;
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: modpack-updater [OPTIONS]
+
+Options:
+  --headless              Update without the interactive interface (requires --instance or --all)
+  --instance <PATH>       Instance or server folder to update
+  --all                   Update every instance in the history, skipping pinned ones
+  --jobs <N>              How many instances --all updates at the same time (default: updates.concurrency in settings.toml)
+  --pack <NAME>           Registered pack to install (default: the instance's last pack)
+  --branch <NAME>         Pack branch to update to (default: the pack's default branch)
+  --server                Treat the folder as a dedicated server
+  --client                Treat the folder as a client instance
+  --create-bundle <FILE>  Save the instance's pack version to an offline bundle instead of updating it
+  --apply-bundle <FILE>   Update from an offline bundle instead of the pack's remote
+  --select <PATH>         Start the interface with this instance selected
+  --rollback              Restore the version the last self-update replaced
+  -h, --help              Print this help";
+
+/// Command-line options. With no arguments the interactive interface starts as usual.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub headless: bool,
+    pub instance: Option<PathBuf>,
+    pub all: bool,
+    pub jobs: Option<usize>,
+    pub pack: Option<String>,
+    pub branch: Option<String>,
+    /// Overrides the side detected from the instance folder.
+    pub side: Option<Side>,
+    /// Where to save an offline bundle of the instance, for `--headless`.
+    pub create_bundle: Option<PathBuf>,
+    /// An offline bundle to update the instance from, for `--headless`.
+    pub apply_bundle: Option<PathBuf>,
+    /// The instance to select on the start screen, e.g. after relaunching into a new version.
+    pub select: Option<PathBuf>,
+    pub rollback: bool,
+    pub help: bool,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => parsed.headless = true,
+                "--instance" => {
+                    let value = args.next().context("--instance requires a path")?;
+                    parsed.instance = Some(crate::git::parse_input_path(&value));
+                }
+                "--all" => parsed.all = true,
+                "--jobs" => {
+                    let value = args.next().context("--jobs requires a number")?;
+                    let jobs = value.parse::<usize>().ok().filter(|n| *n > 0);
+                    parsed.jobs = Some(jobs.context(format!("--jobs expects a positive number, got '{}'", value))?);
+                }
+                "--pack" => parsed.pack = Some(args.next().context("--pack requires a name")?),
+                "--branch" => parsed.branch = Some(args.next().context("--branch requires a name")?),
+                "--server" => parsed.side = Some(Side::Server),
+                "--client" => parsed.side = Some(Side::Client),
+                "--create-bundle" => {
+                    let value = args.next().context("--create-bundle requires a path")?;
+                    parsed.create_bundle = Some(crate::git::parse_input_path(&value));
+                }
+                "--apply-bundle" => {
+                    let value = args.next().context("--apply-bundle requires a path")?;
+                    parsed.apply_bundle = Some(crate::git::parse_input_path(&value));
+                }
+                "--select" => {
+                    let value = args.next().context("--select requires a path")?;
+                    parsed.select = Some(crate::git::parse_input_path(&value));
+                }
+                "--rollback" => parsed.rollback = true,
+                "-h" | "--help" => parsed.help = true,
+                other => bail!("Unknown argument '{}'", other),
+            }
+        }
+
+        if parsed.headless && parsed.instance.is_none() && !parsed.all {
+            bail!("--headless requires --instance <PATH> or --all");
+        }
+        if parsed.rollback && parsed.headless {
+            bail!("--rollback cannot be combined with --headless");
+        }
+        if parsed.select.is_some() && parsed.headless {
+            bail!("--select only applies to the interactive interface; use --instance with --headless");
+        }
+        if parsed.all && parsed.instance.is_some() {
+            bail!("--all cannot be combined with --instance");
+        }
+        if parsed.all && (parsed.pack.is_some() || parsed.branch.is_some()) {
+            bail!("--all uses each instance's own pack and branch and cannot be combined with --pack or --branch");
+        }
+        if parsed.create_bundle.is_some() || parsed.apply_bundle.is_some() {
+            if !parsed.headless || parsed.instance.is_none() {
+                bail!("--create-bundle and --apply-bundle require --headless --instance <PATH>");
+            }
+            if parsed.create_bundle.is_some() && parsed.apply_bundle.is_some() {
+                bail!("--create-bundle cannot be combined with --apply-bundle");
+            }
+        }
+        if parsed.create_bundle.is_some() && parsed.branch.is_some() {
+            bail!("--create-bundle saves the branch the instance is on and cannot be combined with --branch");
+        }
+        Ok(parsed)
+    }
+}
@@ -0,0 +1,802 @@
+use crate::app::GitProgress;
+use crate::pack::Side;
+use crate::running;
+use anyhow::{bail, Context, Result};
+use git2::{Oid, Repository, Tree};
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+/// Folder in the pack holding the defaults that are applied over the instance's configs.
+const DEFAULTS_DIR: &str = "configureddefaults";
+
+/// NeoForge's folder of configs that are copied into the instance when they're missing.
+const NEOFORGE_DEFAULTS_DIR: &str = "defaultconfigs";
+
+// (source inside `configureddefaults`, destination in the instance, is a directory)
+const ITEMS_TO_COPY: &[(&str, &str, bool)] = &[
+    ("config/fancymenu", "config/fancymenu", true),
+    ("customsplashscreen", "customsplashscreen", true),
+    ("config/fog", "config/fog", true),
+    ("config/customsplashscreen.json", "config/customsplashscreen.json", false),
+    ("config/raised.json", "config/raised.json", false),
+    ("sodium-extra.properties", "sodium-extra.properties", false),
+    ("sodiumextrainformation.json", "sodiumextrainformation.json", false),
+    ("sodium-extra-options.json", "sodium-extra-options.json", false),
+    ("sodium-fingerprint.json", "sodium-fingerprint.json", false),
+    ("sodium-mixins.properties", "sodium-mixins.properties", false),
+    ("sodium-options.json", "sodium-options.json", false),
+    ("sodium-shadowy-path-blocks-options.json", "sodium-shadowy-path-blocks-options.json", false),
+    ("tectonic.json", "tectonic.json", false),
+    ("sparsestructures.json5", "sparsestructures.json5", false),
+    ("parcool-client.toml", "parcool-client.toml", false),
+];
+
+/// How the pack's default configs are applied to an instance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigMode {
+    /// Only keys the pack changed since the last update are reset; the player's other settings stay.
+    #[default]
+    Merge,
+    /// Every default config is overwritten on each update.
+    Reset,
+}
+
+impl ConfigMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            ConfigMode::Merge => ConfigMode::Reset,
+            ConfigMode::Reset => ConfigMode::Merge,
+        }
+    }
+}
+
+impl fmt::Display for ConfigMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigMode::Merge => write!(f, "merge"),
+            ConfigMode::Reset => write!(f, "always reset"),
+        }
+    }
+}
+
+/// A config file of the player's that an update changed.
+#[derive(Debug, Clone)]
+pub struct ConfigChange {
+    /// Relative to the instance root.
+    pub path: String,
+    /// The keys that were reset to the pack's new defaults, e.g. `quality.weather_quality`.
+    pub keys: Vec<String>,
+    /// The whole file was replaced, because of the mode or because it couldn't be merged.
+    pub replaced: bool,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.replaced {
+            write!(f, "{} (reset)", self.path)
+        } else {
+            write!(f, "{}: {}", self.path, self.keys.join(", "))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Json,
+    Json5,
+    Toml,
+    Properties,
+}
+
+impl Format {
+    fn from_path(path: &str) -> Option<Self> {
+        match Path::new(path).extension()?.to_str()?.to_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "json5" => Some(Format::Json5),
+            "toml" => Some(Format::Toml),
+            "properties" => Some(Format::Properties),
+            _ => None,
+        }
+    }
+}
+
+/// Applies `configureddefaults` to the instance. In merge mode the defaults from `old_head`
+/// are the common ancestor: keys the pack didn't change keep the player's values.
+pub fn apply_defaults(repo: &Repository, instance_path: &Path, old_head: Option<Oid>, mode: ConfigMode, progress_tx: &Sender<GitProgress>) -> Result<Vec<ConfigChange>> {
+    progress_tx.send(GitProgress::Update("Applying default configurations...".to_string(), 1.0)).ok();
+
+    let source_base = instance_path.join(DEFAULTS_DIR);
+    let old_tree = match (mode, old_head) {
+        (ConfigMode::Merge, Some(oid)) => Some(repo.find_commit(oid)?.tree()?),
+        _ => None,
+    };
+
+    let mut changes = Vec::new();
+    for (source_suffix, dest_suffix, is_directory) in ITEMS_TO_COPY {
+        let source_path = source_base.join(source_suffix);
+        if !source_path.exists() {
+            continue;
+        }
+        let dest_path = instance_path.join(dest_suffix);
+
+        let files = if *is_directory { relative_files(&source_path)? } else { vec![String::new()] };
+        for file in &files {
+            // Single files are listed as an empty relative path, which `Path::join` would turn into `file/`.
+            let join = |base: &str| if file.is_empty() { base.to_string() } else { format!("{}/{}", base, file) };
+            let nested = |path: &Path| if file.is_empty() { path.to_path_buf() } else { path.join(file) };
+            let new = fs::read(nested(&source_path))?;
+            let base = old_tree.as_ref().and_then(|tree| blob_at(repo, tree, &format!("{}/{}", DEFAULTS_DIR, join(source_suffix))));
+            if let Some(change) = apply_file(&join(dest_suffix), &nested(&dest_path), base.as_deref(), &new, mode)? {
+                changes.push(change);
+            }
+        }
+
+        // Files the pack no longer ships are removed. Files the player added are kept when merging.
+        if *is_directory && dest_path.is_dir() {
+            let shipped: BTreeSet<&String> = files.iter().collect();
+            for file in relative_files(&dest_path)? {
+                if shipped.contains(&file) {
+                    continue;
+                }
+                let previously_shipped = old_tree.as_ref().is_some_and(|tree| {
+                    blob_at(repo, tree, &format!("{}/{}/{}", DEFAULTS_DIR, source_suffix, file)).is_some()
+                });
+                if mode == ConfigMode::Reset || previously_shipped {
+                    fs::remove_file(dest_path.join(&file))?;
+                }
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Applies NeoForge's `defaultconfigs/` the way the game would on its own: a missing config is
+/// copied in, server configs (`*-server.toml`) into every world's `serverconfig/`. Files matching
+/// `forced` also get the pack's changes when the instance already has them, following `mode`.
+pub fn apply_neoforge_defaults(
+    repo: &Repository,
+    instance_path: &Path,
+    old_head: Option<Oid>,
+    mode: ConfigMode,
+    forced: &GlobSet,
+    side: Side,
+    progress_tx: &Sender<GitProgress>,
+) -> Result<Vec<ConfigChange>> {
+    let source_base = instance_path.join(NEOFORGE_DEFAULTS_DIR);
+    if !source_base.is_dir() {
+        return Ok(Vec::new());
+    }
+    progress_tx.send(GitProgress::Update("Applying NeoForge default configs...".to_string(), 1.0)).ok();
+
+    let old_tree = match (mode, old_head) {
+        (ConfigMode::Merge, Some(oid)) => Some(repo.find_commit(oid)?.tree()?),
+        _ => None,
+    };
+    let worlds = running::world_dirs(instance_path, side);
+
+    let mut changes = Vec::new();
+    for file in relative_files(&source_base)? {
+        let new = fs::read(source_base.join(&file))?;
+        let destinations: Vec<PathBuf> = if file.ends_with("-server.toml") {
+            worlds.iter().map(|world| world.join("serverconfig").join(&file)).collect()
+        } else {
+            vec![instance_path.join("config").join(&file)]
+        };
+
+        for dest in destinations {
+            let display_path = dest.strip_prefix(instance_path).unwrap_or(&dest).to_string_lossy().replace('\\', "/");
+            if !forced.is_match(&file) {
+                if !dest.exists() {
+                    apply_file(&display_path, &dest, None, &new, mode)?;
+                }
+                continue;
+            }
+            let base = old_tree.as_ref().and_then(|tree| blob_at(repo, tree, &format!("{}/{}", NEOFORGE_DEFAULTS_DIR, file)));
+            if let Some(change) = apply_file(&display_path, &dest, base.as_deref(), &new, mode)? {
+                changes.push(change);
+            }
+        }
+    }
+    Ok(changes)
+}
+
+fn apply_file(display_path: &str, dest: &Path, base: Option<&[u8]>, new: &[u8], mode: ConfigMode) -> Result<Option<ConfigChange>> {
+    let write = |content: &[u8]| -> Result<()> {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(dest, content).context(format!("Failed to write '{}'.", dest.display()))
+    };
+    let replaced = || Some(ConfigChange { path: display_path.to_string(), keys: Vec::new(), replaced: true });
+
+    let user = match fs::read(dest) {
+        Ok(user) => user,
+        // Nothing of the player's to keep.
+        Err(_) => {
+            write(new)?;
+            return Ok(None);
+        }
+    };
+    if user == new {
+        return Ok(None);
+    }
+    let base = match (mode, base) {
+        (ConfigMode::Merge, Some(base)) => base,
+        // Without the previous defaults there's no telling the player's changes from the pack's.
+        _ => {
+            write(new)?;
+            return Ok(replaced());
+        }
+    };
+    if base == new {
+        return Ok(None);
+    }
+
+    let merged = Format::from_path(display_path).and_then(|format| {
+        let text = |bytes: &[u8]| String::from_utf8(bytes.to_vec()).ok();
+        merge(format, &text(base)?, &text(new)?, &text(&user)?).ok()
+    });
+    match merged {
+        Some((_, keys)) if keys.is_empty() => Ok(None),
+        Some((content, keys)) => {
+            write(content.as_bytes())?;
+            Ok(Some(ConfigChange { path: display_path.to_string(), keys, replaced: false }))
+        }
+        None => {
+            write(new)?;
+            Ok(replaced())
+        }
+    }
+}
+
+// Files below `dir`, relative to it with `/` separators, sorted.
+pub(crate) fn relative_files(dir: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn blob_at(repo: &Repository, tree: &Tree, path: &str) -> Option<Vec<u8>> {
+    let entry = tree.get_path(Path::new(path)).ok()?;
+    let blob = entry.to_object(repo).ok()?.peel_to_blob().ok()?;
+    Some(blob.content().to_vec())
+}
+
+type KeyPath = Vec<String>;
+
+/// Three-way merge of a config file. Returns the merged content and the keys that took the
+/// pack's new value.
+fn merge(format: Format, base: &str, new: &str, user: &str) -> Result<(String, Vec<String>)> {
+    match format {
+        Format::Json | Format::Json5 => {
+            let parse = |text: &str| -> Result<Value> {
+                match format {
+                    Format::Json => Ok(serde_json::from_str(text)?),
+                    _ => Ok(json5::from_str(text)?),
+                }
+            };
+            let (base_value, new_value, mut merged) = (parse(base)?, parse(new)?, parse(user)?);
+            let new_spans = scan_json(new);
+            let mut text = Some(user.to_string());
+            let mut changed = Vec::new();
+            for (path, value) in diff(&base_value, &new_value) {
+                if get(&merged, &path) == value {
+                    continue;
+                }
+                text = text.and_then(|text| edit_json(text, new, new_spans.as_ref()?, &path, value));
+                match value {
+                    Some(value) => set(&mut merged, &path, value.clone()),
+                    None => remove(&mut merged, &path),
+                }
+                changed.push(path.join("."));
+            }
+            // The player's file edited in place keeps its comments and layout. Should the edits
+            // not add up to the merged values, those are written out plainly instead.
+            match text {
+                Some(text) if parse(&text).is_ok_and(|value| value == merged) => Ok((text, changed)),
+                _ => Ok((format!("{}\n", serde_json::to_string_pretty(&merged)?), changed)),
+            }
+        }
+        Format::Toml => merge_toml(base, new, user),
+        Format::Properties => merge_properties(base, new, user),
+    }
+}
+
+// Every key whose value differs between the two documents, with its new value or `None` if it
+// was removed. Objects are compared key by key; anything else, arrays included, as a whole.
+fn diff<'a>(base: &Value, new: &'a Value) -> Vec<(KeyPath, Option<&'a Value>)> {
+    fn walk<'a>(base: &Value, new: &'a Value, prefix: &mut KeyPath, out: &mut Vec<(KeyPath, Option<&'a Value>)>) {
+        match (base, new) {
+            (Value::Object(base), Value::Object(new)) => {
+                let keys: BTreeSet<&String> = base.keys().chain(new.keys()).collect();
+                for key in keys {
+                    prefix.push(key.clone());
+                    match (base.get(key), new.get(key)) {
+                        (Some(b), Some(n)) => walk(b, n, prefix, out),
+                        (None, Some(n)) => out.push((prefix.clone(), Some(n))),
+                        (Some(_), None) => out.push((prefix.clone(), None)),
+                        (None, None) => {}
+                    }
+                    prefix.pop();
+                }
+            }
+            _ if base != new => out.push((prefix.clone(), Some(new))),
+            _ => {}
+        }
+    }
+    let mut out = Vec::new();
+    walk(base, new, &mut Vec::new(), &mut out);
+    out
+}
+
+fn get<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+fn set(value: &mut Value, path: &[String], new: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        *value = new;
+        return;
+    };
+    let mut current = value;
+    for key in parents {
+        if !current.is_object() {
+            *current = Value::Object(Default::default());
+        }
+        current = current.as_object_mut().unwrap().entry(key.clone()).or_insert_with(|| Value::Object(Default::default()));
+    }
+    if !current.is_object() {
+        *current = Value::Object(Default::default());
+    }
+    current.as_object_mut().unwrap().insert(last.clone(), new);
+}
+
+fn remove(value: &mut Value, path: &[String]) {
+    let Some((last, parents)) = path.split_last() else { return };
+    let parent = parents.iter().try_fold(value, |value, key| value.get_mut(key));
+    if let Some(Value::Object(map)) = parent {
+        map.remove(last);
+    }
+}
+
+// Where an object member sits in a JSON or JSON5 text. `start` is where its key begins and `after`
+// is the end of whatever came before it: the opening brace or the previous member's comma.
+struct JsonMember {
+    path: KeyPath,
+    after: usize,
+    start: usize,
+    value: std::ops::Range<usize>,
+    comma: Option<usize>,
+}
+
+struct JsonSpans {
+    members: Vec<JsonMember>,
+    // Opening brace of each object that isn't inside an array.
+    objects: Vec<(KeyPath, usize)>,
+}
+
+impl JsonSpans {
+    fn member(&self, path: &[String]) -> Option<&JsonMember> {
+        self.members.iter().find(|member| member.path == path)
+    }
+
+    fn children<'a>(&'a self, path: &'a [String]) -> impl Iterator<Item = &'a JsonMember> {
+        self.members.iter().filter(move |member| member.path.len() == path.len() + 1 && member.path.starts_with(path))
+    }
+}
+
+// Finds the members of a JSON5 (and so also JSON) document. Members inside arrays aren't recorded,
+// as `diff` compares arrays as a whole. `None` if the text isn't well-formed.
+fn scan_json(text: &str) -> Option<JsonSpans> {
+    struct Scanner<'a> {
+        text: &'a str,
+        pos: usize,
+        spans: JsonSpans,
+    }
+
+    impl Scanner<'_> {
+        fn peek(&self) -> Option<u8> {
+            self.text.as_bytes().get(self.pos).copied()
+        }
+
+        fn skip_trivia(&mut self) -> Option<()> {
+            loop {
+                let rest = &self.text[self.pos..];
+                if rest.starts_with("//") {
+                    self.pos += rest.find('\n').unwrap_or(rest.len());
+                } else if let Some(comment) = rest.strip_prefix("/*") {
+                    self.pos += comment.find("*/")? + 4;
+                } else if rest.starts_with(char::is_whitespace) {
+                    self.pos += rest.chars().next()?.len_utf8();
+                } else {
+                    return Some(());
+                }
+            }
+        }
+
+        fn string(&mut self) -> Option<()> {
+            let quote = self.peek()?;
+            self.pos += 1;
+            loop {
+                match self.peek()? {
+                    b'\\' => self.pos += 2,
+                    byte if byte == quote => break,
+                    _ => self.pos += 1,
+                }
+            }
+            self.pos += 1;
+            Some(())
+        }
+
+        // Numbers, literals and unquoted keys.
+        fn word(&mut self) -> Option<()> {
+            let start = self.pos;
+            while self.peek().is_some_and(|byte| !b",:[]{}/\"' \t\r\n".contains(&byte)) {
+                self.pos += 1;
+            }
+            (self.pos > start).then_some(())
+        }
+
+        fn value(&mut self, path: &mut KeyPath, record: bool) -> Option<()> {
+            match self.peek()? {
+                b'{' => {
+                    if record {
+                        self.spans.objects.push((path.clone(), self.pos));
+                    }
+                    self.pos += 1;
+                    let mut after = self.pos;
+                    loop {
+                        self.skip_trivia()?;
+                        if self.peek()? == b'}' {
+                            break;
+                        }
+                        let start = self.pos;
+                        if matches!(self.peek()?, b'"' | b'\'') {
+                            self.string()?;
+                        } else {
+                            self.word()?;
+                        }
+                        let key = &self.text[start..self.pos];
+                        let key = if key.starts_with(['"', '\'']) { json5::from_str::<String>(key).ok()? } else { key.to_string() };
+                        self.skip_trivia()?;
+                        if self.peek()? != b':' {
+                            return None;
+                        }
+                        self.pos += 1;
+                        self.skip_trivia()?;
+                        path.push(key);
+                        let value_start = self.pos;
+                        self.value(path, record)?;
+                        let value = value_start..self.pos;
+                        self.skip_trivia()?;
+                        let comma = (self.peek()? == b',').then_some(self.pos);
+                        if record {
+                            self.spans.members.push(JsonMember { path: path.clone(), after, start, value, comma });
+                        }
+                        path.pop();
+                        match comma {
+                            Some(comma) => {
+                                after = comma + 1;
+                                self.pos = after;
+                            }
+                            None if self.peek()? == b'}' => {}
+                            None => return None,
+                        }
+                    }
+                    self.pos += 1;
+                }
+                b'[' => {
+                    self.pos += 1;
+                    loop {
+                        self.skip_trivia()?;
+                        if self.peek()? == b']' {
+                            break;
+                        }
+                        self.value(path, false)?;
+                        self.skip_trivia()?;
+                        match self.peek()? {
+                            b',' => self.pos += 1,
+                            b']' => {}
+                            _ => return None,
+                        }
+                    }
+                    self.pos += 1;
+                }
+                b'"' | b'\'' => self.string()?,
+                _ => self.word()?,
+            }
+            Some(())
+        }
+    }
+
+    let mut scanner = Scanner { text, pos: 0, spans: JsonSpans { members: Vec::new(), objects: Vec::new() } };
+    scanner.skip_trivia()?;
+    scanner.value(&mut Vec::new(), true)?;
+    scanner.skip_trivia()?;
+    (scanner.pos == text.len()).then_some(scanner.spans)
+}
+
+// Applies one key of a merge to the player's JSON or JSON5 text. New values are copied as the
+// pack wrote them. `None` if the key can't be edited in place.
+fn edit_json(mut text: String, new: &str, new_spans: &JsonSpans, path: &[String], value: Option<&Value>) -> Option<String> {
+    let spans = scan_json(&text)?;
+    let line_start = |pos: usize| text[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let Some(value) = value else {
+        let member = spans.member(path)?;
+        let mut end = member.comma.map_or(member.value.end, |comma| comma + 1);
+        // A member on a line of its own goes with its line.
+        let start = match line_start(member.start) {
+            start if start >= member.after && text[start..member.start].trim().is_empty() => {
+                let rest = &text[end..];
+                let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+                let tail = rest[..line_end].trim();
+                if tail.is_empty() || tail.starts_with("//") {
+                    end += line_end;
+                }
+                start
+            }
+            _ => member.after,
+        };
+        // The last member takes the comma before it along, so no trailing comma is left behind.
+        let previous_comma = match member.comma {
+            None => spans.children(&path[..path.len() - 1]).filter(|other| other.start < member.start).last().and_then(|other| other.comma),
+            Some(_) => None,
+        };
+        text.replace_range(start..end, "");
+        if let Some(comma) = previous_comma {
+            text.replace_range(comma..comma + 1, "");
+        }
+        return Some(text);
+    };
+
+    let value_text = match new_spans.member(path) {
+        Some(member) => new[member.value.clone()].to_string(),
+        None => serde_json::to_string_pretty(value).ok()?,
+    };
+    if path.is_empty() {
+        return Some(new.to_string());
+    }
+    if let Some(member) = spans.member(path) {
+        text.replace_range(member.value.clone(), &value_text);
+        return Some(text);
+    }
+
+    // A new key goes after the last member of the deepest object the player still has, wrapped in
+    // any objects that are missing.
+    let depth = (0..path.len()).rev().find(|&depth| spans.objects.iter().any(|(object, _)| object == &path[..depth]))?;
+    if spans.member(&path[..=depth]).is_some() {
+        return None;
+    }
+    let mut entry = value_text;
+    for key in path[depth + 1..].iter().rev() {
+        entry = format!("{{ {}: {} }}", serde_json::to_string(key).ok()?, entry);
+    }
+    let entry = format!("{}: {}", serde_json::to_string(&path[depth]).ok()?, entry);
+    let (_, open) = spans.objects.iter().find(|(object, _)| object == &path[..depth])?;
+    match spans.children(&path[..depth]).last() {
+        Some(last) => {
+            let start = line_start(last.start);
+            let separator = if text[*open..last.start].contains('\n') {
+                format!(",\n{}", &text[start..start + text[start..].len() - text[start..].trim_start().len()])
+            } else {
+                ", ".to_string()
+            };
+            text.insert_str(last.value.end, &format!("{}{}", separator, entry));
+        }
+        None => text.insert_str(open + 1, &entry),
+    }
+    Some(text)
+}
+
+// Compares through JSON values, but edits the player's document in place so its comments and layout survive.
+fn merge_toml(base: &str, new: &str, user: &str) -> Result<(String, Vec<String>)> {
+    let as_json = |text: &str| -> Result<Value> { Ok(serde_json::to_value(toml::from_str::<toml::Table>(text)?)?) };
+    let (base_value, new_value, user_value) = (as_json(base)?, as_json(new)?, as_json(user)?);
+    let new_doc: toml_edit::DocumentMut = new.parse()?;
+    let mut user_doc: toml_edit::DocumentMut = user.parse()?;
+
+    let mut changed = Vec::new();
+    for (path, value) in diff(&base_value, &new_value) {
+        if get(&user_value, &path) == value {
+            continue;
+        }
+        let Some((last, parents)) = path.split_last() else { bail!("A TOML document is always a table.") };
+        match value {
+            Some(_) => {
+                let item = path.iter().try_fold(new_doc.as_item(), |item, key| item.get(key)).context("Missing TOML key")?.clone();
+                let mut table = user_doc.as_table_mut() as &mut dyn toml_edit::TableLike;
+                for key in parents {
+                    let entry = table.entry(key).or_insert(toml_edit::Item::Table(toml_edit::Table::new()));
+                    if !entry.is_table_like() {
+                        *entry = toml_edit::Item::Table(toml_edit::Table::new());
+                    }
+                    table = entry.as_table_like_mut().unwrap();
+                }
+                // Swapping only the value keeps the key's comment and indentation.
+                match (table.get_mut(last), item) {
+                    (Some(toml_edit::Item::Value(existing)), toml_edit::Item::Value(mut value)) => {
+                        *value.decor_mut() = existing.decor().clone();
+                        *existing = value;
+                    }
+                    (_, item) => {
+                        table.insert(last, item);
+                    }
+                }
+            }
+            None => {
+                let parent = parents.iter().try_fold(user_doc.as_item_mut(), |item, key| item.get_mut(key));
+                if let Some(table) = parent.and_then(|item| item.as_table_like_mut()) {
+                    table.remove(last);
+                }
+            }
+        }
+        changed.push(path.join("."));
+    }
+    Ok((user_doc.to_string(), changed))
+}
+
+fn parse_properties(text: &str) -> BTreeMap<String, String> {
+    text.lines().filter_map(property).map(|(key, _, value)| (key.to_string(), value.to_string())).collect()
+}
+
+// Splits `key=value` (or `key: value`) into the key, everything up to the value, and the value.
+fn property(line: &str) -> Option<(&str, &str, &str)> {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!') {
+        return None;
+    }
+    let separator = line.find(['=', ':'])?;
+    let value = line[separator + 1..].trim_start();
+    let prefix = &line[..line.len() - value.len()];
+    Some((line[..separator].trim(), prefix, value))
+}
+
+// Edits the player's lines so comments and ordering survive; new keys are appended.
+fn merge_properties(base: &str, new: &str, user: &str) -> Result<(String, Vec<String>)> {
+    let (base, new, current) = (parse_properties(base), parse_properties(new), parse_properties(user));
+    let mut changes: BTreeMap<&String, Option<&String>> = BTreeMap::new();
+    for key in base.keys().chain(new.keys()) {
+        if base.get(key) != new.get(key) && current.get(key) != new.get(key) {
+            changes.insert(key, new.get(key));
+        }
+    }
+
+    let mut lines = Vec::new();
+    for line in user.lines() {
+        match property(line) {
+            Some((key, prefix, _)) if changes.contains_key(&key.to_string()) => {
+                if let Some(Some(value)) = changes.get(&key.to_string()) {
+                    lines.push(format!("{}{}", prefix, value));
+                }
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+    for (key, value) in &changes {
+        if let (Some(value), false) = (value, current.contains_key(*key)) {
+            lines.push(format!("{}={}", key, value));
+        }
+    }
+    let changed = changes.keys().map(|key| key.to_string()).collect();
+    Ok((format!("{}\n", lines.join("\n")), changed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(format: Format, base: &str, new: &str, user: &str) -> (String, Vec<String>) {
+        merge(format, base, new, user).unwrap()
+    }
+
+    #[test]
+    fn json5_merge_keeps_comments_and_the_players_values() {
+        let base = "{\n  // Structure spread\n  spreadFactor: 1,\n  idle: 5,\n}\n";
+        let new = "{\n  // Structure spread\n  spreadFactor: 2,\n  idle: 5,\n}\n";
+        let user = "{\n  // Structure spread\n  spreadFactor: 1,\n  // mine\n  idle: 9,\n}\n";
+        let (content, keys) = merged(Format::Json5, base, new, user);
+        assert_eq!(content, "{\n  // Structure spread\n  spreadFactor: 2,\n  // mine\n  idle: 9,\n}\n");
+        assert_eq!(keys, vec!["spreadFactor"]);
+    }
+
+    #[test]
+    fn pack_wins_a_key_both_changed() {
+        let (content, keys) = merged(Format::Json5, "{ a: 1, b: 1 }", "{ a: 2, b: 1 }", "{ a: 3, b: 4 }");
+        assert_eq!(content, "{ a: 2, b: 4 }");
+        assert_eq!(keys, vec!["a"]);
+    }
+
+    #[test]
+    fn keys_the_pack_left_alone_are_not_reported() {
+        let (_, keys) = merged(Format::Json5, "{ a: 1, b: 1 }", "{ a: 1, b: 1, }", "{ a: 3 }");
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn json5_merge_adds_and_removes_keys() {
+        let base = "{\n  keep: true,\n  old: 1,\n  nested: { x: 1 },\n}";
+        let new = "{\n  keep: true,\n  nested: { x: 1, y: 'two' },\n  added: [1, 2],\n}";
+        let user = "{\n  keep: false,\n  old: 1,\n  nested: { x: 1 },\n}";
+        let (content, mut keys) = merged(Format::Json5, base, new, user);
+        assert_eq!(content, "{\n  keep: false,\n  nested: { x: 1, \"y\": 'two' },\n  \"added\": [1, 2],\n}");
+        keys.sort();
+        assert_eq!(keys, vec!["added", "nested.y", "old"]);
+    }
+
+    #[test]
+    fn a_removed_object_is_recreated_around_the_packs_change() {
+        let (content, keys) = merged(Format::Json5, "{ a: 1, group: { b: 1 } }", "{ a: 1, group: { b: 2 } }", "{ a: 5 }");
+        assert_eq!(content, "{ a: 5, \"group\": { \"b\": 2 } }");
+        assert_eq!(keys, vec!["group.b"]);
+    }
+
+    #[test]
+    fn json_merge_leaves_no_trailing_comma() {
+        let base = "{\n  \"a\": 1,\n  \"b\": 1\n}\n";
+        let new = "{\n  \"a\": 2\n}\n";
+        let user = "{\n  \"a\": 1,\n  \"b\": 1\n}\n";
+        let (content, keys) = merged(Format::Json, base, new, user);
+        assert_eq!(content, "{\n  \"a\": 2\n}\n");
+        assert_eq!(keys, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn toml_merge_keeps_comments_and_takes_the_packs_conflicting_value() {
+        let base = "[client]\n# Speed\nspeed = 1\nfov = 70\n";
+        let new = "[client]\n# Speed\nspeed = 2\nfov = 70\nnew = true\n";
+        let user = "[client]\n# Speed\nspeed = 3 # mine\nfov = 90\n";
+        let (content, keys) = merged(Format::Toml, base, new, user);
+        assert_eq!(content, "[client]\n# Speed\nspeed = 2 # mine\nfov = 90\nnew = true\n");
+        assert_eq!(keys, vec!["client.new", "client.speed"]);
+    }
+
+    #[test]
+    fn properties_merge_edits_only_the_changed_lines() {
+        let base = "# sodium\nfog=true\nclouds=fast\n";
+        let new = "# sodium\nfog=false\nclouds=fast\nleaves=fancy\n";
+        let user = "# sodium\nfog = true\nclouds=fancy\n";
+        let (content, keys) = merged(Format::Properties, base, new, user);
+        assert_eq!(content, "# sodium\nfog = false\nclouds=fancy\nleaves=fancy\n");
+        assert_eq!(keys, vec!["fog", "leaves"]);
+    }
+
+    #[test]
+    fn apply_file_replaces_what_it_cant_merge() {
+        let dir = self_update::TempDir::new().unwrap();
+        let dest = dir.path().join("options.json");
+
+        fs::write(&dest, "not json").unwrap();
+        let change = apply_file("options.json", &dest, Some(b"{\"a\": 1}"), b"{\"a\": 2}", ConfigMode::Merge).unwrap();
+        assert!(change.is_some_and(|change| change.replaced));
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "{\"a\": 2}");
+
+        fs::write(&dest, "{\"a\": 1, \"b\": 5}").unwrap();
+        let change = apply_file("options.json", &dest, Some(b"{\"a\": 1}"), b"{\"a\": 2}", ConfigMode::Reset).unwrap();
+        assert!(change.is_some_and(|change| change.replaced));
+
+        fs::write(&dest, "{\"a\": 1, \"b\": 5}").unwrap();
+        let change = apply_file("options.json", &dest, Some(b"{\"a\": 1}"), b"{\"a\": 2}", ConfigMode::Merge).unwrap().unwrap();
+        assert!(!change.replaced);
+        assert_eq!(change.keys, vec!["a"]);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "{\"a\": 2, \"b\": 5}");
+    }
+}
@@ -1,8 +1,17 @@
+use crate::app::settings::{self, Field, Settings};
+use crate::app::packs::{self, Pack};
 use crate::app::{history, App, AppState, RunMode, TutorialState, UpdateStatus};
-use crate::changelog;
+use crate::bulk::{self, BulkJob, BulkOutcome, BulkProgress, BulkResult, BulkRun};
+use crate::bundle::{self, BundleProgress};
+use crate::changelog::{self, ChangelogFocus, ChangelogView, PackChangelog};
 use crate::git;
+use crate::mods::health;
 use crate::music::MusicPlayer;
+use crate::pack::Side;
+use crate::running;
 use crate::ui;
+use crate::update::{self, AvailableRelease, SelfUpdateProgress};
+use crate::worlds::{self, WorldProgress};
 use anyhow::Result;
 use arboard::Clipboard;
 use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
@@ -11,10 +20,11 @@ use ratatui::widgets::ListState;
 use ratatui::Terminal;
 use std::env;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::time::Duration;
 use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
 
 
 pub fn run<B: Backend + Write>(
@@ -22,20 +32,20 @@ pub fn run<B: Backend + Write>(
     app: &mut App,
     music_player: &mut MusicPlayer,
 ) -> Result<()> {
+    let mut started = false;
     loop {
         // --- Channel Checkers ---
 
         if let Some(rx) = &app.update_rx {
             if let Ok(status) = rx.try_recv() {
-                match status {
-                    UpdateStatus::UpdateAvailable(version) => {
-                        if app.tutorial.is_some() {
-                            app.pending_update = Some(version);
-                        } else {
-                            app.state = AppState::ConfirmUpdate { version };
-                        }
+                if let UpdateStatus::UpdateAvailable(release) = status {
+                    if app.tutorial.is_some() {
+                        app.pending_update = Some(release);
+                    } else if matches!(app.state, AppState::Browsing) {
+                        app.state = AppState::ConfirmUpdate { release };
+                    } else {
+                        app.pending_update = Some(release);
                     }
-                    _ => {}
                 }
                 app.update_rx = None;
             }
@@ -43,11 +53,21 @@ pub fn run<B: Backend + Write>(
 
         if let Some(rx) = &app.changelog_rx {
             if let Ok(result) = rx.try_recv() {
-                match result {
-                    Ok(content) => {
-                        app.state = AppState::ViewingChangelog { content, scroll: 0 };
+                match (result, app.whats_new_version.take()) {
+                    // "What's new" opens unprompted, so it only appears over the idle start screen.
+                    (Ok(fetched), Some(version)) => {
+                        if matches!(app.state, AppState::Browsing) && matches!(app.mode, RunMode::StartupSelection) && app.tutorial.is_none() {
+                            let mut view = ChangelogView::new(fetched);
+                            view.title = format!("What's New in v{}", version);
+                            view.show_version(&version);
+                            app.state = AppState::ViewingChangelog(Box::new(view));
+                        }
                     }
-                    Err(e) => {
+                    (Err(_), Some(_)) => {}
+                    (Ok(fetched), None) => {
+                        app.state = AppState::ViewingChangelog(Box::new(ChangelogView::new(fetched)));
+                    }
+                    (Err(e), None) => {
                         app.state = AppState::Finished(format!("Failed to fetch changelog:\n\n{}", e));
                     }
                 }
@@ -61,7 +81,10 @@ pub fn run<B: Backend + Write>(
                     Ok(branches) => {
                         let mut list_state = ListState::default();
                         if !branches.is_empty() {
-                            list_state.select(Some(0));
+                            let preferred = app.settings.updates.default_branch.as_deref();
+                            let default_branch = app.selected_pack.as_ref().map(|p| p.default_branch.as_str());
+                            let position = |branch: Option<&str>| branches.iter().position(|b| Some(b.as_str()) == branch);
+                            list_state.select(Some(position(preferred).or_else(|| position(default_branch)).unwrap_or(0)));
                         }
                         app.state = AppState::BranchSelection { branches, list_state, selected_branch: None };
                     }
@@ -73,22 +96,200 @@ pub fn run<B: Backend + Write>(
             }
         }
 
+        if let Some(rx) = &app.connection_rx {
+            if let Ok(offline) = rx.try_recv() {
+                app.offline = offline;
+                app.connection_rx = None;
+            }
+        }
+
+        if let Some(rx) = &app.status_rx {
+            loop {
+                match rx.try_recv() {
+                    Ok((path, status)) => {
+                        app.instance_statuses.insert(path, status);
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        app.status_rx = None;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(rx) = &app.incoming_rx {
+            if let Ok(result) = rx.try_recv() {
+                app.state = match result {
+                    Ok(changelog) => {
+                        app.pack_changelog = Some(changelog.clone());
+                        AppState::ReviewIncoming { changelog, error: None, scroll: 0 }
+                    }
+                    Err(e) => AppState::ReviewIncoming { changelog: PackChangelog::default(), error: Some(format!("{:#}", e)), scroll: 0 },
+                };
+                app.incoming_rx = None;
+            }
+        }
+
+        if let Some(rx) = &app.mods_rx {
+            if let Ok(inspection) = rx.try_recv() {
+                let mut list_state = ListState::default();
+                if !inspection.jars.is_empty() {
+                    list_state.select(Some(0));
+                }
+                let error = match &mut app.state {
+                    AppState::ReadingMods { error } => error.take(),
+                    _ => None,
+                };
+                app.state = AppState::ViewingMods { inspection: Box::new(inspection), list_state, error };
+                app.mods_rx = None;
+            }
+        }
+
+        if let Some(rx) = &app.worlds_rx {
+            let events: Vec<WorldProgress> = rx.try_iter().collect();
+            for event in events {
+                match event {
+                    WorldProgress::Update(text, ratio) => {
+                        if let AppState::WorkingOnWorlds { message, progress, .. } = &mut app.state {
+                            *message = text;
+                            *progress = ratio;
+                        }
+                    }
+                    WorldProgress::Done(outcome) => {
+                        if let AppState::WorkingOnWorlds { instance, .. } = &app.state {
+                            let instance = instance.clone();
+                            show_world_backups(app, instance, Some(outcome));
+                        }
+                        app.worlds_rx = None;
+                    }
+                }
+            }
+        }
+
+        if let Some(rx) = &app.bundle_rx {
+            let events: Vec<BundleProgress> = rx.try_iter().collect();
+            for event in events {
+                match event {
+                    BundleProgress::Update(text, ratio) => {
+                        if let AppState::CreatingBundle { message, progress } = &mut app.state {
+                            *message = text;
+                            *progress = ratio;
+                        }
+                    }
+                    BundleProgress::Done(outcome) => {
+                        let message = match outcome {
+                            Ok(message) => message,
+                            Err(e) => format!("Couldn't save the bundle:\n\n{}", e),
+                        };
+                        app.state = AppState::Finished(format!("{}\n\n{}", message, ui::CLOSE_HINT));
+                        app.bundle_rx = None;
+                    }
+                }
+            }
+        }
+
+        if let Some(rx) = &app.bulk_rx {
+            loop {
+                match rx.try_recv() {
+                    Ok(BulkProgress::Update { index, message, ratio }) => {
+                        if let Some(run) = &mut app.bulk_run {
+                            run.ratios[index] = ratio;
+                            let message = format!("[{}/{}] {} · {}", run.finished() + 1, run.names.len(), run.names[index], message);
+                            app.state = AppState::UpdatingAll { message, progress: run.progress() };
+                        }
+                    }
+                    Ok(BulkProgress::Finished { index, result }) => {
+                        match &result.outcome {
+                            BulkOutcome::Updated { commit } => history::record_success(
+                                &mut app.history,
+                                &result.path,
+                                result.pack.as_deref().unwrap_or_default(),
+                                result.branch.as_deref().unwrap_or_default(),
+                                commit.clone(),
+                            ),
+                            BulkOutcome::Failed(message) => history::record_failure(&mut app.history, &result.path, message),
+                            BulkOutcome::Skipped(_) => {}
+                        }
+                        history::save(&app.history).ok();
+                        if let Some(run) = &mut app.bulk_run {
+                            run.results[index] = Some(result);
+                        }
+                    }
+                    Ok(BulkProgress::AllDone) | Err(mpsc::TryRecvError::Disconnected) => {
+                        let results = app.bulk_run.take().map(BulkRun::into_results).unwrap_or_default();
+                        app.state = AppState::UpdateAllSummary { results, scroll: 0 };
+                        app.bulk_rx = None;
+                        app.refresh_instance_statuses();
+                        break;
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                }
+            }
+        }
+
+        if let Some(rx) = &app.self_update_rx {
+            match rx.try_recv() {
+                Ok(SelfUpdateProgress::Update(message, ratio)) => {
+                    app.state = AppState::Processing { message, progress: ratio };
+                }
+                Ok(SelfUpdateProgress::Done(Ok(()))) => {
+                    app.relaunch = true;
+                    return Ok(());
+                }
+                Ok(SelfUpdateProgress::Done(Err(message))) => {
+                    app.state = AppState::Finished(format!("The update failed. This version is still installed.\n\n{}\n\n{}", message, ui::CLOSE_HINT));
+                    app.self_update_rx = None;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    app.state = AppState::Finished(format!("The update stopped unexpectedly.\n\n{}", ui::CLOSE_HINT));
+                    app.self_update_rx = None;
+                }
+                Err(mpsc::TryRecvError::Empty) => {}
+            }
+        }
+
         if let Some(rx) = &app.progress_rx {
             if let Ok(progress) = rx.try_recv() {
                 match progress {
                     git::GitProgress::Update(message, ratio) => {
                         app.state = AppState::Processing { message, progress: ratio };
                     }
-                    git::GitProgress::Success(message) => {
+                    git::GitProgress::Success(report) => {
                         let path = app.confirmed_path.clone().unwrap();
-                        if !app.history.contains(&path) {
-                            app.history.push(path);
+                        let report = *report;
+                        app.removable_duplicates = health::untracked_duplicates(&path, &report.mod_problems);
+                        app.mod_problems = report.mod_problems;
+                        app.config_changes = report.config_changes;
+                        app.protected_files = report.protected_files;
+                        let (message, changelog) = (report.message, report.changelog);
+                        if let (Some(pack), Some(branch)) = (&app.selected_pack, &app.confirmed_branch) {
+                            history::record_success(&mut app.history, &path, &pack.name, branch, git::head_commit(&path));
+                            if let Some(choices) = app.optional_choices.take() {
+                                history::record_optional_choices(&mut app.history, &path, choices);
+                            }
                             history::save(&app.history).ok();
                         }
+                        app.pack_changelog = changelog;
                         app.state = AppState::Finished(message);
                         app.progress_rx = None;
                     }
+                    git::GitProgress::ChooseOptional { groups, choices, reply } => {
+                        let mut list_state = ListState::default();
+                        list_state.select(Some(0));
+                        app.state = AppState::ChoosingOptional { groups, choices, list_state, reply };
+                    }
                     git::GitProgress::Failure(message) => {
+                        app.pack_changelog = None;
+                        app.config_changes.clear();
+                        app.protected_files.clear();
+                        app.mod_problems.clear();
+                        app.removable_duplicates.clear();
+                        if let Some(path) = &app.confirmed_path {
+                            let summary = message.lines().rfind(|l| !l.trim().is_empty()).unwrap_or_default();
+                            history::record_failure(&mut app.history, path, summary);
+                            history::save(&app.history).ok();
+                        }
                         app.state = AppState::Finished(message);
                         app.progress_rx = None;
                     }
@@ -97,6 +298,11 @@ pub fn run<B: Backend + Write>(
         }
 
         terminal.draw(|f| ui::draw(f, app, music_player))?;
+        // Only a version that got as far as drawing its interface counts as working.
+        if !started {
+            update::rollback::record_started();
+            started = true;
+        }
 
         if event::poll(Duration::from_millis(10))? {
             if let Event::Key(key) = event::read()? {
@@ -104,11 +310,11 @@ pub fn run<B: Backend + Write>(
                     // --- Top-level input handlers for popups ---
                     match &mut app.state {
 
-                        AppState::ConfirmUpdate { .. } => {
+                        AppState::ConfirmUpdate { release } => {
                             match key.code {
                                 KeyCode::Char('y') | KeyCode::Char('Y') => {
-                                    app.should_perform_update = true;
-                                    return Ok(());
+                                    let release = release.clone();
+                                    start_self_update(app, release);
                                 }
                                 KeyCode::Esc => {
                                     app.state = AppState::Browsing;
@@ -117,12 +323,17 @@ pub fn run<B: Backend + Write>(
                             }
                             continue;
                         }
-                        AppState::ViewingChangelog { scroll, .. } => {
-                            match key.code {
-                                KeyCode::Up => *scroll = scroll.saturating_sub(1),
-                                KeyCode::Down => *scroll = scroll.saturating_add(1),
-                                KeyCode::Esc => app.state = AppState::Browsing,
-                                _ => {}
+                        // The download can't be cancelled halfway.
+                        AppState::Processing { .. } if app.self_update_rx.is_some() => continue,
+                        AppState::Finished(_) if matches!(app.mode, RunMode::StartupSelection) => {
+                            if matches!(key.code, KeyCode::Enter | KeyCode::Char('q') | KeyCode::Esc) {
+                                app.state = AppState::Browsing;
+                            }
+                            continue;
+                        }
+                        AppState::ViewingChangelog(view) => {
+                            if !handle_changelog_input(view, key) {
+                                app.state = AppState::Browsing;
                             }
                             continue;
                         }
@@ -136,7 +347,25 @@ pub fn run<B: Backend + Write>(
                         let mut should_quit = false;
                         match app.mode {
                             RunMode::StartupSelection => {
-                                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                                let in_popup = matches!(
+                                    app.state,
+                                    AppState::RenamingInstance { .. }
+                                        | AppState::ConfirmRemoveInstance { .. }
+                                        | AppState::ConfirmInvalidFolder { .. }
+                                        | AppState::ReadingMods { .. }
+                                        | AppState::ViewingMods { .. }
+                                        | AppState::ConfirmRemoveDuplicates { .. }
+                                        | AppState::Settings { .. }
+                                        | AppState::WorldBackups { .. }
+                                        | AppState::ConfirmRestoreWorld { .. }
+                                        | AppState::WorkingOnWorlds { .. }
+                                        | AppState::ConfirmUpdateAll { .. }
+                                        | AppState::UpdatingAll { .. }
+                                        | AppState::UpdateAllSummary { .. }
+                                        | AppState::EnteringBundlePath { .. }
+                                        | AppState::CreatingBundle { .. }
+                                );
+                                if !in_popup && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
                                     music_player.play_confirm_sfx();
                                     should_quit = true;
                                 } else {
@@ -160,20 +389,181 @@ pub fn run<B: Backend + Write>(
     }
 }
 
+fn handle_settings_input(app: &mut App, key: event::KeyEvent, music_player: &mut MusicPlayer) {
+    let AppState::Settings { list_state, editing } = &mut app.state else { return };
+    let selected = list_state.selected().unwrap_or(0);
+    let field = Field::ALL[selected];
+
+    let mut changed = app.settings.clone();
+    if let Some(input) = editing {
+        match key.code {
+            KeyCode::Enter => {
+                field.set_text(&mut changed, input.value());
+                *editing = None;
+            }
+            KeyCode::Esc => *editing = None,
+            _ => {
+                input.handle_event(&Event::Key(key));
+            }
+        }
+    } else {
+        let count = Field::ALL.len();
+        match key.code {
+            KeyCode::Up => list_state.select(Some((selected + count - 1) % count)),
+            KeyCode::Down => list_state.select(Some((selected + 1) % count)),
+            KeyCode::Enter if field.is_text() => *editing = Some(Input::from(field.text(&app.settings))),
+            KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Right if !field.is_text() => field.step(&mut changed, true),
+            KeyCode::Left if !field.is_text() => field.step(&mut changed, false),
+            KeyCode::Esc | KeyCode::Char('q') => {
+                music_player.play_cancel_sfx();
+                // A channel switch may have found a release while the screen was open.
+                app.state = match app.pending_update.take() {
+                    Some(release) => AppState::ConfirmUpdate { release },
+                    None => AppState::Browsing,
+                };
+            }
+            _ => {}
+        }
+    }
+    if changed != app.settings {
+        apply_setting(app, changed, field, music_player);
+    }
+}
+
+// Saves the changed settings and puts the one that changed into effect right away.
+fn apply_setting(app: &mut App, changed: Settings, field: Field, music_player: &mut MusicPlayer) {
+    if let Err(e) = settings::save(&changed) {
+        app.settings_error = Some(format!("{:#}", e));
+        return;
+    }
+    app.settings_error = None;
+    app.settings = changed;
+    match field {
+        Field::Music if app.settings.audio.music => music_player.play(),
+        Field::Music if !music_player.is_paused => music_player.toggle_pause(),
+        Field::Volume => music_player.set_volume(app.settings.audio.volume),
+        Field::SoundEffects => music_player.sound_effects = app.settings.audio.sound_effects,
+        Field::Channel => {
+            let (tx, rx) = mpsc::channel();
+            app.update_rx = Some(rx);
+            let (config, channel) = (app.settings.self_update.clone(), app.settings.updates.channel);
+            std::thread::spawn(move || update::check_for_updates_background(config, channel, tx));
+        }
+        _ => {}
+    }
+}
+
+// Downloads and installs the release in the background; the event loop relaunches once it's in place.
+fn start_self_update(app: &mut App, release: AvailableRelease) {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            app.state = AppState::Finished(format!("The update failed. This version is still installed.\n\n{}\n\n{}", e, ui::CLOSE_HINT));
+            return;
+        }
+    };
+    let (tx, rx) = mpsc::channel();
+    app.self_update_rx = Some(rx);
+    app.state = AppState::Processing { message: format!("Updating to {}...", release.version), progress: 0.0 };
+    std::thread::spawn(move || {
+        update::perform_update_threaded(release, exe, tx);
+    });
+}
+
+/// Returns `false` when the changelog should be closed.
+fn handle_changelog_input(view: &mut ChangelogView, key: event::KeyEvent) -> bool {
+    match view.focus {
+        ChangelogFocus::Searching => match key.code {
+            KeyCode::Enter => view.apply_search(),
+            KeyCode::Esc => view.focus = ChangelogFocus::Reading,
+            _ => {
+                view.search.handle_event(&Event::Key(key));
+            }
+        },
+        ChangelogFocus::Index => {
+            let count = view.sections().len();
+            let selected = view.index_state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Up if count > 0 => view.index_state.select(Some((selected + count - 1) % count)),
+                KeyCode::Down if count > 0 => view.index_state.select(Some((selected + 1) % count)),
+                KeyCode::Enter => {
+                    if let Some(line) = view.sections().get(selected).map(|heading| heading.line) {
+                        view.jump_to_line(line);
+                    }
+                    view.focus = ChangelogFocus::Reading;
+                }
+                KeyCode::Esc | KeyCode::Char('i') => view.focus = ChangelogFocus::Reading,
+                _ => {}
+            }
+        }
+        ChangelogFocus::Reading => match key.code {
+            KeyCode::Up => view.scroll_by(-1),
+            KeyCode::Down => view.scroll_by(1),
+            KeyCode::PageUp => view.scroll_by(-view.page()),
+            KeyCode::PageDown | KeyCode::Char(' ') => view.scroll_by(view.page()),
+            KeyCode::Home => view.scroll = 0,
+            KeyCode::End => view.scroll = u16::MAX,
+            KeyCode::Char('i') if !view.sections().is_empty() => {
+                // Start the index at the section currently on screen.
+                let scroll = view.scroll as usize;
+                let line_rows = &view.line_rows;
+                let current = view.sections().iter().rposition(|h| line_rows.get(h.line).copied().unwrap_or(h.line) <= scroll).unwrap_or(0);
+                view.index_state.select(Some(current));
+                view.focus = ChangelogFocus::Index;
+            }
+            KeyCode::Char('/') => {
+                view.search = Input::from(view.query.clone().unwrap_or_default());
+                view.focus = ChangelogFocus::Searching;
+            }
+            KeyCode::Char('n') => view.next_match(true),
+            KeyCode::Char('N') => view.next_match(false),
+            KeyCode::Esc if view.query.is_some() => {
+                view.query = None;
+                view.matches.clear();
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return false,
+            _ => {}
+        },
+    }
+    true
+}
+
 fn is_valid_instance_folder(path: &Path) -> bool {
     let has_mods = path.join("mods").is_dir();
     let has_config = path.join("config").is_dir();
     has_mods && has_config
 }
 
+// Uses the pack the instance was updated from before, and only asks when there's a choice.
+fn choose_pack_or_confirm(app: &mut App) -> AppState {
+    // A bundle says which pack it holds.
+    if app.bundle.is_some() {
+        return AppState::ConfirmReinit;
+    }
+    let remembered = app.confirmed_path.as_deref().and_then(|path| app.pack_for(path));
+    app.selected_pack = match remembered {
+        Some(pack) => Some(pack),
+        None if app.packs.len() == 1 => app.packs.first().cloned(),
+        None => None,
+    };
+
+    if app.selected_pack.is_some() {
+        AppState::ConfirmReinit
+    } else {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        AppState::PackSelection { list_state }
+    }
+}
+
 fn handle_tutorial_input(app: &mut App, key: event::KeyEvent, music_player: &mut MusicPlayer) -> Result<()> {
     if key.code == KeyCode::Char('s') {
         music_player.play_confirm_sfx();
         history::mark_tutorial_as_completed().ok();
         app.tutorial = None;
         app.tutorial_interactive = true;
-        if let Some(version) = app.pending_update.clone() {
-            app.state = AppState::ConfirmUpdate { version };
+        if let Some(release) = app.pending_update.clone() {
+            app.state = AppState::ConfirmUpdate { release };
         }
         return Ok(());
     }
@@ -182,8 +572,8 @@ fn handle_tutorial_input(app: &mut App, key: event::KeyEvent, music_player: &mut
         KeyCode::Char('q') | KeyCode::Esc => {
             app.tutorial = None;
             music_player.play_cancel_sfx();
-            if let Some(version) = app.pending_update.clone() {
-                app.state = AppState::ConfirmUpdate { version };
+            if let Some(release) = app.pending_update.clone() {
+                app.state = AppState::ConfirmUpdate { release };
             }
             return Ok(());
         }
@@ -290,30 +680,246 @@ fn handle_tutorial_input(app: &mut App, key: event::KeyEvent, music_player: &mut
 }
 
 fn handle_startup_input(app: &mut App, key: event::KeyEvent, music_player: &mut MusicPlayer) -> Result<()> {
+    match app.state {
+        AppState::Settings { .. } => {
+            handle_settings_input(app, key, music_player);
+            return Ok(());
+        }
+        AppState::RenamingInstance { index } => {
+            match key.code {
+                KeyCode::Enter => {
+                    let name = app.input.value().trim().to_string();
+                    if let Some(entry) = app.history.get_mut(index) {
+                        entry.name = if name.is_empty() { None } else { Some(name) };
+                        history::save(&app.history).ok();
+                    }
+                    music_player.play_confirm_sfx();
+                    app.state = AppState::Browsing;
+                }
+                KeyCode::Esc => {
+                    music_player.play_cancel_sfx();
+                    app.state = AppState::Browsing;
+                }
+                _ => {
+                    app.input.handle_event(&Event::Key(key));
+                }
+            }
+            return Ok(());
+        }
+        AppState::ConfirmRemoveInstance { index } => {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    if index < app.history.len() {
+                        app.history.remove(index);
+                        history::save(&app.history).ok();
+                        app.history_state.select(Some(index.min(app.history.len())));
+                    }
+                    music_player.play_confirm_sfx();
+                    app.state = AppState::Browsing;
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                    music_player.play_cancel_sfx();
+                    app.state = AppState::Browsing;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        AppState::ConfirmUpdateAll { .. } => {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    music_player.play_confirm_sfx();
+                    if let AppState::ConfirmUpdateAll { jobs, skipped } = std::mem::replace(&mut app.state, AppState::Browsing) {
+                        start_update_all(app, jobs, skipped);
+                    }
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                    music_player.play_cancel_sfx();
+                    app.state = AppState::Browsing;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        // Updates keep running in the background; there's nothing to cancel safely mid-checkout.
+        AppState::UpdatingAll { .. } => {
+            if key.code == KeyCode::Char('p') {
+                music_player.toggle_pause();
+            }
+            return Ok(());
+        }
+        AppState::EnteringBundlePath { create, index } => {
+            handle_bundle_path_input(app, key, music_player, create, index)?;
+            return Ok(());
+        }
+        // Like a world backup, a half-written bundle is of no use; it finishes on its own.
+        AppState::CreatingBundle { .. } => {
+            if key.code == KeyCode::Char('p') {
+                music_player.toggle_pause();
+            }
+            return Ok(());
+        }
+        AppState::ReadingMods { .. } => return Ok(()),
+        AppState::ViewingMods { ref inspection, ref mut list_state, .. } => {
+            let count = inspection.jars.len();
+            let selected = list_state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Up if count > 0 => list_state.select(Some((selected + count - 1) % count)),
+                KeyCode::Down if count > 0 => list_state.select(Some((selected + 1) % count)),
+                KeyCode::PageUp => list_state.select(Some(selected.saturating_sub(10))),
+                KeyCode::PageDown if count > 0 => list_state.select(Some((selected + 10).min(count - 1))),
+                KeyCode::Char('d') | KeyCode::Char('D') if !inspection.removable.is_empty() => {
+                    app.state = AppState::ConfirmRemoveDuplicates { jars: inspection.removable.clone(), instance: inspection.path.clone(), message: None };
+                }
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    music_player.play_cancel_sfx();
+                    app.state = AppState::Browsing;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        AppState::ConfirmRemoveDuplicates { .. } => {
+            handle_remove_duplicates_input(app, key);
+            return Ok(());
+        }
+        AppState::WorldBackups { .. } | AppState::ConfirmRestoreWorld { .. } => {
+            handle_world_backups_input(app, key, music_player);
+            return Ok(());
+        }
+        // Like updates, a backup or restore can't be stopped halfway without damaging the world.
+        AppState::WorkingOnWorlds { .. } => {
+            if key.code == KeyCode::Char('p') {
+                music_player.toggle_pause();
+            }
+            return Ok(());
+        }
+        AppState::UpdateAllSummary { ref mut scroll, .. } => {
+            match key.code {
+                KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                KeyCode::Down => *scroll = scroll.saturating_add(1),
+                KeyCode::Enter | KeyCode::Esc => {
+                    music_player.play_confirm_sfx();
+                    app.state = AppState::Browsing;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        // Reached when a remembered folder is missing or no longer looks like an instance.
+        AppState::ConfirmInvalidFolder { .. } => {
+            if matches!(key.code, KeyCode::Enter | KeyCode::Esc) {
+                music_player.play_cancel_sfx();
+                app.state = AppState::Browsing;
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let highlighted = app.history_state.selected().filter(|i| *i < app.history.len());
     if app.gosling_mode {
         if key.code != KeyCode::Char('p') {
             music_player.play_sfx();
         }
-    } else {
-        match key.code {
-            KeyCode::Enter => {
-                music_player.play_confirm_sfx();
-            }
-            _ => {}
-        }
+    } else if key.code == KeyCode::Enter {
+        music_player.play_confirm_sfx();
     }
 
     match key.code {
+        KeyCode::Up if key.modifiers.contains(KeyModifiers::SHIFT) => app.move_history_entry(true),
+        KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) => app.move_history_entry(false),
         KeyCode::Up => app.history_previous(),
         KeyCode::Down => app.history_next(),
+        KeyCode::Char('r') => {
+            if let Some(index) = highlighted {
+                app.input = app.history[index].name.clone().map(Input::from).unwrap_or_default();
+                app.state = AppState::RenamingInstance { index };
+            }
+        }
+        KeyCode::Char('k') => {
+            if let Some(index) = highlighted {
+                let entry = &mut app.history[index];
+                entry.config_mode = entry.config_mode.toggled();
+                history::save(&app.history).ok();
+            }
+        }
+        KeyCode::Char('t') => {
+            if let Some(index) = highlighted {
+                app.history[index].pinned = !app.history[index].pinned;
+                history::save(&app.history).ok();
+            }
+        }
+        KeyCode::Char('m') => {
+            if let Some(path) = highlighted.map(|i| app.history[i].path.clone()).filter(|path| path.is_dir()) {
+                read_mods(app, path, None);
+            }
+        }
+        KeyCode::Char('u') => {
+            let mut changed = app.settings.clone();
+            Field::Channel.step(&mut changed, true);
+            apply_setting(app, changed, Field::Channel, music_player);
+        }
+        KeyCode::Char('s') => {
+            let mut list_state = ListState::default();
+            list_state.select(Some(0));
+            app.state = AppState::Settings { list_state, editing: None };
+        }
+        KeyCode::Char('b') => {
+            if let Some(path) = highlighted.map(|i| app.history[i].path.clone()).filter(|path| path.is_dir()) {
+                show_world_backups(app, path, None);
+            }
+        }
+        KeyCode::Char('x') => {
+            if let Some(index) = highlighted.filter(|i| app.history[*i].path.is_dir()) {
+                let entry = &app.history[index];
+                let pack = entry.pack.clone().unwrap_or_else(|| Pack::default().name);
+                app.input = Input::from(bundle::default_path(&entry.path, &pack).display().to_string());
+                app.input_error = None;
+                app.state = AppState::EnteringBundlePath { create: true, index: Some(index) };
+            }
+        }
+        KeyCode::Char('i') => {
+            app.input.reset();
+            app.input_error = None;
+            app.state = AppState::EnteringBundlePath { create: false, index: highlighted };
+        }
+        KeyCode::Char('a') => {
+            let (jobs, skipped) = bulk::plan(&app.history, &app.packs, app.side_override);
+            app.state = if jobs.is_empty() {
+                AppState::UpdateAllSummary { results: skipped, scroll: 0 }
+            } else {
+                AppState::ConfirmUpdateAll { jobs, skipped }
+            };
+        }
+        KeyCode::Delete | KeyCode::Char('d') => {
+            if let Some(index) = highlighted {
+                app.state = AppState::ConfirmRemoveInstance { index };
+            }
+        }
         KeyCode::Enter => {
+            app.pack_changelog = None;
             if let Some(selected_index) = app.history_state.selected() {
                 if selected_index < app.history.len() {
-                    let path = app.history[selected_index].clone();
+                    let path = app.history[selected_index].path.clone();
                     if is_valid_instance_folder(&path) {
-                        app.confirmed_path = Some(path);
-                        app.state = AppState::ConfirmReinit;
+                        let is_behind = app.instance_statuses.get(&path).is_some_and(|s| s.is_behind());
+                        let pack = app.pack_for(&path);
+                        let branch = app.history[selected_index].branch.clone().or_else(|| pack.as_ref().map(|p| p.default_branch.clone()));
+                        app.confirmed_path = Some(path.clone());
                         app.mode = RunMode::FileBrowser;
+                        match (is_behind, pack, branch) {
+                            // Show what's coming before asking for confirmation.
+                            (true, Some(pack), Some(branch)) => {
+                                let (tx, rx) = mpsc::channel();
+                                app.incoming_rx = Some(rx);
+                                app.state = AppState::FetchingIncoming;
+                                std::thread::spawn(move || {
+                                    git::fetch_incoming_changelog_threaded(path, pack, branch, tx);
+                                });
+                            }
+                            _ => app.state = choose_pack_or_confirm(app),
+                        }
                     } else {
                         app.state = AppState::ConfirmInvalidFolder { path };
                     }
@@ -324,11 +930,18 @@ fn handle_startup_input(app: &mut App, key: event::KeyEvent, music_player: &mut
             }
         }
         KeyCode::Char('c') => {
+            // A highlighted instance whose pack publishes a changelog shows that one instead.
+            let url = app.history_state.selected()
+                .and_then(|i| app.history.get(i))
+                .and_then(|entry| app.pack_for(&entry.path))
+                .and_then(|pack| pack.changelog_url)
+                .unwrap_or_else(|| app.settings.self_update.changelog_url.clone());
             let (tx, rx) = mpsc::channel();
             app.changelog_rx = Some(rx);
+            app.whats_new_version = None;
             app.state = AppState::FetchingChangelog;
             std::thread::spawn(move || {
-                changelog::fetch_changelog_background(tx);
+                changelog::fetch_changelog_background(url, tx);
             });
         }
         KeyCode::Char('p') => music_player.toggle_pause(),
@@ -337,18 +950,196 @@ fn handle_startup_input(app: &mut App, key: event::KeyEvent, music_player: &mut
     Ok(())
 }
 
+fn handle_bundle_path_input(app: &mut App, key: event::KeyEvent, music_player: &mut MusicPlayer, create: bool, index: Option<usize>) -> Result<()> {
+    if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('v') {
+        if let Ok(text) = Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+            app.input.handle_event(&Event::Paste(text));
+        }
+        return Ok(());
+    }
+    match key.code {
+        KeyCode::Enter => {
+            let path = git::parse_input_path(app.input.value());
+            if create {
+                let Some(entry) = index.and_then(|i| app.history.get(i)) else { return Ok(()) };
+                let pack = entry.pack.clone().unwrap_or_else(|| Pack::default().name);
+                // A folder gets the bundle under its usual name.
+                let dest = if path.is_dir() { path.join(bundle::default_path(&entry.path, &pack).file_name().unwrap_or_default()) } else { path };
+                let instance = entry.path.clone();
+                let (tx, rx) = mpsc::channel();
+                app.bundle_rx = Some(rx);
+                app.state = AppState::CreatingBundle { message: "Saving the bundle...".to_string(), progress: 0.0 };
+                std::thread::spawn(move || bundle::create_threaded(instance, dest, pack, tx));
+            } else {
+                let opened = bundle::read_manifest(&path).and_then(|manifest| Ok((manifest.register_pack()?, manifest)));
+                let ((pack, added_pack), manifest) = match opened {
+                    Ok(opened) => opened,
+                    Err(e) => {
+                        app.input_error = Some(format!("Error: {:#}", e));
+                        return Ok(());
+                    }
+                };
+                music_player.play_confirm_sfx();
+                if packs::find(&app.packs, &pack.name).is_none() {
+                    app.packs.push(pack.clone());
+                }
+                app.input_error = None;
+                app.bundle = Some(path);
+                app.bundle_added_pack = added_pack;
+                app.selected_pack = Some(pack);
+                app.confirmed_branch = Some(manifest.branch);
+                app.pack_changelog = None;
+                match index.map(|i| app.history[i].path.clone()) {
+                    Some(instance) if is_valid_instance_folder(&instance) => {
+                        app.confirmed_path = Some(instance);
+                        app.mode = RunMode::FileBrowser;
+                        app.state = AppState::ConfirmReinit;
+                    }
+                    Some(instance) => {
+                        app.bundle = None;
+                        app.state = AppState::ConfirmInvalidFolder { path: instance };
+                    }
+                    // Picked in the file browser, which goes on to the confirmation as usual.
+                    None => app.init_file_browser(env::current_dir()?)?,
+                }
+            }
+        }
+        KeyCode::Esc => {
+            music_player.play_cancel_sfx();
+            app.input_error = None;
+            app.state = AppState::Browsing;
+        }
+        _ => {
+            app.input.handle_event(&Event::Key(key));
+        }
+    }
+    Ok(())
+}
+
+fn start_update_all(app: &mut App, jobs: Vec<BulkJob>, skipped: Vec<BulkResult>) {
+    let (tx, rx) = mpsc::channel();
+    app.bulk_rx = Some(rx);
+    app.bulk_run = Some(BulkRun::new(&jobs, skipped));
+    app.state = AppState::UpdatingAll { message: "Initializing...".to_string(), progress: 0.0 };
+    let concurrency = app.settings.updates.concurrency;
+    std::thread::spawn(move || {
+        bulk::run_threaded(jobs, concurrency, tx);
+    });
+}
+
+fn read_mods(app: &mut App, path: std::path::PathBuf, error: Option<String>) {
+    let (tx, rx) = mpsc::channel();
+    app.mods_rx = Some(rx);
+    app.state = AppState::ReadingMods { error };
+    std::thread::spawn(move || {
+        health::inspect_threaded(path, tx);
+    });
+}
+
+fn show_world_backups(app: &mut App, instance: PathBuf, outcome: Option<Result<String, String>>) {
+    let backups = worlds::list(&instance);
+    let mut list_state = ListState::default();
+    if !backups.is_empty() {
+        list_state.select(Some(0));
+    }
+    app.state = AppState::WorldBackups { instance, backups, list_state, outcome };
+}
+
+fn handle_world_backups_input(app: &mut App, key: event::KeyEvent, music_player: &mut MusicPlayer) {
+    match &mut app.state {
+        AppState::WorldBackups { instance, backups, list_state, .. } => {
+            let count = backups.len();
+            let selected = list_state.selected().unwrap_or(0);
+            let instance = instance.clone();
+            let side = app.side_override.unwrap_or_else(|| Side::detect(&instance));
+            match key.code {
+                KeyCode::Up if count > 0 => list_state.select(Some((selected + count - 1) % count)),
+                KeyCode::Down if count > 0 => list_state.select(Some((selected + 1) % count)),
+                KeyCode::Enter if count > 0 => {
+                    music_player.play_confirm_sfx();
+                    let backup = backups[selected].clone();
+                    app.state = AppState::ConfirmRestoreWorld { instance, backup };
+                }
+                KeyCode::Char('a') => {
+                    if let Some(entry) = app.history.iter_mut().find(|e| e.path == instance) {
+                        entry.backup_worlds = !entry.backup_worlds;
+                        history::save(&app.history).ok();
+                    }
+                }
+                KeyCode::Char('b') => {
+                    let keep = app.history.iter().find(|e| e.path == instance).map_or(worlds::DEFAULT_KEEP, |e| e.keep_world_backups);
+                    let (tx, rx) = mpsc::channel();
+                    app.worlds_rx = Some(rx);
+                    app.state = AppState::WorkingOnWorlds { instance: instance.clone(), message: "Backing up worlds...".to_string(), progress: 0.0 };
+                    std::thread::spawn(move || worlds::backup_threaded(instance, side, keep, tx));
+                }
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    music_player.play_cancel_sfx();
+                    app.state = AppState::Browsing;
+                }
+                _ => {}
+            }
+        }
+        AppState::ConfirmRestoreWorld { instance, backup } => {
+            let (instance, backup) = (instance.clone(), backup.clone());
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    music_player.play_confirm_sfx();
+                    let side = app.side_override.unwrap_or_else(|| Side::detect(&instance));
+                    let (tx, rx) = mpsc::channel();
+                    app.worlds_rx = Some(rx);
+                    app.state = AppState::WorkingOnWorlds { instance: instance.clone(), message: "Restoring world...".to_string(), progress: 0.0 };
+                    std::thread::spawn(move || worlds::restore_threaded(instance, side, backup, tx));
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                    music_player.play_cancel_sfx();
+                    show_world_backups(app, instance, None);
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+// Shared by the Mods screen and the finished screen, which is returned to with the outcome.
+fn handle_remove_duplicates_input(app: &mut App, key: event::KeyEvent) {
+    let AppState::ConfirmRemoveDuplicates { jars, instance, message } = &app.state else { return };
+    let (jars, instance, message) = (jars.clone(), instance.clone(), message.clone());
+    match key.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') => {
+            let failed = health::remove_jars(&jars, &mut app.mod_problems);
+            app.removable_duplicates.clear();
+            let details: Vec<String> = failed.iter().map(|(jar, e)| format!("{}: {}", jar.display(), e)).collect();
+            let Some(mut message) = message else {
+                let error = (!details.is_empty()).then(|| format!("Could not remove {}", details.join("; ")));
+                read_mods(app, instance, error);
+                return;
+            };
+            if !details.is_empty() {
+                let body = message.trim_end_matches(ui::CLOSE_HINT).trim_end();
+                message = format!("{}\n\nCould not remove:\n{}\n\n{}", body, details.join("\n"), ui::CLOSE_HINT);
+            }
+            app.state = AppState::Finished(message);
+        }
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => match message {
+            Some(message) => app.state = AppState::Finished(message),
+            None => read_mods(app, instance, None),
+        },
+        _ => {}
+    }
+}
+
 fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player: &mut MusicPlayer) -> Result<bool> {
-    match &app.state {
-        AppState::Browsing => match key.code {
+    if let AppState::Browsing = &app.state {
+        match key.code {
             KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {}
-            KeyCode::Enter => {
-                if !app.items.is_empty() {
-                    let current_path = &app.items[app.selected];
-                    if Some(current_path) == app.selected_path.as_ref() {
-                        music_player.play_confirm_sfx();
-                    } else {
-                        music_player.play_scroll_sfx();
-                    }
+            KeyCode::Enter if !app.items.is_empty() => {
+                let current_path = &app.items[app.selected];
+                if Some(current_path) == app.selected_path.as_ref() {
+                    music_player.play_confirm_sfx();
+                } else {
+                    music_player.play_scroll_sfx();
                 }
             }
             KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
@@ -361,8 +1152,7 @@ fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player:
                 music_player.play_confirm_sfx();
             }
             _ => {}
-        },
-        _ => {}
+        }
     }
 
     let mut next_state: Option<AppState> = None;
@@ -375,41 +1165,39 @@ fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player:
             KeyCode::Right => app.go_in()?,
             KeyCode::Left => app.go_up()?,
             KeyCode::Home => app.reset()?,
-            KeyCode::Enter => {
-                if !app.items.is_empty() {
-                    let current_path = &app.items[app.selected];
-                    if Some(current_path) == app.selected_path.as_ref() {
-                        if is_valid_instance_folder(current_path) {
-                            app.confirmed_path = Some(current_path.clone());
-                            next_state = Some(AppState::ConfirmReinit);
-                            if app.tutorial.is_some() {
-                                history::mark_tutorial_as_completed().ok();
-                                app.tutorial = None;
-                                if let Some(version) = app.pending_update.clone() {
-                                    next_state = Some(AppState::ConfirmUpdate { version });
-                                }
+            KeyCode::Enter if !app.items.is_empty() => {
+                let current_path = &app.items[app.selected];
+                if Some(current_path) == app.selected_path.as_ref() {
+                    if is_valid_instance_folder(current_path) {
+                        app.confirmed_path = Some(current_path.clone());
+                        next_state = Some(choose_pack_or_confirm(app));
+                        if app.tutorial.is_some() {
+                            history::mark_tutorial_as_completed().ok();
+                            app.tutorial = None;
+                            if let Some(release) = app.pending_update.clone() {
+                                next_state = Some(AppState::ConfirmUpdate { release });
                             }
-                        } else {
-                            next_state = Some(AppState::ConfirmInvalidFolder {
-                                path: current_path.clone(),
-                            });
                         }
                     } else {
-                        app.selected_path = Some(current_path.clone());
+                        next_state = Some(AppState::ConfirmInvalidFolder {
+                            path: current_path.clone(),
+                        });
                     }
+                } else {
+                    app.selected_path = Some(current_path.clone());
                 }
             }
             KeyCode::Esc => {
                 if app.selected_path.is_some() {
                     app.selected_path = None;
                 } else {
-                    app.history.retain(|path| path.exists() && path.is_dir());
                     if app.history.is_empty() {
                         app.history_state.select(None);
                     } else {
                         app.history_state.select(Some(app.history.len()));
                     }
                     app.mode = RunMode::StartupSelection;
+                    app.bundle = None;
                 }
             }
             KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
@@ -427,11 +1215,9 @@ fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player:
                 app.selected_path = None;
             }
         }
-        AppState::InsideInstanceFolderError => {
-            if key.code == KeyCode::Left {
-                app.go_up()?;
-                next_state = Some(AppState::Browsing);
-            }
+        AppState::InsideInstanceFolderError if key.code == KeyCode::Left => {
+            app.go_up()?;
+            next_state = Some(AppState::Browsing);
         }
         AppState::AwaitingInput => {
             if key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('v') {
@@ -485,13 +1271,56 @@ fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player:
                 }
             }
         },
+        AppState::PackSelection { list_state } => match key.code {
+            KeyCode::Down => {
+                let i = list_state.selected().map_or(0, |i| (i + 1) % app.packs.len());
+                list_state.select(Some(i));
+            }
+            KeyCode::Up => {
+                let i = list_state.selected().map_or(0, |i| (i + app.packs.len() - 1) % app.packs.len());
+                list_state.select(Some(i));
+            }
+            KeyCode::Enter => {
+                if let Some(i) = list_state.selected() {
+                    app.selected_pack = app.packs.get(i).cloned();
+                    next_state = Some(AppState::ConfirmReinit);
+                }
+            }
+            KeyCode::Esc => {
+                next_state = Some(AppState::Browsing);
+                app.confirmed_path = None;
+            }
+            _ => {}
+        },
+        AppState::ReviewIncoming { scroll, .. } => match key.code {
+            KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down => *scroll = scroll.saturating_add(1),
+            KeyCode::Enter => next_state = Some(choose_pack_or_confirm(app)),
+            KeyCode::Esc => {
+                next_state = Some(AppState::Browsing);
+                app.confirmed_path = None;
+                app.mode = RunMode::StartupSelection;
+            }
+            _ => {}
+        },
         AppState::ConfirmReinit => match key.code {
+            // The bundle holds one branch, so there's nothing to choose.
+            KeyCode::Char('y') | KeyCode::Char('Y') if app.bundle.is_some() => {
+                branch_to_process = app.confirmed_branch.clone();
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc if app.bundle.is_some() => {
+                next_state = Some(AppState::Browsing);
+                app.confirmed_path = None;
+                app.bundle = None;
+                app.mode = RunMode::StartupSelection;
+            }
             KeyCode::Char('y') | KeyCode::Char('Y') => {
                 let (tx, rx) = mpsc::channel();
                 app.branch_rx = Some(rx);
                 next_state = Some(AppState::FetchingBranches);
+                let remotes = app.selected_pack.clone().unwrap_or_default().remotes();
                 std::thread::spawn(move || {
-                    git::fetch_remote_branches_threaded(tx);
+                    git::fetch_remote_branches_threaded(remotes, tx);
                 });
             }
             KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
@@ -502,17 +1331,13 @@ fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player:
         },
         AppState::BranchSelection { branches, list_state, selected_branch } => {
             match key.code {
-                KeyCode::Down => {
-                    if !branches.is_empty() {
-                        let i = list_state.selected().map_or(0, |i| (i + 1) % branches.len());
-                        list_state.select(Some(i));
-                    }
+                KeyCode::Down if !branches.is_empty() => {
+                    let i = list_state.selected().map_or(0, |i| (i + 1) % branches.len());
+                    list_state.select(Some(i));
                 }
-                KeyCode::Up => {
-                    if !branches.is_empty() {
-                        let i = list_state.selected().map_or(0, |i| (i + branches.len() - 1) % branches.len());
-                        list_state.select(Some(i));
-                    }
+                KeyCode::Up if !branches.is_empty() => {
+                    let i = list_state.selected().map_or(0, |i| (i + branches.len() - 1) % branches.len());
+                    list_state.select(Some(i));
                 }
                 KeyCode::Enter => {
                     if let Some(i) = list_state.selected() {
@@ -534,11 +1359,51 @@ fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player:
                 _ => {}
             }
         }
-        AppState::Finished(_) => {
-            if matches!(key.code, KeyCode::Enter | KeyCode::Char('q') | KeyCode::Esc) {
-                return Ok(false);
+        AppState::InstanceInUse { branch, .. } => match key.code {
+            KeyCode::Char('r') | KeyCode::Char('R') | KeyCode::Enter => {
+                branch_to_process = Some(branch.clone());
+            }
+            KeyCode::Esc => {
+                next_state = Some(AppState::Browsing);
+                app.confirmed_path = None;
+            }
+            _ => {}
+        },
+        AppState::ChoosingOptional { groups, choices, list_state, reply } => {
+            let count = groups.len();
+            let selected = list_state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Up => list_state.select(Some((selected + count - 1) % count)),
+                KeyCode::Down => list_state.select(Some((selected + 1) % count)),
+                KeyCode::Char(' ') => {
+                    let group = &groups[selected];
+                    let enabled = group.is_enabled(choices);
+                    choices.insert(group.id.clone(), !enabled);
+                }
+                KeyCode::Enter => {
+                    music_player.play_confirm_sfx();
+                    reply.send(Some(choices.clone())).ok();
+                    app.optional_choices = Some(choices.clone());
+                    next_state = Some(AppState::Processing { message: "Applying your choices...".to_string(), progress: 1.0 });
+                }
+                KeyCode::Esc => {
+                    music_player.play_cancel_sfx();
+                    reply.send(None).ok();
+                    app.progress_rx = None;
+                    next_state = Some(AppState::Finished(format!("Update cancelled. Nothing was changed.\n\n{}", ui::CLOSE_HINT)));
+                }
+                _ => {}
             }
         }
+        AppState::Finished(message) => match key.code {
+            KeyCode::Enter | KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('d') | KeyCode::Char('D') if !app.removable_duplicates.is_empty() => {
+                let instance = app.confirmed_path.clone().unwrap_or_default();
+                next_state = Some(AppState::ConfirmRemoveDuplicates { jars: app.removable_duplicates.clone(), instance, message: Some(message.clone()) });
+            }
+            _ => {}
+        },
+        AppState::ConfirmRemoveDuplicates { .. } => handle_remove_duplicates_input(app, key),
         _ => {}
     }
 
@@ -547,14 +1412,29 @@ fn handle_file_browser_input(app: &mut App, key: event::KeyEvent, music_player:
     }
 
     if let Some(branch) = branch_to_process {
+        app.confirmed_branch = Some(branch.clone());
+        let path = app.confirmed_path.clone().unwrap();
+        let side = app.confirmed_side();
+        // Jars can't be replaced safely while the game has them open.
+        if let Some(in_use) = running::find_running(&path, side) {
+            app.state = AppState::InstanceInUse { message: in_use.to_string(), branch };
+            return Ok(true);
+        }
+
         let (tx, rx) = mpsc::channel();
         app.progress_rx = Some(rx);
         app.state = AppState::Processing { message: "Initializing...".to_string(), progress: 0.0, };
-        let path = app.confirmed_path.clone().unwrap();
+        app.optional_choices = None;
+        let entry = app.history.iter().find(|e| e.path == path);
+        let optional = entry.map(|e| e.optional.clone()).unwrap_or_default();
+        let config_mode = entry.map(|e| e.config_mode).unwrap_or_default();
+        let protected = entry.map(|e| e.protected.clone()).unwrap_or_default();
+        let world_backups = entry.and_then(|e| e.backup_worlds.then_some(e.keep_world_backups));
+        let options = git::UpdateOptions { side, pack: app.selected_pack.clone().unwrap_or_default(), optional, choose_optional: true, config_mode, protected, world_backups, clean_managed_dirs: app.settings.updates.clean_managed_dirs, bundle: app.bundle.clone(), added_pack: app.bundle.is_some() && app.bundle_added_pack };
         std::thread::spawn(move || {
-            git::perform_git_operations_threaded(path, branch, tx);
+            git::perform_git_operations_threaded(path, branch, options, tx);
         });
     }
 
     Ok(true)
-}
\ No newline at end of file
+}
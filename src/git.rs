@@ -1,14 +1,57 @@
 pub(crate) use crate::app::GitProgress;
+use crate::app::{InstanceStatus, UpdateReport};
+use crate::app::packs::Pack;
+use crate::bundle;
+use crate::changelog::PackChangelog;
+use crate::configs::{self, ConfigMode};
+use crate::mods;
+use crate::net;
+use crate::pack::{OptionalChoices, PackMetadata, Side};
+use crate::protect;
+use crate::running;
+use crate::worlds;
 use anyhow::{bail, Context, Result};
-use git2::{build::CheckoutBuilder, AnnotatedCommit, Remote, Repository};
-use octocrab::Octocrab;
+use git2::{build::CheckoutBuilder, AnnotatedCommit, Commit, Remote, Repository};
+use globset::GlobSet;
+use ring::digest;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{self, Sender};
 use tokio::runtime::Runtime;
 
-const GIT_REMOTE_URL: &str = "https://github.com/minecraftwithtwink/Twinkcraft-Modpack.git";
+/// Folders the pack fully owns: anything untracked in them is removed on update.
+const MANAGED_DIRS: &[&str] = &[
+    "mods",
+    "kubejs",
+    "configureddefaults",
+    "resourcepacks",
+    "patchouli_books",
+    "datapacks",
+];
+
+/// Per-run settings for `perform_git_operations_threaded`.
+#[derive(Debug, Clone)]
+pub struct UpdateOptions {
+    pub side: Side,
+    pub pack: Pack,
+    pub optional: OptionalChoices,
+    /// Ask for the optional groups with `GitProgress::ChooseOptional` before changing any files.
+    pub choose_optional: bool,
+    pub config_mode: ConfigMode,
+    /// The instance's own protected paths, on top of `protect::BUILT_IN`.
+    pub protected: Vec<String>,
+    /// Back up the worlds, keeping this many backups of each, before an update that changes `mods/`.
+    pub world_backups: Option<usize>,
+    /// Remove untracked files from `MANAGED_DIRS`.
+    pub clean_managed_dirs: bool,
+    /// Update from this offline bundle instead of the pack's remote.
+    pub bundle: Option<PathBuf>,
+    /// Applying the bundle added its pack to `packs.toml`, which the result mentions.
+    pub added_pack: bool,
+}
 
 // LFS-related structures
 #[derive(Serialize)]
@@ -51,31 +94,148 @@ struct LfsAction {
 }
 
 // --- ADDED: A new function to fetch the list of remote branches ---
-pub fn fetch_remote_branches_threaded(tx: Sender<Result<Vec<String>>>) {
-    let result = (|| -> Result<Vec<String>> {
-        let mut remote = Remote::create_detached(GIT_REMOTE_URL)?;
-        remote.connect(git2::Direction::Fetch)?;
-        let list = remote.list()?;
-
-        let mut branches: Vec<String> = list.iter()
-            .filter_map(|head| {
-                let name = head.name();
-                if name.starts_with("refs/heads/") {
-                    Some(name.trim_start_matches("refs/heads/").to_string())
-                } else {
-                    None
+/// Lists the branches of the first of `remotes` (the pack's remote, then its mirrors) that answers.
+pub fn fetch_remote_branches_threaded(remotes: Vec<String>, tx: Sender<Result<Vec<String>>>) {
+    let result = list_first_remote_heads(&remotes).map(|heads| {
+        let mut branches: Vec<String> = heads.into_keys().collect();
+        branches.sort();
+        branches
+    });
+    tx.send(result).ok();
+}
+
+/// An instance to check in `check_instance_statuses_threaded`.
+pub struct StatusTarget {
+    pub path: PathBuf,
+    pub side: Side,
+    /// The pack's remote and its mirrors; empty without a pack.
+    pub remotes: Vec<String>,
+    pub branch: Option<String>,
+    pub pinned: bool,
+    pub optional: OptionalChoices,
+    pub protected: Vec<String>,
+}
+
+// Lists the branch tips of a remote without fetching anything.
+fn list_remote_heads(remote_url: &str) -> Result<HashMap<String, git2::Oid>> {
+    net::ensure_online(remote_url)?;
+    let mut remote = Remote::create_detached(remote_url)?;
+    net::retry(|| net::git_connect(&mut remote))?;
+    let heads = remote.list()?
+        .iter()
+        .filter_map(|head| head.name().strip_prefix("refs/heads/").map(|name| (name.to_string(), head.oid())))
+        .collect();
+    Ok(heads)
+}
+
+// Counts tracked files with local modifications. Untracked files don't matter to an update,
+// files removed because they belong to the other side or a disabled optional group are expected,
+// and protected files are the player's to change.
+fn count_local_changes(repo: &Repository, side: Side, optional: &OptionalChoices, protected: &GlobSet) -> usize {
+    let excluded = repo.head().and_then(|h| h.peel_to_commit()).ok()
+        .and_then(|commit| PackMetadata::from_commit(repo, &commit).ok())
+        .and_then(|metadata| metadata.excluded_paths(side, optional).ok())
+        .unwrap_or_default();
+
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false).exclude_submodules(true);
+    repo.statuses(Some(&mut opts))
+        .map(|statuses| {
+            statuses.iter()
+                // Excluded files are never checked out, so they can show as deleted from the index too.
+                .filter(|entry| !entry.path().is_some_and(|p| excluded.is_match(p)))
+                .filter(|entry| !entry.path().is_some_and(|p| protected.is_match(p)))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+fn instance_status(target: &StatusTarget, tip: git2::Oid) -> Result<InstanceStatus> {
+    let repo = Repository::open(&target.path)?;
+    let head = repo.head()?.target().context("HEAD does not point to a commit")?;
+    let protected = protect::protected_paths(&target.protected)?;
+    let dirty = count_local_changes(&repo, target.side, &target.optional, &protected);
+    if head == tip {
+        return Ok(InstanceStatus::UpToDate { dirty });
+    }
+    // The count is only known when the remote tip was fetched before, e.g. by an earlier review.
+    let commits = if repo.find_commit(tip).is_ok() {
+        repo.graph_ahead_behind(head, tip).ok().map(|(_, behind)| behind)
+    } else {
+        None
+    };
+    match commits {
+        Some(0) => Ok(InstanceStatus::UpToDate { dirty }),
+        commits => Ok(InstanceStatus::Behind { commits, dirty }),
+    }
+}
+
+// The branch tips of the first remote that answers, trying the mirrors after the pack's remote.
+fn list_first_remote_heads(remotes: &[String]) -> Result<HashMap<String, git2::Oid>> {
+    let mut first_error = None;
+    for remote in remotes {
+        match list_remote_heads(remote) {
+            Ok(heads) => return Ok(heads),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| anyhow::anyhow!("The pack has no remote.")))
+}
+
+/// Compares each instance's HEAD with its remote branch tip, listing every remote only once.
+pub fn check_instance_statuses_threaded(targets: Vec<StatusTarget>, tx: Sender<(PathBuf, InstanceStatus)>) {
+    let mut remote_heads: HashMap<String, Result<HashMap<String, git2::Oid>, InstanceStatus>> = HashMap::new();
+
+    for target in targets {
+        let status = if target.pinned {
+            InstanceStatus::Pinned
+        } else {
+            match (target.remotes.first(), &target.branch) {
+                (Some(remote), Some(branch)) => {
+                    let heads = remote_heads
+                        .entry(remote.clone())
+                        .or_insert_with(|| list_first_remote_heads(&target.remotes).map_err(|e| {
+                            if net::is_offline(&e) { InstanceStatus::Offline } else { InstanceStatus::Unknown(e.to_string()) }
+                        }));
+                    match heads {
+                        Ok(heads) => match heads.get(branch) {
+                            Some(tip) => instance_status(&target, *tip).unwrap_or_else(|e| InstanceStatus::Unknown(e.to_string())),
+                            None => InstanceStatus::Unknown(format!("Branch '{}' no longer exists", branch)),
+                        },
+                        Err(status) => status.clone(),
+                    }
                 }
-            })
-            .collect();
+                _ => InstanceStatus::Unknown("No pack associated".to_string()),
+            }
+        };
+        if tx.send((target.path, status)).is_err() {
+            return;
+        }
+    }
+}
 
-        branches.sort();
-        Ok(branches)
+/// Fetches the branch into the instance without touching its files and describes what
+/// an update would bring in.
+pub fn fetch_incoming_changelog_threaded(path: PathBuf, pack: Pack, branch_name: String, tx: Sender<Result<PackChangelog>>) {
+    let result = (|| -> Result<PackChangelog> {
+        let repo = Repository::open(&path)?;
+        repo.remote_set_url("origin", &pack.remote).context("Failed to set remote URL")?;
+        let mut fo = net::git_fetch_options()?;
+        // Nobody watches the progress of this quick fetch.
+        let (progress_tx, _) = mpsc::channel();
+        fetch_branch(&repo, &pack, &branch_name, &mut fo, &progress_tx)?;
+
+        let tip = repo.find_reference(&format!("refs/remotes/origin/{}", branch_name))?.peel_to_commit()?;
+        let head = repo.head().ok().and_then(|h| h.target());
+        PackChangelog::between(&repo, head, tip.id())
     })();
     tx.send(result).ok();
 }
 
 // Function to check if a file is an LFS pointer file
-fn is_lfs_pointer_file(content: &str) -> Option<(String, u64)> {
+pub fn is_lfs_pointer_file(content: &str) -> Option<(String, u64)> {
     let lines: Vec<&str> = content.lines().collect();
     if lines.len() >= 3
         && lines[0] == "version https://git-lfs.github.com/spec/v1"
@@ -91,86 +251,76 @@ fn is_lfs_pointer_file(content: &str) -> Option<(String, u64)> {
     None
 }
 
-// Function to download LFS files using GitHub API
-async fn download_lfs_files_async(repo_path: &Path, branch_name: &str, progress_tx: &Sender<GitProgress>) -> Result<()> {
+// Function to download LFS files from the pack's LFS server, or its mirrors in order. Returns the
+// mirror that served files, if any.
+async fn download_lfs_files_async(repo: &Repository, repo_path: &Path, lfs_urls: &[String], progress_tx: &Sender<GitProgress>) -> Result<Option<String>> {
     progress_tx.send(GitProgress::Update("Scanning for LFS files...".to_string(), 0.0)).ok();
 
-    let octocrab = Octocrab::builder().build()?;
-    let owner = "minecraftwithtwink";
-    let repo_name = "Twinkcraft-Modpack";
-
-    // Get repository contents recursively to find LFS files
-    let mut lfs_files = Vec::new();
-    scan_for_lfs_files_recursive(&octocrab, owner, repo_name, branch_name, "", repo_path, &mut lfs_files).await?;
+    let lfs_files = scan_for_lfs_files(repo, repo_path)?;
 
     if lfs_files.is_empty() {
         progress_tx.send(GitProgress::Update("No LFS files found.".to_string(), 1.0)).ok();
-        return Ok(());
+        return Ok(None);
     }
 
     progress_tx.send(GitProgress::Update(format!("Found {} LFS files, downloading...", lfs_files.len()), 0.1)).ok();
 
-    // Download LFS files in batches
+    // An endpoint that fails isn't tried again for the later files, so a blocked server costs one timeout.
+    let mut current = 0;
+    let mut used_mirror = None;
     for (i, (file_path, oid, size)) in lfs_files.iter().enumerate() {
         let progress = 0.1 + (i as f64 / lfs_files.len() as f64) * 0.9;
         progress_tx.send(GitProgress::Update(format!("Downloading LFS file: {}", file_path), progress)).ok();
 
-        download_single_lfs_file(owner, repo_name, oid, *size, &repo_path.join(file_path)).await?;
+        let local_path = repo_path.join(file_path);
+        loop {
+            let lfs_url = &lfs_urls[current];
+            let result = match net::ensure_online(lfs_url) {
+                Ok(()) => net::retry_async(|| download_single_lfs_file(lfs_url, oid, *size, &local_path)).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    if current > 0 {
+                        used_mirror = Some(lfs_url.clone());
+                    }
+                    break;
+                }
+                Err(_) if current + 1 < lfs_urls.len() => {
+                    current += 1;
+                    progress_tx.send(GitProgress::Update(format!("Trying LFS mirror {}...", lfs_urls[current]), progress)).ok();
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     progress_tx.send(GitProgress::Update("LFS files downloaded successfully.".to_string(), 1.0)).ok();
-    Ok(())
+    Ok(used_mirror)
 }
 
-// Recursive function to scan for LFS files in repository
-fn scan_for_lfs_files_recursive<'a>(
-    octocrab: &'a Octocrab,
-    owner: &'a str,
-    repo: &'a str,
-    branch: &'a str,
-    path: &'a str,
-    local_repo_path: &'a Path,
-    lfs_files: &'a mut Vec<(String, String, u64)>,
-) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + 'a>> {
-    Box::pin(async move {
-    let contents = octocrab
-        .repos(owner, repo)
-        .get_content()
-        .path(path)
-        .r#ref(branch)
-        .send()
-        .await?;
-
-    for item in contents.items {
-        let item_path = if path.is_empty() { item.name.clone() } else { format!("{}/{}", path, item.name) };
-
-        match item.r#type.as_str() {
-            "file" => {
-                // Check if this file exists locally and is an LFS pointer
-                let local_file_path = local_repo_path.join(&item_path);
-                if local_file_path.exists() {
-                    if let Ok(content) = std::fs::read_to_string(&local_file_path) {
-                        if let Some((oid, size)) = is_lfs_pointer_file(&content) {
-                            lfs_files.push((item_path, oid, size));
-                        }
-                    }
-                }
-            }
-            "dir" => {
-                // Recursively scan subdirectories
-                scan_for_lfs_files_recursive(octocrab, owner, repo, branch, &item_path, local_repo_path, lfs_files).await?;
+// Finds tracked files that were checked out as LFS pointers instead of their content
+pub fn scan_for_lfs_files(repo: &Repository, local_repo_path: &Path) -> Result<Vec<(String, String, u64)>> {
+    let mut lfs_files = Vec::new();
+    for entry in repo.index()?.iter() {
+        let item_path = String::from_utf8_lossy(&entry.path).into_owned();
+        let local_file_path = local_repo_path.join(&item_path);
+        // Pointer files are tiny; anything bigger already holds real content.
+        if fs::metadata(&local_file_path).map(|m| m.len() > 1024).unwrap_or(true) {
+            continue;
+        }
+        if let Ok(content) = std::fs::read_to_string(&local_file_path) {
+            if let Some((oid, size)) = is_lfs_pointer_file(&content) {
+                lfs_files.push((item_path, oid, size));
             }
-            _ => {} // Ignore other types
         }
     }
-
-    Ok(())
-    })
+    Ok(lfs_files)
 }
 
 // Function to download a single LFS file
-async fn download_single_lfs_file(owner: &str, repo: &str, oid: &str, size: u64, local_path: &Path) -> Result<()> {
-    let client = reqwest::Client::new();
+async fn download_single_lfs_file(lfs_url: &str, oid: &str, size: u64, local_path: &Path) -> Result<()> {
+    let client = net::async_client()?;
 
     // Create the batch request
     let batch_request = LfsBatchRequest {
@@ -183,9 +333,9 @@ async fn download_single_lfs_file(owner: &str, repo: &str, oid: &str, size: u64,
     };
 
     // Make request to LFS batch API
-    let lfs_url = format!("https://github.com/{}/{}.git/info/lfs/objects/batch", owner, repo);
+    let batch_url = format!("{}/objects/batch", lfs_url);
     let response = client
-        .post(&lfs_url)
+        .post(&batch_url)
         .header("Accept", "application/vnd.git-lfs+json")
         .header("Content-Type", "application/json")
         .json(&batch_request)
@@ -202,21 +352,26 @@ async fn download_single_lfs_file(owner: &str, repo: &str, oid: &str, size: u64,
         if let Some(actions) = &object.actions {
             if let Some(download_action) = &actions.download {
                 // Download the actual file
-                let file_response = client.get(&download_action.href).send().await?;
+                let mut file_response = client.get(&download_action.href).send().await?;
 
                 if !file_response.status().is_success() {
                     bail!("Failed to download LFS file: {}", file_response.status());
                 }
 
-                let file_content = file_response.bytes().await?;
-
                 // Ensure parent directory exists
                 if let Some(parent) = local_path.parent() {
                     std::fs::create_dir_all(parent)?;
                 }
 
-                // Write the file
-                std::fs::write(local_path, file_content)?;
+                // The pointer is only replaced once the content matches its oid
+                let file_name = local_path.file_name().unwrap_or_default().to_string_lossy();
+                let temp_path = local_path.with_file_name(format!("{}.lfs-partial", file_name));
+                let written = write_lfs_object(&mut file_response, &temp_path, oid).await;
+                if written.is_err() {
+                    fs::remove_file(&temp_path).ok();
+                }
+                written?;
+                fs::rename(&temp_path, local_path)?;
                 return Ok(());
             }
         }
@@ -225,86 +380,145 @@ async fn download_single_lfs_file(owner: &str, repo: &str, oid: &str, size: u64,
     bail!("No download URL found for LFS file with OID: {}", oid);
 }
 
-fn clean_managed_directories(repo: &Repository, progress_tx: &Sender<GitProgress>) -> Result<()> {
-    const DIRS_TO_CLEAN: &[&str] = &[
-        "mods",
-        "kubejs",
-        "configureddefaults",
-        "resourcepacks",
-        "patchouli_books",
-        "datapacks",
-    ];
+// Streams an LFS object into `path`, hashing it on the way, and fails if it isn't the object `oid` names.
+async fn write_lfs_object(response: &mut reqwest::Response, path: &Path, oid: &str) -> Result<()> {
+    let mut file = fs::File::create(path)?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    while let Some(chunk) = response.chunk().await? {
+        context.update(&chunk);
+        file.write_all(&chunk)?;
+    }
+    let hash: String = context.finish().as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    if !hash.eq_ignore_ascii_case(oid) {
+        bail!("The LFS file with OID {} was served with the SHA-256 {} instead.", oid, hash);
+    }
+    Ok(())
+}
 
+fn clean_managed_directories(repo: &Repository, instance_path: &Path, progress_tx: &Sender<GitProgress>) -> Result<()> {
     progress_tx.send(GitProgress::Update("Cleaning managed directories...".to_string(), 1.0)).ok();
 
-    for dir_name in DIRS_TO_CLEAN {
-        let mut builder = CheckoutBuilder::new();
-        builder.force().remove_untracked(true).path(dir_name);
-        repo.checkout_head(Some(&mut builder)).context(format!("Failed to clean the '{}' directory.", dir_name))?;
+    // The forced checkout already reset the tracked files, so only untracked ones are left to remove.
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(true).recurse_untracked_dirs(false).include_ignored(false).exclude_submodules(true);
+    for dir_name in MANAGED_DIRS {
+        opts.pathspec(dir_name);
+    }
+    for entry in repo.statuses(Some(&mut opts))?.iter().filter(|e| e.status().is_wt_new()) {
+        let relative = String::from_utf8_lossy(entry.path_bytes()).into_owned();
+        let path = instance_path.join(&relative);
+        let removed = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        removed.context(format!("Failed to clean '{}'.", relative))?;
     }
     Ok(())
 }
 
-fn force_copy_default_configs(instance_path: &Path, progress_tx: &Sender<GitProgress>) -> Result<()> {
-    progress_tx.send(GitProgress::Update("Applying default configurations...".to_string(), 1.0)).ok();
-
-    let source_base = instance_path.join("configureddefaults");
-
-    const ITEMS_TO_COPY: &[(&str, &str, bool)] = &[
-        ("config/fancymenu", "config/fancymenu", true),
-        ("customsplashscreen", "customsplashscreen", true),
-        ("config/fog", "config/fog", true),
-        ("config/customsplashscreen.json", "config/customsplashscreen.json", false),
-        ("config/raised.json", "config/raised.json", false),
-        ("sodium-extra.properties", "sodium-extra.properties", false),
-        ("sodiumextrainformation.json", "sodiumextrainformation.json", false),
-        ("sodium-extra-options.json", "sodium-extra-options.json", false),
-        ("sodium-fingerprint.json", "sodium-fingerprint.json", false),
-        ("sodium-mixins.properties", "sodium-mixins.properties", false),
-        ("sodium-options.json", "sodium-options.json", false),
-        ("sodium-shadowy-path-blocks-options.json", "sodium-shadowy-path-blocks-options.json", false),
-        ("tectonic.json", "tectonic.json", false),
-        ("sparsestructures.json5", "sparsestructures.json5", false),
-        ("parcool-client.toml", "parcool-client.toml", false),
-    ];
-
-    for (source_suffix, dest_suffix, is_directory) in ITEMS_TO_COPY {
-        let source_path = source_base.join(source_suffix);
-        let dest_path = instance_path.join(dest_suffix);
-
-        if source_path.exists() {
-            if let Some(parent) = dest_path.parent() {
-                fs::create_dir_all(parent)?;
+// Deletes tracked files that don't belong on this side but are still there from an earlier update.
+fn remove_excluded_files(repo: &Repository, instance_path: &Path, excluded: &GlobSet, progress_tx: &Sender<GitProgress>) -> Result<()> {
+    if excluded.is_empty() {
+        return Ok(());
+    }
+    progress_tx.send(GitProgress::Update("Removing excluded files...".to_string(), 1.0)).ok();
+
+    let index = repo.index()?;
+    for entry in index.iter() {
+        let relative = String::from_utf8_lossy(&entry.path).into_owned();
+        if excluded.is_match(&relative) {
+            let file_path = instance_path.join(&relative);
+            if file_path.is_file() {
+                fs::remove_file(&file_path).context(format!("Failed to remove '{}'.", relative))?;
             }
+        }
+    }
+    Ok(())
+}
 
-            if *is_directory {
-                if dest_path.exists() {
-                    fs::remove_dir_all(&dest_path)?;
-                }
-                copy_dir_all(&source_path, &dest_path)?;
-            } else {
-                fs::copy(&source_path, &dest_path)?;
+// Forces the working tree to match HEAD without ever writing the excluded files. Paths that are
+// only in the index are kept in the list so files the new commit deletes are still removed.
+fn force_checkout_head(repo: &Repository, excluded: &GlobSet) -> Result<()> {
+    let mut builder = CheckoutBuilder::new();
+    builder.force();
+    if !excluded.is_empty() {
+        let mut paths = BTreeSet::new();
+        repo.head()?.peel_to_tree()?.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                paths.insert(format!("{}{}", dir, entry.name().unwrap_or_default()));
             }
+            git2::TreeWalkResult::Ok
+        })?;
+        paths.extend(repo.index()?.iter().map(|entry| String::from_utf8_lossy(&entry.path).into_owned()));
+        builder.disable_pathspec_match(true);
+        for path in paths.iter().filter(|path| !excluded.is_match(path.as_str())) {
+            builder.path(path);
         }
     }
-
+    repo.checkout_head(Some(&mut builder))?;
     Ok(())
 }
 
-fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()> {
-    fs::create_dir_all(&dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+// A fresh install counts as changing the mods: the instance may have been played with others.
+fn changes_mods(repo: &Repository, old_head: Option<git2::Oid>, new: &Commit) -> bool {
+    let mods_tree = |commit: &Commit| commit.tree().ok()?.get_path(Path::new("mods")).ok().map(|entry| entry.id());
+    match old_head.and_then(|oid| repo.find_commit(oid).ok()) {
+        Some(old) => mods_tree(&old) != mods_tree(new),
+        None => true,
+    }
+}
+
+// Moves the instance's branch to `fetch_commit` and forces the working tree to match, leaving out
+// the excluded files.
+fn check_out(repo: &Repository, fetch_commit: &Commit, branch_name: &str, excluded: &GlobSet, progress_tx: &Sender<GitProgress>) -> Result<()> {
+    let fetch_head: AnnotatedCommit = repo.find_annotated_commit(fetch_commit.id())?;
+    let (analysis, _) = repo.merge_analysis(&[&fetch_head])?;
+
+    if analysis.is_up_to_date() {
+        progress_tx.send(GitProgress::Update("Repository up-to-date. Verifying files...".to_string(), 1.0)).ok();
+        force_checkout_head(repo, excluded)?;
+    } else if analysis.is_fast_forward() || repo.head().is_err() {
+        progress_tx.send(GitProgress::Update("Applying fast-forward update...".to_string(), 1.0)).ok();
+        let local_branch_ref_name = format!("refs/heads/{}", branch_name);
+        let mut local_branch_ref = match repo.find_reference(&local_branch_ref_name) {
+            Ok(r) => r,
+            Err(_) => repo.reference(&local_branch_ref_name, fetch_commit.id(), true, "Create local branch")?,
+        };
+        local_branch_ref.set_target(fetch_commit.id(), "Fast-forward")?;
+        repo.set_head(&local_branch_ref_name)?;
+        force_checkout_head(repo, excluded)?;
+    } else {
+        progress_tx.send(GitProgress::Update("Merging changes...".to_string(), 1.0)).ok();
+        let our_commit = repo.head()?.peel_to_commit()?;
+        let merge_base_oid = repo.merge_base(our_commit.id(), fetch_commit.id())?;
+        let merge_base_commit = repo.find_commit(merge_base_oid)?;
+        let mut index = repo.merge_trees(&merge_base_commit.tree()?, &our_commit.tree()?, &fetch_commit.tree()?, None)?;
+        if index.has_conflicts() {
+            bail!("Merge conflict detected! Please resolve manually.");
         }
+        let result_tree_id = index.write_tree_to(repo)?;
+        let result_tree = repo.find_tree(result_tree_id)?;
+        let signature = git2::Signature::now("Modpack Updater", "updater@example.com")?;
+        repo.commit(Some("HEAD"), &signature, &signature, &format!("Merge remote-tracking branch 'origin/{}'", branch_name), &result_tree, &[&our_commit, fetch_commit])?;
+        force_checkout_head(repo, excluded)?;
+    }
+    Ok(())
+}
+
+// A dedicated server's world must never be part of the pack, or checkout would overwrite it.
+fn ensure_world_untracked(commit: &Commit, instance_path: &Path) -> Result<()> {
+    let level_dir = running::server_level_dir(instance_path);
+    let level_name = level_dir.file_name().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("world"));
+    if commit.tree()?.get_path(&level_name).is_ok() {
+        bail!("The pack tracks '{}', which is this server's world. Refusing to update.", level_name.display());
     }
     Ok(())
 }
 
+/// The commit an instance's working tree is on, abbreviated.
+pub fn head_commit(path: &Path) -> Option<String> {
+    let repo = Repository::open(path).ok()?;
+    let oid = repo.head().ok()?.target()?;
+    Some(oid.to_string()[..7].to_string())
+}
+
 pub fn parse_input_path(input: &str) -> PathBuf {
     let trimmed = input.trim();
     let stripped = if trimmed.starts_with('"') && trimmed.ends_with('"') && trimmed.len() > 1 {
@@ -318,9 +532,72 @@ pub fn parse_input_path(input: &str) -> PathBuf {
     { PathBuf::from(stripped.replace('\\', "/")) }
 }
 
+/// The mirror an update came from because the pack's remote couldn't be used.
+struct UsedMirror {
+    url: String,
+    /// Whether the pack's remote could be asked for the tip the mirror had to match.
+    verified: bool,
+}
+
+// Fetches the branch into `refs/remotes/origin` from the pack's remote, or else from the first
+// mirror that answers. The remote's tip, when it can still be listed, is the one every mirror
+// has to serve, so a stale or tampered mirror is skipped.
+fn fetch_branch(repo: &Repository, pack: &Pack, branch_name: &str, fo: &mut git2::FetchOptions, progress_tx: &Sender<GitProgress>) -> Result<Option<UsedMirror>> {
+    let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch_name);
+    let mut expected = None;
+    let mut failures: Vec<(String, anyhow::Error)> = Vec::new();
+
+    for (index, url) in pack.remotes().into_iter().enumerate() {
+        let is_mirror = index > 0;
+        if is_mirror {
+            progress_tx.send(GitProgress::Update(format!("Trying mirror {}...", url), 0.0)).ok();
+        }
+        let attempt = (|| -> Result<()> {
+            let heads = list_remote_heads(&url)?;
+            let tip = *heads.get(branch_name).context(format!("Branch '{}' doesn't exist there.", branch_name))?;
+            match expected {
+                None if !is_mirror => expected = Some(tip),
+                Some(expected) if tip != expected => bail!("It is at {} but the pack's remote is at {}; the mirror may be out of date.", short_id(tip), short_id(expected)),
+                _ => {}
+            }
+
+            let stage = if is_mirror { "Fetching from mirror..." } else { "Fetching from remote..." };
+            progress_tx.send(GitProgress::Update(stage.to_string(), 0.0)).ok();
+            let mut remote = if is_mirror { repo.remote_anonymous(&url)? } else { repo.find_remote("origin").context("Failed to find remote 'origin'")? };
+            net::retry(|| Ok(remote.fetch(&[&refspec], Some(&mut *fo), None)?))?;
+            let fetched = repo.find_reference(&format!("refs/remotes/origin/{}", branch_name))?.target();
+            if fetched != Some(tip) {
+                bail!("The commit it sent doesn't match the branch tip it advertised.");
+            }
+            Ok(())
+        })();
+        match attempt {
+            Ok(()) => return Ok(is_mirror.then(|| UsedMirror { url, verified: expected.is_some() })),
+            Err(e) => failures.push((url, e)),
+        }
+    }
+
+    if failures.len() == 1 {
+        let (_, e) = failures.remove(0);
+        return Err(e.context(format!("Failed to fetch. Check network/proxy/branch name ('{}').", branch_name)));
+    }
+    // Offline is one clear message, not the same one for every mirror.
+    if failures.iter().all(|(_, e)| net::is_offline(e)) {
+        return Err(failures.remove(0).1);
+    }
+    let reasons: Vec<String> = failures.iter().map(|(url, e)| format!("  {}: {:#}", url, e)).collect();
+    bail!("Failed to fetch branch '{}' from the pack's remote or any of its mirrors:\n{}", branch_name, reasons.join("\n"));
+}
+
+fn short_id(oid: git2::Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
 // --- MODIFIED: Now accepts a branch_name parameter ---
-pub fn perform_git_operations_threaded(path: PathBuf, branch_name: String, progress_tx: Sender<GitProgress>) {
-    let result = (|| -> Result<String> {
+pub fn perform_git_operations_threaded(path: PathBuf, branch_name: String, options: UpdateOptions, progress_tx: Sender<GitProgress>) {
+    let result = (|| -> Result<UpdateReport> {
+        running::ensure_not_running(&path, options.side)?;
+
         let mut callbacks = git2::RemoteCallbacks::new();
         let tx = progress_tx.clone();
         callbacks.transfer_progress(move |stats| {
@@ -334,70 +611,184 @@ pub fn perform_git_operations_threaded(path: PathBuf, branch_name: String, progr
         });
 
         progress_tx.send(GitProgress::Update("Setting up remote...".to_string(), 0.0)).ok();
-        let mut fo = git2::FetchOptions::new();
+        let mut fo = net::git_fetch_options()?;
         fo.remote_callbacks(callbacks);
-        let mut proxy_opts = git2::ProxyOptions::new();
-        proxy_opts.auto();
-        fo.proxy_options(proxy_opts);
 
         let repo = match Repository::open(&path) {
             Ok(repo) => repo,
             Err(_) => Repository::init(&path)?,
         };
-        repo.remote_set_url("origin", GIT_REMOTE_URL).context("Failed to set remote URL")?;
-        let mut remote = repo.find_remote("origin").context("Failed to find remote 'origin'")?;
-
-        progress_tx.send(GitProgress::Update("Fetching from remote...".to_string(), 0.0)).ok();
-        let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch_name);
-        remote.fetch(&[&refspec], Some(&mut fo), None).context(format!("Failed to fetch. Check network/proxy/branch name ('{}').", branch_name))?;
+        // `origin` keeps pointing at the pack's own remote even when a mirror is used.
+        repo.remote_set_url("origin", &options.pack.remote).context("Failed to set remote URL")?;
+        let old_head = repo.head().ok().and_then(|h| h.target());
+
+        let mirror = match &options.bundle {
+            Some(bundle) => {
+                progress_tx.send(GitProgress::Update("Reading the bundle...".to_string(), 0.0)).ok();
+                bundle::fetch(&repo, bundle, &branch_name)?;
+                None
+            }
+            None => fetch_branch(&repo, &options.pack, &branch_name, &mut fo, &progress_tx)?,
+        };
 
         progress_tx.send(GitProgress::Update("Analyzing changes...".to_string(), 1.0)).ok();
         let remote_branch_ref_name = format!("refs/remotes/origin/{}", branch_name);
         let fetch_commit = repo.find_reference(&remote_branch_ref_name)?.peel_to_commit().context("Failed to find the latest commit")?;
-        let fetch_head: AnnotatedCommit = repo.find_annotated_commit(fetch_commit.id())?;
-        let (analysis, _) = repo.merge_analysis(&[&fetch_head])?;
-
-        if analysis.is_up_to_date() {
-            progress_tx.send(GitProgress::Update("Repository up-to-date. Verifying files...".to_string(), 1.0)).ok();
-            repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
-        } else if analysis.is_fast_forward() || repo.head().is_err() {
-            progress_tx.send(GitProgress::Update("Applying fast-forward update...".to_string(), 1.0)).ok();
-            let local_branch_ref_name = format!("refs/heads/{}", branch_name);
-            let mut local_branch_ref = match repo.find_reference(&local_branch_ref_name) {
-                Ok(r) => r,
-                Err(_) => repo.reference(&local_branch_ref_name, fetch_commit.id(), true, "Create local branch")?,
+        let metadata = PackMetadata::from_commit(&repo, &fetch_commit)?;
+        let mut optional = metadata.resolve_choices(&options.optional);
+        if options.choose_optional && !metadata.optional.is_empty() {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            progress_tx.send(GitProgress::ChooseOptional { groups: metadata.optional.clone(), choices: optional, reply: reply_tx }).ok();
+            optional = reply_rx.recv().ok().flatten().context("The update was cancelled.")?;
+        }
+        let excluded = metadata.excluded_paths(options.side, &optional)?;
+        if options.side == Side::Server {
+            ensure_world_untracked(&fetch_commit, &path)?;
+        }
+        if let Some(keep) = options.world_backups.filter(|_| changes_mods(&repo, old_head, &fetch_commit)) {
+            worlds::backup_all(&path, options.side, keep, &|message, ratio| {
+                progress_tx.send(GitProgress::Update(message, ratio)).ok();
+            })?;
+        }
+        let protected = protect::protected_paths(&options.protected)?;
+        let cleaned_dirs = if options.clean_managed_dirs { MANAGED_DIRS } else { &[] };
+        // Default configs land in `config/` and every world's `serverconfig/`, untracked but still the player's.
+        let mut written_dirs: Vec<String> = cleaned_dirs.iter().map(|dir| dir.to_string()).collect();
+        written_dirs.push("config".to_string());
+        written_dirs.extend(running::world_dirs(&path, options.side).iter().filter_map(|world| {
+            Some(world.join("serverconfig").strip_prefix(&path).ok()?.to_string_lossy().replace('\\', "/"))
+        }));
+        let guard = protect::Guard::backup(&repo, &path, &fetch_commit.tree()?, &protected, &written_dirs)?;
+        let forced_configs = metadata.forced_default_configs()?;
+        let applied = (|| -> Result<Vec<configs::ConfigChange>> {
+            check_out(&repo, &fetch_commit, &branch_name, &excluded, &progress_tx)?;
+            if options.clean_managed_dirs {
+                clean_managed_directories(&repo, &path, &progress_tx)?;
+            }
+            let mut config_changes = match options.side {
+                Side::Client => configs::apply_defaults(&repo, &path, old_head, options.config_mode, &progress_tx)?,
+                Side::Server => Vec::new(),
             };
-            local_branch_ref.set_target(fetch_commit.id(), "Fast-forward")?;
-            repo.set_head(&local_branch_ref_name)?;
-            repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
-        } else {
-            progress_tx.send(GitProgress::Update("Merging changes...".to_string(), 1.0)).ok();
-            let our_commit = repo.head()?.peel_to_commit()?;
-            let merge_base_oid = repo.merge_base(our_commit.id(), fetch_commit.id())?;
-            let merge_base_commit = repo.find_commit(merge_base_oid)?;
-            let mut index = repo.merge_trees(&merge_base_commit.tree()?, &our_commit.tree()?, &fetch_commit.tree()?, None)?;
-            if index.has_conflicts() {
-                bail!("Merge conflict detected! Please resolve manually.");
+            remove_excluded_files(&repo, &path, &excluded, &progress_tx)?;
+            // After the exclusions, so a side never gets defaults it doesn't ship, and before the
+            // restore, so forced defaults never replace a protected file.
+            config_changes.extend(configs::apply_neoforge_defaults(&repo, &path, old_head, options.config_mode, &forced_configs, options.side, &progress_tx)?);
+            Ok(config_changes)
+        })();
+        // The player's files go back even when the update failed halfway. That failure is the one
+        // reported, with a failed restore attached to it.
+        let restored = guard.restore();
+        let mut config_changes = match (applied, &restored) {
+            (Ok(changes), _) => changes,
+            (Err(e), Ok(_)) => return Err(e),
+            (Err(e), Err(restore_error)) => {
+                return Err(e.context(format!("Restoring the protected files also failed ({:#}); the originals are in {}", restore_error, protect::BACKUP_DIR)));
             }
-            let result_tree_id = index.write_tree_to(&repo)?;
-            let result_tree = repo.find_tree(result_tree_id)?;
-            let signature = git2::Signature::now("Modpack Updater", "updater@example.com")?;
-            repo.commit(Some("HEAD"), &signature, &signature, &format!("Merge remote-tracking branch 'origin/{}'", branch_name), &result_tree, &[&our_commit, &fetch_commit])?;
-            repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
-        }
-
-        clean_managed_directories(&repo, &progress_tx)?;
-        force_copy_default_configs(&path, &progress_tx)?;
-
-        // Download LFS files
-        let rt = Runtime::new()?;
-        rt.block_on(download_lfs_files_async(&path, &branch_name, &progress_tx))?;
+        };
+        let protected_files = restored?;
+        config_changes.retain(|change| !protected_files.contains(&change.path));
+
+        // Download LFS files, or copy them from the bundle
+        let lfs_mirror = match &options.bundle {
+            Some(bundle) => {
+                bundle::restore_lfs_files(&repo, &path, bundle, &progress_tx)?;
+                None
+            }
+            None => Runtime::new()?.block_on(download_lfs_files_async(&repo, &path, &options.pack.lfs_urls(), &progress_tx))?,
+        };
 
-        Ok(format!("Successfully updated and verified repository at:\n\n{}\n\nPress Enter to close.", path.display()))
+        // Only a fresh install has nothing to compare against; a failure here shouldn't fail the update.
+        let changelog = match (old_head, repo.head().ok().and_then(|h| h.target())) {
+            (Some(old), Some(new)) => PackChangelog::between(&repo, Some(old), new).ok(),
+            _ => None,
+        };
+        let mut sources = String::new();
+        if let Some(mirror) = &mirror {
+            let check = if mirror.verified { "matched the pack's remote" } else { "couldn't be checked against the pack's remote" };
+            sources.push_str(&format!("\n\nDownloaded from the mirror {} (its commit {}).", mirror.url, check));
+        }
+        if let Some(lfs_mirror) = &lfs_mirror {
+            sources.push_str(&format!("\n\nLFS files came from the mirror {}.", lfs_mirror));
+        }
+        if let Some(bundle) = &options.bundle {
+            sources.push_str(&format!("\n\nInstalled from the bundle {}.", bundle.display()));
+            if options.added_pack {
+                sources.push_str(&format!(" The pack {} was added to packs.toml, so the instance updates from {} once it's online.", options.pack.name, options.pack.remote));
+            }
+        }
+        let message = format!("Successfully updated and verified {} {} instance at:\n\n{}{}\n\nPress Enter to close.", options.pack.name, options.side, path.display(), sources);
+        progress_tx.send(GitProgress::Update("Checking mods...".to_string(), 1.0)).ok();
+        let mod_problems = mods::health::check_instance(&path);
+        Ok(UpdateReport { message, changelog, mod_problems, config_changes, protected_files })
     })();
 
     match result {
-        Ok(msg) => progress_tx.send(GitProgress::Success(msg)).ok(),
+        Ok(report) => progress_tx.send(GitProgress::Success(Box::new(report))).ok(),
         Err(e) => progress_tx.send(GitProgress::Failure(format!("An error occurred:\n\n{:#}", e))).ok(),
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self_update::TempDir;
+
+    const METADATA: &str = r#"
+[[optional]]
+id = "extras"
+name = "Extras"
+default = false
+paths = ["config/extra/**"]
+"#;
+
+    // Commits `files` to a pack repository and fetches it into an empty instance, the way a fresh
+    // install starts. Returns the instance and the fetched commit's id.
+    fn fetch_pack(root: &Path, files: &[(&str, &str)]) -> (Repository, git2::Oid) {
+        let pack_path = root.join("pack");
+        let pack = Repository::init(&pack_path).unwrap();
+        for (file, content) in files {
+            let path = pack_path.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut index = pack.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        let tree = pack.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Pack", "pack@example.com").unwrap();
+        let commit = pack.commit(Some("refs/heads/main"), &signature, &signature, "Pack", &tree, &[]).unwrap();
+
+        let instance = Repository::init(root.join("instance")).unwrap();
+        instance.remote_anonymous(pack_path.to_str().unwrap()).unwrap()
+            .fetch(&["refs/heads/main:refs/remotes/origin/main"], None, None).unwrap();
+        (instance, commit)
+    }
+
+    #[test]
+    fn fresh_install_with_exclusions_has_no_local_changes() {
+        let root = TempDir::new().unwrap();
+        let (repo, commit) = fetch_pack(root.path(), &[
+            (crate::pack::METADATA_FILE, METADATA),
+            ("config/a.toml", "a = 1"),
+            ("config/extra/e.toml", "e = 1"),
+            ("mods/alpha-1.0.jar", "alpha"),
+            ("mods/sodium-0.5.jar", "sodium"),
+        ]);
+        let commit = repo.find_commit(commit).unwrap();
+        let optional = OptionalChoices::new();
+        let excluded = PackMetadata::from_commit(&repo, &commit).unwrap().excluded_paths(Side::Server, &optional).unwrap();
+        let (tx, _rx) = mpsc::channel();
+        let instance = repo.workdir().unwrap().to_path_buf();
+
+        check_out(&repo, &commit, "main", &excluded, &tx).unwrap();
+        remove_excluded_files(&repo, &instance, &excluded, &tx).unwrap();
+
+        assert!(instance.join("mods/alpha-1.0.jar").is_file());
+        assert!(!instance.join("mods/sodium-0.5.jar").exists());
+        assert!(!instance.join("config/extra/e.toml").exists());
+        let protected = protect::protected_paths(&[]).unwrap();
+        assert_eq!(count_local_changes(&repo, Side::Server, &optional, &protected), 0);
+
+        fs::write(instance.join("config/a.toml"), "a = 2").unwrap();
+        assert_eq!(count_local_changes(&repo, Side::Server, &optional, &protected), 1);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::app::history::{self, HistoryEntry};
+use crate::app::packs::{self, Pack};
+use crate::app::settings;
+use crate::app::GitProgress;
+use crate::bulk::{self, BulkOutcome, BulkProgress, BulkResult};
+use crate::bundle;
+use crate::cli::Args;
+use crate::git::{self, UpdateOptions};
+use crate::net;
+use crate::pack::Side;
+use crate::protect;
+use crate::running;
+use std::cell::Cell;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_INSTANCE_RUNNING: i32 = 3;
+pub const EXIT_OFFLINE: i32 = 4;
+
+/// Runs a single update without the TUI, printing progress to stdout. Returns the process exit code.
+pub fn run(args: &Args) -> i32 {
+    match settings::load() {
+        Ok((_, Some(warning))) => eprintln!("Warning: {:#}", warning),
+        Ok(_) => {}
+        Err(e) => eprintln!("Warning: {:#}. Using the default settings.", e),
+    }
+    if args.all {
+        return run_all(args);
+    }
+    let Some(path) = args.instance.clone() else {
+        eprintln!("--headless requires --instance <PATH>");
+        return EXIT_USAGE;
+    };
+    if !path.is_dir() {
+        eprintln!("Error: '{}' is not a directory.", path.display());
+        return EXIT_USAGE;
+    }
+    if let Some(dest) = &args.create_bundle {
+        return create_bundle(args, &path, dest);
+    }
+
+    let side = args.side.unwrap_or_else(|| Side::detect(&path));
+    if let Some(in_use) = running::find_running(&path, side) {
+        eprintln!("{}", in_use);
+        return EXIT_INSTANCE_RUNNING;
+    }
+
+    let mut history = load_history();
+    let manifest = match args.apply_bundle.as_deref().map(bundle::read_manifest).transpose() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return EXIT_USAGE;
+        }
+    };
+    // A bundle installs the pack it was made from unless --pack says otherwise.
+    let pack = match &manifest {
+        Some(manifest) if args.pack.is_none() => manifest.register_pack(),
+        _ => resolve_pack(args, &history, &path).map(|pack| (pack, false)),
+    };
+    let (pack, added_pack) = match pack {
+        Ok(pack) => pack,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    if manifest.is_none() {
+        if let Err(e) = net::check_connection(&pack.remotes()) {
+            eprintln!("Error: {}", e);
+            return EXIT_OFFLINE;
+        }
+    }
+
+    let branch = args.branch.clone().or_else(|| manifest.as_ref().map(|m| m.branch.clone())).unwrap_or_else(|| pack.default_branch.clone());
+    match &args.apply_bundle {
+        Some(bundle) => println!("Updating {} {} instance at {} (branch '{}') from the bundle {}", pack.name, side, path.display(), branch, bundle.display()),
+        None => println!("Updating {} {} instance at {} (branch '{}')", pack.name, side, path.display(), branch),
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let worker_path = path.clone();
+    let pack_name = pack.name.clone();
+    let branch_name = branch.clone();
+    // Headless runs keep the choices made in the TUI, or the pack's defaults.
+    let entry = history.iter().find(|e| e.path == path);
+    let optional = entry.map(|e| e.optional.clone()).unwrap_or_default();
+    let config_mode = entry.map(|e| e.config_mode).unwrap_or_default();
+    let protected = entry.map(|e| e.protected.clone()).unwrap_or_default();
+    let world_backups = entry.and_then(|e| e.backup_worlds.then_some(e.keep_world_backups));
+    let options = UpdateOptions { side, pack, optional, choose_optional: false, config_mode, protected, world_backups, clean_managed_dirs: settings::current().updates.clean_managed_dirs, bundle: args.apply_bundle.clone(), added_pack };
+    thread::spawn(move || {
+        git::perform_git_operations_threaded(worker_path, branch, options, tx);
+    });
+
+    let code = print_progress(rx);
+    if code == EXIT_OK {
+        history::record_success(&mut history, &path, &pack_name, &branch_name, git::head_commit(&path));
+    } else {
+        history::record_failure(&mut history, &path, "Headless update failed");
+    }
+    history::save(&history).ok();
+    code
+}
+
+/// Saves an offline bundle of the instance's installed pack version.
+fn create_bundle(args: &Args, path: &Path, dest: &Path) -> i32 {
+    let history = load_history();
+    let pack = match resolve_pack(args, &history, path) {
+        Ok(pack) => pack,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return EXIT_USAGE;
+        }
+    };
+    println!("Saving a bundle of the {} instance at {} to {}", pack.name, path.display(), dest.display());
+
+    let last_decile = Cell::new(-1);
+    let progress = |message: String, ratio: f64| {
+        let decile = (ratio * 10.0) as i32;
+        if decile != last_decile.get() {
+            println!("[{:>3.0}%] {}", ratio * 100.0, message);
+            last_decile.set(decile);
+        }
+    };
+    match bundle::create(path, dest, &pack.name, &progress) {
+        Ok(manifest) => {
+            println!("Saved {} ({} at {}, {} LFS files).", dest.display(), manifest.branch, manifest.commit, manifest.lfs_objects);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Updates every instance in the history and prints a summary table. One failing
+/// instance does not stop the others; the exit code reports whether any failed.
+fn run_all(args: &Args) -> i32 {
+    let mut history = load_history();
+    let registered = match packs::load() {
+        Ok(registered) => registered,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let (jobs, skipped) = bulk::plan(&history, &registered, args.side);
+    if jobs.is_empty() {
+        println!("No instances to update.");
+        print_summary(&skipped);
+        return EXIT_OK;
+    }
+
+    let mut remotes: Vec<String> = jobs.iter().flat_map(|job| job.options.pack.remotes()).collect();
+    remotes.sort();
+    remotes.dedup();
+    if let Err(e) = net::check_connection(&remotes) {
+        eprintln!("Error: {}", e);
+        return EXIT_OFFLINE;
+    }
+
+    let jobs_total = jobs.len();
+    let parallel = args.jobs.unwrap_or(settings::current().updates.concurrency);
+    println!("Updating {} instance(s), {} at a time", jobs_total, parallel.min(jobs_total));
+    let names: Vec<String> = jobs.iter().map(|job| job.name.clone()).collect();
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || bulk::run_threaded(jobs, parallel, tx));
+
+    let mut results: Vec<Option<BulkResult>> = vec![None; jobs_total];
+    let mut last_stage = vec![String::new(); jobs_total];
+    for progress in rx {
+        match progress {
+            BulkProgress::Update { index, message, ratio } => {
+                let stage = message.split(':').next().unwrap_or_default().to_string();
+                if stage != last_stage[index] {
+                    println!("[{}] [{:>3.0}%] {}", names[index], ratio * 100.0, message);
+                    last_stage[index] = stage;
+                }
+            }
+            BulkProgress::Finished { index, result } => {
+                println!("[{}] {}", names[index], bulk::outcome_label(&result.outcome));
+                match &result.outcome {
+                    BulkOutcome::Updated { commit } => history::record_success(
+                        &mut history,
+                        &result.path,
+                        result.pack.as_deref().unwrap_or_default(),
+                        result.branch.as_deref().unwrap_or_default(),
+                        commit.clone(),
+                    ),
+                    BulkOutcome::Failed(message) => history::record_failure(&mut history, &result.path, message),
+                    BulkOutcome::Skipped(_) => {}
+                }
+                results[index] = Some(result);
+            }
+            BulkProgress::AllDone => break,
+        }
+    }
+    history::save(&history).ok();
+
+    let mut all_results: Vec<BulkResult> = results.into_iter().flatten().collect();
+    let unfinished = all_results.len() < jobs_total;
+    all_results.extend(skipped);
+    print_summary(&all_results);
+
+    let failed = all_results.iter().any(|r| matches!(r.outcome, BulkOutcome::Failed(_)));
+    if failed || unfinished {
+        EXIT_FAILURE
+    } else {
+        EXIT_OK
+    }
+}
+
+fn print_summary(results: &[BulkResult]) {
+    if results.is_empty() {
+        return;
+    }
+    let width = results.iter().map(|r| r.name.chars().count()).max().unwrap_or(0).max("Instance".len());
+    println!("\n{:<width$}  Result", "Instance", width = width);
+    for result in results {
+        println!("{:<width$}  {}", result.name, bulk::outcome_label(&result.outcome), width = width);
+    }
+}
+
+// An unreadable history has already been moved aside by `load`, so the run goes on without it.
+fn load_history() -> Vec<HistoryEntry> {
+    history::load().unwrap_or_else(|e| {
+        eprintln!("Warning: {:#}", e);
+        Vec::new()
+    })
+}
+
+// --pack wins, then the pack the instance was last updated from, then the first registered pack.
+fn resolve_pack(args: &Args, history: &[HistoryEntry], path: &Path) -> anyhow::Result<Pack> {
+    let registered = packs::load()?;
+    let remembered = history.iter().find(|e| e.path == path).and_then(|e| e.pack.clone());
+    match args.pack.as_deref().or(remembered.as_deref()) {
+        Some(name) => packs::find(&registered, name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No pack named '{}' in '{}'.", name, packs::get_packs_path().map(|p| p.display().to_string()).unwrap_or_default())),
+        None => Ok(registered.into_iter().next().unwrap_or_default()),
+    }
+}
+
+// Prints each new stage once, and repeated stages (like download counters) every 10%.
+fn print_progress(rx: mpsc::Receiver<GitProgress>) -> i32 {
+    let mut last_stage = String::new();
+    let mut last_decile = -1;
+    for progress in rx {
+        match progress {
+            GitProgress::Update(message, ratio) => {
+                let stage = message.split(':').next().unwrap_or_default().to_string();
+                let decile = (ratio * 10.0) as i32;
+                if stage != last_stage || decile != last_decile {
+                    println!("[{:>3.0}%] {}", ratio * 100.0, message);
+                    last_stage = stage;
+                    last_decile = decile;
+                }
+            }
+            GitProgress::Success(report) => {
+                println!("{}", report.message.trim_end_matches("Press Enter to close.").trim_end());
+                if let Some(changelog) = report.changelog.filter(|c| !c.commits.is_empty()) {
+                    println!("\nChanges: {}", changelog.summary());
+                    for line in changelog.mod_lines() {
+                        println!("  {}", line);
+                    }
+                    for commit in &changelog.commits {
+                        println!("  {}", commit);
+                    }
+                }
+                if !report.config_changes.is_empty() {
+                    println!("\nConfigs reset to the pack's new defaults:");
+                    for change in &report.config_changes {
+                        println!("  {}", change);
+                    }
+                }
+                if !report.protected_files.is_empty() {
+                    println!("\nKept these protected files as they were (the originals are also in {}):", protect::BACKUP_DIR);
+                    for file in &report.protected_files {
+                        println!("  {}", file);
+                    }
+                }
+                if !report.mod_problems.is_empty() {
+                    println!("\nWarning: {} problem(s) with the installed mods:", report.mod_problems.len());
+                    for problem in &report.mod_problems {
+                        println!("  {}", problem);
+                    }
+                }
+                return EXIT_OK;
+            }
+            GitProgress::ChooseOptional { choices, reply, .. } => {
+                reply.send(Some(choices)).ok();
+            }
+            GitProgress::Failure(message) => {
+                eprintln!("{}", message);
+                return EXIT_FAILURE;
+            }
+        }
+    }
+    eprintln!("The update worker stopped unexpectedly.");
+    EXIT_FAILURE
+}
@@ -1,12 +1,24 @@
 mod app;
+mod bulk;
+mod bundle;
 mod changelog;
+mod cli;
+mod configs;
 mod event;
 mod git;
+mod headless;
+mod markdown;
+mod mods;
 mod music;
+mod net;
+mod pack;
+mod protect;
+mod running;
 mod ui;
 mod update;
+mod worlds;
 
-use crate::app::App;
+use crate::app::{App, AppState};
 use anyhow::Result;
 use crossterm::execute;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
@@ -14,25 +26,104 @@ use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::env;
 use std::io;
+use std::path::Path;
 use std::process::Command;
 use std::sync::mpsc;
 use std::thread;
 
 fn main() -> Result<()> {
-    let (update_tx, update_rx) = mpsc::channel();
-    thread::spawn(move || {
-        update::check_for_updates_background(update_tx);
+    let args = match cli::Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if args.rollback {
+        match update::rollback::rollback() {
+            Ok(restored) => println!("Restored {}.", restored),
+            Err(e) => {
+                eprintln!("Rollback failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if args.headless {
+        std::process::exit(headless::run(&args));
+    }
+
+    if let Some(failed) = update::rollback::record_launch() {
+        if offer_rollback(&failed)? {
+            return Ok(());
+        }
+    }
+
+    let (settings, settings_error) = match app::settings::load() {
+        Ok((settings, warning)) => {
+            let warning = warning.map(|e| format!("{:#}", e));
+            if let Some(warning) = &warning {
+                println!("Warning: {}", warning);
+            }
+            (settings, warning)
+        }
+        Err(e) => {
+            println!("Warning: {:#}. Using the default settings.", e);
+            (app::settings::Settings::default(), Some(format!("{:#}. The defaults are in use; changing a setting rewrites the file.", e)))
+        }
+    };
+    let update_channel = settings.updates.channel;
+    let update_rx = settings.self_update.is_due().then(|| {
+        let (update_tx, update_rx) = mpsc::channel();
+        let config = settings.self_update.clone();
+        thread::spawn(move || {
+            update::check_for_updates_background(config, update_channel, update_tx);
+        });
+        update_rx
     });
 
     // 1. Setup
-    let mut music_player = music::MusicPlayer::new()?;
-    music_player.play();
-    let history = app::history::load().unwrap_or_else(|_| {
-        println!("Warning: Could not load history file.");
-        Vec::new()
+    let mut music_player = music::MusicPlayer::new(&settings.audio)?;
+    if settings.audio.music {
+        music_player.play();
+    } else {
+        music_player.is_paused = true;
+    }
+    let (history, history_error) = match app::history::load() {
+        Ok(history) => (history, None),
+        Err(e) => (Vec::new(), Some(format!("{:#}", e))),
+    };
+    let packs = app::packs::load().unwrap_or_else(|e| {
+        println!("Warning: Could not load packs file: {:#}", e);
+        vec![app::packs::Pack::default()]
     });
-    let mut app = App::new(history)?;
-    app.update_rx = Some(update_rx);
+    let mut app = App::new(history, packs)?;
+    app.update_rx = update_rx;
+    app.settings = settings;
+    app.settings_error = settings_error;
+    app.side_override = args.side;
+    if let Some(index) = args.select.as_ref().and_then(|path| app.history.iter().position(|e| &e.path == path)) {
+        app.history_state.select(Some(index));
+    }
+    app.refresh_instance_statuses();
+
+    let current_version = env!("CARGO_PKG_VERSION");
+    if let Some(previous) = app::history::record_running_version(current_version) {
+        if self_update::version::bump_is_greater(&previous, current_version).unwrap_or(false) {
+            app.show_whats_new(current_version.to_string());
+        }
+    }
+    if let Some(error) = history_error {
+        app.state = AppState::Finished(format!("Your instance history could not be loaded.\n\n{}", error));
+    }
+
+    // Taken before a self-update can replace the file; on Linux the running path points at the
+    // replaced binary afterwards.
+    let exe = env::current_exe()?;
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -49,22 +140,17 @@ fn main() -> Result<()> {
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
-    if app.should_perform_update {
-        println!("Starting update...");
-        match update::perform_update() {
-            Ok(_) => {
-                println!("Update successful! Relaunching...");
-                if let Ok(updated_exe_path) = env::current_exe() {
-                    Command::new(updated_exe_path).spawn()?;
-                }
-            }
-            Err(e) => {
-                eprintln!("Update failed: {}", e);
-                println!("Press Enter to close.");
-                let _ = io::stdin().read_line(&mut String::new());
-            }
+    if app.relaunch {
+        let mut relaunch_args = Vec::new();
+        if let Some(entry) = app.history_state.selected().and_then(|i| app.history.get(i)) {
+            relaunch_args.extend(["--select".to_string(), entry.path.display().to_string()]);
         }
-        return Ok(());
+        match args.side {
+            Some(pack::Side::Server) => relaunch_args.push("--server".to_string()),
+            Some(pack::Side::Client) => relaunch_args.push("--client".to_string()),
+            None => {}
+        }
+        return relaunch(&exe, &relaunch_args);
     }
 
     // 4. Handle normal exit conditions
@@ -75,4 +161,46 @@ fn main() -> Result<()> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+// Asks on the console whether to go back to the version a broken self-update replaced, and
+// relaunches it if so. Returns whether this process should exit.
+fn offer_rollback(failed: &update::rollback::FailedLaunch) -> Result<bool> {
+    println!(
+        "Version {} did not finish starting last time. Restore the previous version ({})? [y/N]",
+        failed.version, failed.previous
+    );
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return Ok(false);
+    }
+
+    // Taken before the swap; on Linux the running path points at the replaced file afterwards.
+    let exe = env::current_exe()?;
+    match update::rollback::rollback() {
+        Ok(restored) => {
+            println!("Restored {}. Relaunching...", restored);
+            relaunch(&exe, &[])?;
+            Ok(true)
+        }
+        Err(e) => {
+            eprintln!("Rollback failed: {:#}", e);
+            Ok(false)
+        }
+    }
+}
+
+// Starts `exe` in place of this process, so the new version keeps the terminal to itself.
+fn relaunch(exe: &Path, args: &[String]) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let error = Command::new(exe).args(args).exec();
+        Err(anyhow::Error::new(error).context(format!("Failed to start '{}'.", exe.display())))
+    }
+    #[cfg(not(unix))]
+    {
+        let status = Command::new(exe).args(args).status()?;
+        std::process::exit(status.code().unwrap_or(0));
+    }
+}
@@ -0,0 +1,367 @@
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+
+/// A heading in a rendered document, pointing at the line it starts on.
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub level: u8,
+    pub title: String,
+    pub line: usize,
+}
+
+/// Markdown turned into styled lines. `indents` holds how far wrapped continuations
+/// of each line are indented so list items and quotes stay aligned.
+#[derive(Debug, Default, Clone)]
+pub struct Document {
+    pub lines: Vec<Line<'static>>,
+    pub indents: Vec<usize>,
+    pub headings: Vec<Heading>,
+}
+
+impl Document {
+    /// The plain text of a line, for searching.
+    pub fn line_text(&self, index: usize) -> String {
+        self.lines[index].spans.iter().map(|span| span.content.as_ref()).collect()
+    }
+}
+
+#[derive(Default)]
+struct Renderer {
+    doc: Document,
+    current: Vec<Span<'static>>,
+    indent: usize,
+    styles: Vec<Style>,
+    lists: Vec<Option<u64>>,
+    bullet: Option<String>,
+    quote_depth: usize,
+    in_code_block: bool,
+    heading: Option<(u8, String)>,
+    link: Option<String>,
+    link_text: String,
+}
+
+impl Renderer {
+    fn style(&self) -> Style {
+        self.styles.iter().fold(Style::default(), |acc, style| acc.patch(*style))
+    }
+
+    // Starts a new line with the list, quote and bullet prefix if nothing has been written yet.
+    fn push(&mut self, text: &str, style: Style) {
+        if self.current.is_empty() {
+            let depth = self.lists.len();
+            let mut prefix = "│ ".repeat(self.quote_depth);
+            match self.bullet.take() {
+                Some(bullet) => {
+                    prefix.push_str(&"  ".repeat(depth.saturating_sub(1)));
+                    prefix.push_str(&bullet);
+                }
+                None => prefix.push_str(&"  ".repeat(depth)),
+            }
+            self.indent = prefix.chars().count();
+            if !prefix.is_empty() {
+                self.current.push(Span::styled(prefix, Style::default().fg(Color::DarkGray)));
+            }
+        }
+        if !text.is_empty() {
+            self.current.push(Span::styled(text.to_string(), style));
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.current.is_empty() {
+            self.doc.lines.push(Line::from(std::mem::take(&mut self.current)));
+            self.doc.indents.push(self.indent);
+        }
+    }
+
+    // Ends the current block, leaving a single empty line between blocks.
+    fn blank(&mut self) {
+        self.flush();
+        if self.doc.lines.last().is_some_and(|line| line.width() > 0) {
+            self.doc.lines.push(Line::default());
+            self.doc.indents.push(0);
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some((_, title)) = &mut self.heading {
+            title.push_str(text);
+        }
+        if self.link.is_some() {
+            self.link_text.push_str(text);
+        }
+
+        if self.in_code_block {
+            let style = code_style();
+            for line in text.lines() {
+                self.push(&format!("    {}", line), style);
+                self.flush();
+            }
+        } else {
+            let style = self.style();
+            self.push(text, style);
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.blank();
+                let level = heading_level(level);
+                self.styles.push(heading_style(level));
+                self.heading = Some((level, String::new()));
+            }
+            Tag::BlockQuote(_) => {
+                self.blank();
+                self.quote_depth += 1;
+                self.styles.push(Style::default().add_modifier(Modifier::ITALIC));
+            }
+            Tag::CodeBlock(_) => {
+                self.blank();
+                self.in_code_block = true;
+            }
+            Tag::List(start) => {
+                if self.lists.is_empty() {
+                    self.blank();
+                } else {
+                    self.flush();
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.bullet = Some(bullet);
+            }
+            Tag::Emphasis => self.styles.push(Style::default().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.styles.push(Style::default().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self.styles.push(Style::default().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link { dest_url, .. } => {
+                self.styles.push(Style::default().fg(Color::LightBlue).add_modifier(Modifier::UNDERLINED));
+                self.link = Some(dest_url.to_string());
+                self.link_text.clear();
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                if let Some((level, title)) = self.heading.take() {
+                    self.doc.headings.push(Heading { level, title: title.trim().to_string(), line: self.doc.lines.len() });
+                }
+                self.blank();
+            }
+            TagEnd::Paragraph => {
+                if self.lists.is_empty() {
+                    self.blank();
+                } else {
+                    self.flush();
+                }
+            }
+            TagEnd::BlockQuote(_) => {
+                self.styles.pop();
+                self.flush();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.blank();
+            }
+            TagEnd::CodeBlock => {
+                self.in_code_block = false;
+                self.blank();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::Item => self.flush(),
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                // Bare links already show their target.
+                if let Some(url) = self.link.take() {
+                    if url != self.link_text && !url.starts_with('#') {
+                        self.push(&format!(" ({})", url), Style::default().fg(Color::DarkGray));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+fn heading_style(level: u8) -> Style {
+    match level {
+        1 => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        2 => Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        _ => Style::default().add_modifier(Modifier::BOLD),
+    }
+}
+
+fn code_style() -> Style {
+    Style::default().fg(Color::LightYellow)
+}
+
+/// Renders Markdown into styled terminal lines.
+pub fn render(markdown: &str) -> Document {
+    let mut renderer = Renderer::default();
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(tag) => renderer.start(tag),
+            Event::End(tag) => renderer.end(tag),
+            Event::Text(text) => renderer.text(&text),
+            Event::Code(code) => {
+                if let Some((_, title)) = &mut renderer.heading {
+                    title.push_str(&code);
+                }
+                renderer.push(&code, code_style());
+            }
+            Event::SoftBreak => renderer.text(" "),
+            Event::HardBreak => renderer.flush(),
+            Event::Rule => {
+                renderer.blank();
+                renderer.push(&"─".repeat(40), Style::default().fg(Color::DarkGray));
+                renderer.blank();
+            }
+            Event::TaskListMarker(done) => renderer.push(if done { "[x] " } else { "[ ] " }, Style::default()),
+            _ => {}
+        }
+    }
+    renderer.flush();
+
+    let mut doc = renderer.doc;
+    while doc.lines.last().is_some_and(|line| line.width() == 0) {
+        doc.lines.pop();
+        doc.indents.pop();
+    }
+    doc
+}
+
+/// Wraps the document to `width` columns. Returns the rows and, for every
+/// document line, the index of its first row.
+pub fn wrap(doc: &Document, width: usize) -> (Vec<Line<'static>>, Vec<usize>) {
+    let width = width.max(10);
+    let mut rows = Vec::new();
+    let mut first_rows = Vec::with_capacity(doc.lines.len());
+
+    for (line, indent) in doc.lines.iter().zip(&doc.indents) {
+        first_rows.push(rows.len());
+        if line.width() <= width {
+            rows.push(line.clone());
+            continue;
+        }
+
+        let indent = (*indent).min(width / 2);
+        let mut row: Vec<Span<'static>> = Vec::new();
+        let mut row_width = 0;
+        for span in &line.spans {
+            for word in span.content.split_inclusive(' ') {
+                let mut word = word.to_string();
+                loop {
+                    let word_width = Span::raw(word.as_str()).width();
+                    if row_width + word_width <= width {
+                        row_width += word_width;
+                        row.push(Span::styled(word, span.style));
+                        break;
+                    }
+                    if row_width > indent {
+                        rows.push(Line::from(std::mem::take(&mut row)));
+                        row.push(Span::raw(" ".repeat(indent)));
+                        row_width = indent;
+                        word = word.trim_start().to_string();
+                        continue;
+                    }
+                    // A single word longer than the row is split where it runs out of room.
+                    let room = width - row_width;
+                    let split = word.char_indices().nth(room.max(1)).map_or(word.len(), |(i, _)| i);
+                    let rest = word.split_off(split);
+                    row.push(Span::styled(word, span.style));
+                    rows.push(Line::from(std::mem::take(&mut row)));
+                    row.push(Span::raw(" ".repeat(indent)));
+                    row_width = indent;
+                    word = rest;
+                    if word.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+        if row_width > indent || rows.len() == first_rows[first_rows.len() - 1] {
+            rows.push(Line::from(row));
+        }
+    }
+    (rows, first_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(doc: &Document) -> Vec<String> {
+        (0..doc.lines.len()).map(|i| doc.line_text(i)).collect()
+    }
+
+    #[test]
+    fn headings_point_at_their_lines() {
+        let doc = render("# Changelog\n\nIntro.\n\n## 1.2.0 `beta`\n\n- Fixed it\n");
+        assert_eq!(texts(&doc), vec!["Changelog", "", "Intro.", "", "1.2.0 beta", "", "• Fixed it"]);
+        let headings: Vec<(u8, &str, usize)> = doc.headings.iter().map(|h| (h.level, h.title.as_str(), h.line)).collect();
+        assert_eq!(headings, vec![(1, "Changelog", 0), (2, "1.2.0 beta", 4)]);
+        assert_eq!(doc.lines[0].spans[0].style, heading_style(1));
+    }
+
+    #[test]
+    fn lists_are_numbered_and_nested() {
+        let doc = render("3. three\n4. four\n   - inner\n   - [x] done\n");
+        assert_eq!(texts(&doc), vec!["3. three", "4. four", "  • inner", "  • [x] done"]);
+        assert_eq!(doc.indents, vec![3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn links_show_their_target_unless_it_is_already_the_text() {
+        let doc = render("See [the wiki](https://example.com/wiki), <https://example.com> and [below](#notes).");
+        assert_eq!(texts(&doc), vec!["See the wiki (https://example.com/wiki), https://example.com and below."]);
+    }
+
+    #[test]
+    fn code_blocks_and_quotes_keep_their_lines() {
+        let doc = render("> quoted\n> text\n\n```\nfirst\n  second\n```\n\n---\n");
+        assert_eq!(texts(&doc), vec!["│ quoted text", "", "    first", "      second", "", &"─".repeat(40)]);
+        assert_eq!(doc.lines[2].spans[0].style, code_style());
+        assert_eq!(doc.indents[0], 2);
+    }
+
+    #[test]
+    fn wrap_indents_continuations_and_maps_lines_to_rows() {
+        let doc = render("- one two three four\n- short\n\nabcdefghijklmnopqrstuvwxyz\n");
+        let (rows, first_rows) = wrap(&doc, 12);
+        let rows: Vec<String> = rows.iter().map(|row| row.spans.iter().map(|span| span.content.as_ref()).collect()).collect();
+        assert_eq!(rows, vec!["• one two ", "  three four", "• short", "", "abcdefghijkl", "mnopqrstuvwx", "yz"]);
+        assert_eq!(first_rows, vec![0, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,254 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+const NEOFORGE_MODS_TOML: &str = "META-INF/neoforge.mods.toml";
+const FORGE_MODS_TOML: &str = "META-INF/mods.toml";
+const FABRIC_MOD_JSON: &str = "fabric.mod.json";
+const MANIFEST: &str = "META-INF/MANIFEST.MF";
+/// Where Fabric and NeoForge/Forge (jar-in-jar) keep bundled libraries and mods.
+const NESTED_JAR_DIRS: [&str; 2] = ["META-INF/jars/", "META-INF/jarjar/"];
+
+pub mod health;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loader {
+    NeoForge,
+    Forge,
+    Fabric,
+}
+
+impl std::fmt::Display for Loader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Loader::NeoForge => write!(f, "NeoForge"),
+            Loader::Forge => write!(f, "Forge"),
+            Loader::Fabric => write!(f, "Fabric"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    Required,
+    Optional,
+    /// The mod refuses to load alongside this one.
+    Incompatible,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub mod_id: String,
+    pub kind: DependencyKind,
+    /// As declared by the mod, e.g. `[21.1,)` or `>=0.15`. Empty when any version will do.
+    pub version_range: String,
+}
+
+/// One mod declared by a jar. A jar can declare several.
+#[derive(Debug, Clone)]
+pub struct ModInfo {
+    pub mod_id: String,
+    pub name: String,
+    pub version: String,
+    pub loader: Loader,
+    /// The loader version range from `loaderVersion` or Fabric's `fabricloader` dependency.
+    pub loader_version: Option<String>,
+    pub dependencies: Vec<Dependency>,
+    /// Other mod ids this mod stands in for, from Fabric's `provides`.
+    pub provides: Vec<String>,
+}
+
+/// A jar in `mods/` and what could be read from it.
+#[derive(Debug, Clone)]
+pub struct ModJar {
+    pub path: PathBuf,
+    pub mods: Vec<ModInfo>,
+    /// Mods shipped inside the jar, which satisfy dependencies but aren't separate files.
+    pub bundled: Vec<ModInfo>,
+    /// Why the jar couldn't be read. Jars without any metadata have no mods and no error.
+    pub error: Option<String>,
+}
+
+impl ModJar {
+    pub fn file_name(&self) -> String {
+        self.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+}
+
+/// Reads the metadata of every jar in the instance's `mods/` folder, sorted by file name.
+pub fn scan_instance(instance_path: &Path) -> Vec<ModJar> {
+    let Ok(entries) = fs::read_dir(instance_path.join("mods")) else { return Vec::new() };
+    let mut jars: Vec<ModJar> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "jar"))
+        .map(|path| match File::open(&path).map_err(anyhow::Error::from).and_then(read_jar_with_bundled) {
+            Ok((mods, bundled)) => ModJar { path, mods, bundled, error: None },
+            Err(e) => ModJar { path, mods: Vec::new(), bundled: Vec::new(), error: Some(format!("{:#}", e)) },
+        })
+        .collect();
+    jars.sort_by_key(|jar| jar.file_name().to_lowercase());
+    jars
+}
+
+/// Reads mod metadata from jar bytes, e.g. a blob from the pack repository.
+pub fn read_jar_bytes(bytes: &[u8]) -> Result<Vec<ModInfo>> {
+    read_jar(Cursor::new(bytes))
+}
+
+// Also reads the jars nested one level deep. Unreadable nested jars are ignored.
+fn read_jar_with_bundled<R: Read + Seek>(reader: R) -> Result<(Vec<ModInfo>, Vec<ModInfo>)> {
+    let mut archive = ZipArchive::new(reader).context("Not a valid jar")?;
+    let mods = read_metadata(&mut archive)?;
+
+    let nested: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.ends_with(".jar") && NESTED_JAR_DIRS.iter().any(|dir| name.starts_with(dir)))
+        .map(str::to_string)
+        .collect();
+    let mut bundled = Vec::new();
+    for name in nested {
+        let mut bytes = Vec::new();
+        let Ok(mut entry) = archive.by_name(&name) else { continue };
+        if entry.read_to_end(&mut bytes).is_ok() {
+            bundled.extend(read_jar_bytes(&bytes).unwrap_or_default());
+        }
+    }
+    Ok((mods, bundled))
+}
+
+fn read_jar<R: Read + Seek>(reader: R) -> Result<Vec<ModInfo>> {
+    let mut archive = ZipArchive::new(reader).context("Not a valid jar")?;
+    read_metadata(&mut archive)
+}
+
+fn read_metadata<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<ModInfo>> {
+    let manifest_version = read_entry(archive, MANIFEST).and_then(|manifest| {
+        manifest.lines().find_map(|line| line.strip_prefix("Implementation-Version:").map(|v| v.trim().to_string()))
+    });
+
+    if let Some(content) = read_entry(archive, NEOFORGE_MODS_TOML) {
+        return parse_mods_toml(&content, Loader::NeoForge, manifest_version.as_deref()).context(NEOFORGE_MODS_TOML);
+    }
+    if let Some(content) = read_entry(archive, FORGE_MODS_TOML) {
+        return parse_mods_toml(&content, Loader::Forge, manifest_version.as_deref()).context(FORGE_MODS_TOML);
+    }
+    if let Some(content) = read_entry(archive, FABRIC_MOD_JSON) {
+        return parse_fabric_mod_json(&content).map(|info| vec![info]).context(FABRIC_MOD_JSON);
+    }
+    Ok(Vec::new())
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Option<String> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content).ok()?;
+    Some(content)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModsToml {
+    loader_version: Option<String>,
+    #[serde(default)]
+    mods: Vec<ModsTomlEntry>,
+    /// Keyed by the mod id the dependencies belong to.
+    #[serde(default)]
+    dependencies: HashMap<String, Vec<ModsTomlDependency>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModsTomlEntry {
+    mod_id: String,
+    version: Option<String>,
+    display_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModsTomlDependency {
+    mod_id: String,
+    /// NeoForge: `required`, `optional`, `incompatible` or `discouraged`.
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    /// Forge and older NeoForge.
+    mandatory: Option<bool>,
+    version_range: Option<String>,
+}
+
+fn parse_mods_toml(content: &str, loader: Loader, manifest_version: Option<&str>) -> Result<Vec<ModInfo>> {
+    let file: ModsToml = toml::from_str(content)?;
+    let mut dependencies = file.dependencies;
+
+    Ok(file.mods.into_iter().map(|entry| {
+        let version = match entry.version.as_deref() {
+            // Filled in from the manifest at build time by ModDevGradle and ForgeGradle.
+            Some("${file.jarVersion}") | None => manifest_version.unwrap_or_default().to_string(),
+            Some(version) => version.to_string(),
+        };
+        let dependencies = dependencies.remove(&entry.mod_id).unwrap_or_default().into_iter().map(|dep| {
+            let kind = match (dep.kind.as_deref().map(str::to_lowercase).as_deref(), dep.mandatory) {
+                (Some("required"), _) | (None, Some(true)) => DependencyKind::Required,
+                (Some("incompatible"), _) => DependencyKind::Incompatible,
+                _ => DependencyKind::Optional,
+            };
+            Dependency { mod_id: dep.mod_id, kind, version_range: dep.version_range.unwrap_or_default() }
+        }).collect();
+
+        ModInfo {
+            name: entry.display_name.unwrap_or_else(|| entry.mod_id.clone()),
+            mod_id: entry.mod_id,
+            version,
+            loader,
+            loader_version: file.loader_version.clone(),
+            dependencies,
+            provides: Vec::new(),
+        }
+    }).collect())
+}
+
+#[derive(Deserialize)]
+struct FabricModJson {
+    id: String,
+    version: Option<String>,
+    name: Option<String>,
+    #[serde(default)]
+    depends: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    recommends: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    breaks: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    provides: Vec<String>,
+}
+
+fn parse_fabric_mod_json(content: &str) -> Result<ModInfo> {
+    let file: FabricModJson = serde_json::from_str(content)?;
+    // Version predicates are a string or a list of alternatives.
+    let range = |value: &serde_json::Value| match value {
+        serde_json::Value::String(range) => range.clone(),
+        serde_json::Value::Array(ranges) => ranges.iter().filter_map(|r| r.as_str()).collect::<Vec<_>>().join(" || "),
+        _ => String::new(),
+    };
+
+    let mut dependencies = Vec::new();
+    for (deps, kind) in [(&file.depends, DependencyKind::Required), (&file.recommends, DependencyKind::Optional), (&file.breaks, DependencyKind::Incompatible)] {
+        dependencies.extend(deps.iter().map(|(mod_id, value)| Dependency { mod_id: mod_id.clone(), kind, version_range: range(value) }));
+    }
+    dependencies.sort_by(|a, b| a.mod_id.cmp(&b.mod_id));
+
+    Ok(ModInfo {
+        name: file.name.unwrap_or_else(|| file.id.clone()),
+        loader_version: file.depends.get("fabricloader").map(range),
+        mod_id: file.id,
+        version: file.version.unwrap_or_default(),
+        loader: Loader::Fabric,
+        dependencies,
+        provides: file.provides,
+    })
+}
@@ -0,0 +1,376 @@
+use super::{DependencyKind, Loader, ModJar};
+use git2::Repository;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+/// Dependencies provided by the game or the loader rather than a jar in `mods/`.
+const BUILT_IN: [&str; 7] = ["minecraft", "neoforge", "forge", "fabricloader", "fabric-loader", "java", "javafml"];
+
+/// The mod loader installed for an instance, as far as it can be told from the folder.
+#[derive(Debug, Clone)]
+pub struct InstalledLoader {
+    pub loader: Loader,
+    pub version: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum ModProblem {
+    /// Several jars declare the same mod id; the game refuses to start.
+    Duplicate { mod_id: String, jars: Vec<PathBuf> },
+    MissingDependency { mod_name: String, dependency: String, version_range: String },
+    /// Two installed mods declare each other incompatible.
+    Conflict { mod_name: String, other: String },
+    /// The jar is built for a different loader than the instance runs.
+    WrongLoader { mod_name: String, loader: Loader, installed: Loader },
+    /// The mod requires a loader version the instance doesn't have.
+    LoaderVersion { mod_name: String, loader: Loader, version_range: String, installed: String },
+}
+
+impl std::fmt::Display for ModProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModProblem::Duplicate { mod_id, jars } => {
+                let names: Vec<String> = jars.iter().map(|jar| file_name(jar)).collect();
+                write!(f, "'{}' is installed {} times: {}", mod_id, jars.len(), names.join(", "))
+            }
+            ModProblem::MissingDependency { mod_name, dependency, version_range } if version_range.is_empty() || version_range == "*" => {
+                write!(f, "{} requires '{}', which isn't installed", mod_name, dependency)
+            }
+            ModProblem::MissingDependency { mod_name, dependency, version_range } => {
+                write!(f, "{} requires '{}' {}, which isn't installed", mod_name, dependency, version_range)
+            }
+            ModProblem::Conflict { mod_name, other } => write!(f, "{} is incompatible with {}", mod_name, other),
+            ModProblem::WrongLoader { mod_name, loader, installed } => {
+                write!(f, "{} is a {} mod, but the instance runs {}", mod_name, loader, installed)
+            }
+            ModProblem::LoaderVersion { mod_name, loader, version_range, installed } => {
+                write!(f, "{} requires {} {}, but {} is installed", mod_name, loader, version_range, installed)
+            }
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// The mods of an instance together with what's wrong with them.
+pub struct Inspection {
+    pub path: PathBuf,
+    pub jars: Vec<ModJar>,
+    pub problems: Vec<ModProblem>,
+    /// Untracked duplicates that can be removed, see [`untracked_duplicates`].
+    pub removable: Vec<PathBuf>,
+}
+
+/// Scans the instance's mods and checks them against each other and the installed loader.
+pub fn check_instance(instance_path: &Path) -> Vec<ModProblem> {
+    check(&super::scan_instance(instance_path), detect_loader(instance_path).as_ref())
+}
+
+/// Scans and checks the instance in a background thread.
+pub fn inspect_threaded(instance_path: PathBuf, tx: Sender<Inspection>) {
+    let jars = super::scan_instance(&instance_path);
+    let problems = check(&jars, detect_loader(&instance_path).as_ref());
+    let removable = untracked_duplicates(&instance_path, &problems);
+    tx.send(Inspection { path: instance_path, jars, problems, removable }).ok();
+}
+
+pub fn check(jars: &[ModJar], installed: Option<&InstalledLoader>) -> Vec<ModProblem> {
+    let mut problems = Vec::new();
+
+    let mut by_id: HashMap<&str, Vec<PathBuf>> = HashMap::new();
+    for jar in jars {
+        for info in &jar.mods {
+            let paths = by_id.entry(info.mod_id.as_str()).or_default();
+            // A jar declaring the same id twice is the mod's problem, not a stray copy.
+            if !paths.contains(&jar.path) {
+                paths.push(jar.path.clone());
+            }
+        }
+    }
+    let mut duplicates: Vec<_> = by_id.iter().filter(|(_, paths)| paths.len() > 1).collect();
+    duplicates.sort_by_key(|(mod_id, _)| *mod_id);
+    problems.extend(duplicates.into_iter().map(|(mod_id, paths)| ModProblem::Duplicate { mod_id: mod_id.to_string(), jars: paths.clone() }));
+
+    let available: HashSet<&str> = jars
+        .iter()
+        .flat_map(|jar| jar.mods.iter().chain(&jar.bundled))
+        .flat_map(|info| std::iter::once(info.mod_id.as_str()).chain(info.provides.iter().map(String::as_str)))
+        .collect();
+    let names: HashMap<&str, &str> = jars.iter().flat_map(|jar| &jar.mods).map(|info| (info.mod_id.as_str(), info.name.as_str())).collect();
+
+    let mut conflicts = HashSet::new();
+    for info in jars.iter().flat_map(|jar| &jar.mods) {
+        if let Some(installed) = installed {
+            if !loader_compatible(info.loader, installed.loader) {
+                problems.push(ModProblem::WrongLoader { mod_name: info.name.clone(), loader: info.loader, installed: installed.loader });
+                continue;
+            }
+        }
+
+        for dependency in &info.dependencies {
+            let id = dependency.mod_id.as_str();
+            match dependency.kind {
+                DependencyKind::Required if is_loader_id(id) => {
+                    let Some(installed) = installed.filter(|installed| loader_compatible(info.loader, installed.loader)) else { continue };
+                    // A bare version in `mods.toml` is only a recommendation.
+                    let soft = info.loader != Loader::Fabric && !dependency.version_range.trim_start().starts_with(['[', '(']);
+                    if !soft && version_matches(&dependency.version_range, &installed.version) == Some(false) {
+                        problems.push(ModProblem::LoaderVersion {
+                            mod_name: info.name.clone(),
+                            loader: installed.loader,
+                            version_range: dependency.version_range.clone(),
+                            installed: installed.version.clone(),
+                        });
+                    }
+                }
+                DependencyKind::Required if !BUILT_IN.contains(&id) && !available.contains(id) => {
+                    problems.push(ModProblem::MissingDependency {
+                        mod_name: info.name.clone(),
+                        dependency: id.to_string(),
+                        version_range: dependency.version_range.clone(),
+                    });
+                }
+                // Both sides often declare the same conflict; report it once.
+                DependencyKind::Incompatible if available.contains(id) && id != info.mod_id => {
+                    let mut pair = [info.mod_id.clone(), id.to_string()];
+                    pair.sort();
+                    if conflicts.insert(pair) {
+                        let other = names.get(id).copied().unwrap_or(id);
+                        problems.push(ModProblem::Conflict { mod_name: info.name.clone(), other: other.to_string() });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    problems
+}
+
+fn is_loader_id(mod_id: &str) -> bool {
+    matches!(mod_id, "neoforge" | "forge" | "fabricloader" | "fabric-loader")
+}
+
+// NeoForge still loads Forge's `mods.toml` on 1.20.1, so only Fabric is told apart.
+fn loader_compatible(mod_loader: Loader, installed: Loader) -> bool {
+    (mod_loader == Loader::Fabric) == (installed == Loader::Fabric)
+}
+
+#[derive(Deserialize)]
+struct MmcPack {
+    #[serde(default)]
+    components: Vec<MmcComponent>,
+}
+
+#[derive(Deserialize)]
+struct MmcComponent {
+    uid: String,
+    version: Option<String>,
+}
+
+/// Finds the loader from a Prism/MultiMC `mmc-pack.json` next to or above the instance,
+/// or from the `libraries` folder of a server.
+pub fn detect_loader(instance_path: &Path) -> Option<InstalledLoader> {
+    let loader_for_uid = |uid: &str| match uid {
+        "net.neoforged" => Some(Loader::NeoForge),
+        "net.minecraftforge" => Some(Loader::Forge),
+        "net.fabricmc.fabric-loader" => Some(Loader::Fabric),
+        _ => None,
+    };
+    for dir in [Some(instance_path), instance_path.parent()].into_iter().flatten() {
+        let Ok(content) = fs::read_to_string(dir.join("mmc-pack.json")) else { continue };
+        let Ok(pack) = serde_json::from_str::<MmcPack>(&content) else { continue };
+        let found = pack.components.into_iter().find_map(|component| {
+            let loader = loader_for_uid(&component.uid)?;
+            Some(InstalledLoader { loader, version: component.version? })
+        });
+        if found.is_some() {
+            return found;
+        }
+    }
+
+    let libraries = instance_path.join("libraries");
+    for (dir, loader) in [("net/neoforged/neoforge", Loader::NeoForge), ("net/minecraftforge/forge", Loader::Forge), ("net/fabricmc/fabric-loader", Loader::Fabric)] {
+        let Ok(entries) = fs::read_dir(libraries.join(dir)) else { continue };
+        let newest = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .max_by(|a, b| compare_versions(a, b));
+        if let Some(version) = newest {
+            // Forge folders are named `<minecraft>-<forge>`.
+            let version = match loader {
+                Loader::Forge => version.split_once('-').map_or(version.clone(), |(_, forge)| forge.to_string()),
+                _ => version,
+            };
+            return Some(InstalledLoader { loader, version });
+        }
+    }
+    None
+}
+
+/// Jars in duplicate groups that the pack doesn't track, where at least one copy is tracked.
+/// These are the stray copies that are safe to remove.
+pub fn untracked_duplicates(instance_path: &Path, problems: &[ModProblem]) -> Vec<PathBuf> {
+    let Ok(repo) = Repository::open(instance_path) else { return Vec::new() };
+    let Ok(index) = repo.index() else { return Vec::new() };
+    let is_tracked = |jar: &Path| {
+        jar.strip_prefix(instance_path)
+            .ok()
+            .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+            .is_some_and(|relative| index.get_path(Path::new(&relative), 0).is_some())
+    };
+
+    let mut removable = Vec::new();
+    for problem in problems {
+        let ModProblem::Duplicate { jars, .. } = problem else { continue };
+        let (tracked, untracked): (Vec<&PathBuf>, Vec<&PathBuf>) = jars.iter().partition(|jar| is_tracked(jar));
+        if !tracked.is_empty() {
+            for jar in untracked {
+                if !removable.contains(jar) {
+                    removable.push(jar.clone());
+                }
+            }
+        }
+    }
+    removable
+}
+
+/// Deletes the given jars and drops the problems they caused. Returns the jars that couldn't be removed.
+pub fn remove_jars(jars: &[PathBuf], problems: &mut Vec<ModProblem>) -> Vec<(PathBuf, String)> {
+    let mut failed = Vec::new();
+    let mut removed = Vec::new();
+    for jar in jars {
+        match fs::remove_file(jar) {
+            Ok(()) => removed.push(jar.clone()),
+            Err(e) => failed.push((jar.clone(), e.to_string())),
+        }
+    }
+    for problem in problems.iter_mut() {
+        if let ModProblem::Duplicate { jars, .. } = problem {
+            jars.retain(|jar| !removed.contains(jar));
+        }
+    }
+    problems.retain(|problem| !matches!(problem, ModProblem::Duplicate { jars, .. } if jars.len() < 2));
+    failed
+}
+
+// Numeric parts compare as numbers, anything else as text. Build metadata after `+` is ignored.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<String> {
+        v.split('+').next().unwrap_or_default().split(['.', '-', '_']).filter(|p| !p.is_empty()).map(str::to_string).collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    for i in 0..a.len().max(b.len()) {
+        let ordering = match (a.get(i), b.get(i)) {
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            },
+            // `1.0` < `1.0.1`, but a pre-release suffix like `1.0-beta` sorts before `1.0`.
+            (Some(x), None) => if x.parse::<u64>().is_ok() { Ordering::Greater } else { Ordering::Less },
+            (None, Some(y)) => if y.parse::<u64>().is_ok() { Ordering::Less } else { Ordering::Greater },
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Whether `version` satisfies a Maven range (`[21.1,)`, `[1.0,2.0),[3.0,)`) or a Fabric
+/// predicate (`>=0.15 <0.17`, `^1.2 || ~1.3`, `1.20.x`). `None` when the range can't be read.
+pub fn version_matches(range: &str, version: &str) -> Option<bool> {
+    let range = range.trim();
+    if range.is_empty() || range == "*" {
+        return Some(true);
+    }
+    if range.starts_with('[') || range.starts_with('(') {
+        return maven_range_matches(range, version);
+    }
+    let mut any = None;
+    for alternative in range.split("||") {
+        let mut all = Some(true);
+        for predicate in alternative.split_whitespace() {
+            all = match (all, fabric_predicate_matches(predicate, version)) {
+                (Some(a), Some(b)) => Some(a && b),
+                _ => None,
+            };
+        }
+        match all {
+            Some(true) => return Some(true),
+            Some(false) => any = Some(false),
+            None => {}
+        }
+    }
+    any
+}
+
+fn maven_range_matches(range: &str, version: &str) -> Option<bool> {
+    let mut rest = range;
+    let mut matched = false;
+    while !rest.is_empty() {
+        let end = rest.find([']', ')'])?;
+        let (spec, tail) = rest.split_at(end + 1);
+        let (open, close) = (spec.chars().next()?, spec.chars().last()?);
+        let inner = &spec[1..spec.len() - 1];
+        let within = match inner.split_once(',') {
+            Some((low, high)) => {
+                let (low, high) = (low.trim(), high.trim());
+                let above = low.is_empty() || match compare_versions(version, low) {
+                    Ordering::Greater => true,
+                    Ordering::Equal => open == '[',
+                    Ordering::Less => false,
+                };
+                let below = high.is_empty() || match compare_versions(version, high) {
+                    Ordering::Less => true,
+                    Ordering::Equal => close == ']',
+                    Ordering::Greater => false,
+                };
+                above && below
+            }
+            None => compare_versions(version, inner.trim()) == Ordering::Equal,
+        };
+        matched |= within;
+        rest = tail.trim_start_matches([',', ' ']);
+    }
+    Some(matched)
+}
+
+fn fabric_predicate_matches(predicate: &str, version: &str) -> Option<bool> {
+    let (operator, target) = match predicate.find(|c: char| c.is_ascii_alphanumeric()) {
+        Some(i) => predicate.split_at(i),
+        None => return (predicate == "*").then_some(true),
+    };
+    let ordering = compare_versions(version, target.trim_end_matches(".x").trim_end_matches(".*"));
+    let numbers = |v: &str| -> Vec<u64> { v.split('.').map_while(|p| p.parse().ok()).collect() };
+    Some(match operator {
+        ">=" => ordering != Ordering::Less,
+        ">" => ordering == Ordering::Greater,
+        "<=" => ordering != Ordering::Greater,
+        "<" => ordering == Ordering::Less,
+        "=" | "" if target.ends_with(".x") || target.ends_with(".*") => {
+            let prefix = numbers(target);
+            numbers(version).starts_with(&prefix)
+        }
+        "=" | "" => ordering == Ordering::Equal,
+        // Same major version (or minor for 0.x), and at least the target.
+        "^" => {
+            let (v, t) = (numbers(version), numbers(target));
+            let same = if t.first() == Some(&0) { v.get(..2) == t.get(..2) } else { v.first() == t.first() };
+            same && ordering != Ordering::Less
+        }
+        // Same major and minor version, and at least the target.
+        "~" => {
+            let (v, t) = (numbers(version), numbers(target));
+            v.get(..2) == t.get(..2) && ordering != Ordering::Less
+        }
+        _ => return None,
+    })
+}
@@ -1,3 +1,4 @@
+use crate::app::settings::AudioSettings;
 use anyhow::Result;
 use lazy_static::lazy_static;
 use ratatui::style::{Color, Modifier, Style};
@@ -51,18 +52,20 @@ lazy_static! {
     };
 }
 
-enum MusicCommand { Play, TogglePause, Stop, Exit, PlaySecretTrack, PlaySfx, PlayScrollSfx, PlayConfirmSfx, PlayCancelSfx }
+enum MusicCommand { Play, TogglePause, Stop, Exit, PlaySecretTrack, PlaySfx, PlayScrollSfx, PlayConfirmSfx, PlayCancelSfx, SetVolume(f32) }
 
 pub struct MusicPlayer {
     command_tx: Sender<MusicCommand>,
     pub is_paused: bool,
+    pub sound_effects: bool,
     current_song_index: Arc<Mutex<usize>>,
     secret_mode_active: Arc<Mutex<bool>>,
 }
 
 impl MusicPlayer {
-    pub fn new() -> Result<Self> {
+    pub fn new(audio: &AudioSettings) -> Result<Self> {
         let (command_tx, command_rx) = mpsc::channel();
+        let volume = volume_level(audio.volume);
         
         let current_song_index = Arc::new(Mutex::new(0));
         let secret_mode_active = Arc::new(Mutex::new(false));
@@ -73,7 +76,7 @@ impl MusicPlayer {
         thread::spawn(move || {
             if let Ok((_stream, stream_handle)) = OutputStream::try_default() {
                 if let Ok(sink) = Sink::try_new(&stream_handle) {
-                    sink.set_volume(0.1);
+                    sink.set_volume(volume);
                     let mut is_playing = false;
 
                     loop {
@@ -118,6 +121,7 @@ impl MusicPlayer {
                                     else { sink.pause(); is_playing = false; }
                                 }
                                 MusicCommand::Stop => { is_playing = false; sink.stop(); }
+                                MusicCommand::SetVolume(volume) => sink.set_volume(volume),
                                 MusicCommand::Exit => break,
                             }
                         }
@@ -136,7 +140,7 @@ impl MusicPlayer {
             }
         });
 
-        Ok(Self { command_tx, is_paused: false, current_song_index, secret_mode_active })
+        Ok(Self { command_tx, is_paused: false, sound_effects: audio.sound_effects, current_song_index, secret_mode_active })
     }
 
     pub fn get_current_song_info(&self) -> (String, String, Style) {
@@ -151,10 +155,22 @@ impl MusicPlayer {
 
     pub fn play(&mut self) { self.is_paused = false; self.command_tx.send(MusicCommand::Play).ok(); }
     pub fn play_secret_track(&mut self) { self.is_paused = false; self.command_tx.send(MusicCommand::PlaySecretTrack).ok(); }
-    pub fn play_sfx(&self) { self.command_tx.send(MusicCommand::PlaySfx).ok(); }
-    pub fn play_scroll_sfx(&self) { self.command_tx.send(MusicCommand::PlayScrollSfx).ok(); }
-    pub fn play_confirm_sfx(&self) { self.command_tx.send(MusicCommand::PlayConfirmSfx).ok(); }
-    pub fn play_cancel_sfx(&self) { self.command_tx.send(MusicCommand::PlayCancelSfx).ok(); }
+    pub fn play_sfx(&self) { self.send_sfx(MusicCommand::PlaySfx); }
+    pub fn play_scroll_sfx(&self) { self.send_sfx(MusicCommand::PlayScrollSfx); }
+    pub fn play_confirm_sfx(&self) { self.send_sfx(MusicCommand::PlayConfirmSfx); }
+    pub fn play_cancel_sfx(&self) { self.send_sfx(MusicCommand::PlayCancelSfx); }
+    pub fn set_volume(&self, percent: u8) { self.command_tx.send(MusicCommand::SetVolume(volume_level(percent))).ok(); }
     pub fn toggle_pause(&mut self) { self.is_paused = !self.is_paused; self.command_tx.send(MusicCommand::TogglePause).ok(); }
     pub fn stop(&self) { self.command_tx.send(MusicCommand::Stop).ok(); self.command_tx.send(MusicCommand::Exit).ok(); }
-}
\ No newline at end of file
+
+    fn send_sfx(&self, command: MusicCommand) {
+        if self.sound_effects {
+            self.command_tx.send(command).ok();
+        }
+    }
+}
+
+// The settings store a percentage of the sink's full volume. Sound effects aren't affected.
+fn volume_level(percent: u8) -> f32 {
+    f32::from(percent.min(100)) / 100.0
+}
@@ -0,0 +1,278 @@
+use crate::app::settings::{self, NetworkSettings};
+use anyhow::{Context, Result};
+use git2::{FetchOptions, ProxyOptions, Remote};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The longest the connection check waits for a server, whatever the timeout setting.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a server that answered the connection check is assumed to still be reachable.
+const PROBE_CACHE: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref REACHABLE: Mutex<HashMap<(String, u16), Instant>> = Mutex::new(HashMap::new());
+}
+
+/// The server couldn't be reached at all, as opposed to it answering with an error.
+#[derive(Debug)]
+pub struct Offline {
+    pub host: String,
+}
+
+impl fmt::Display for Offline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "You seem to be offline: {} can't be reached. Check your connection, or the proxy in the settings.", self.host)
+    }
+}
+
+impl std::error::Error for Offline {}
+
+pub fn is_offline(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Offline>().is_some()
+}
+
+fn timeout(network: &NetworkSettings) -> Duration {
+    Duration::from_secs(network.timeout)
+}
+
+fn reqwest_proxy(network: &NetworkSettings) -> Result<Option<reqwest::Proxy>> {
+    match &network.proxy {
+        Some(url) => Ok(Some(reqwest::Proxy::all(url).context(format!("Invalid proxy '{}'.", url))?)),
+        None => Ok(None),
+    }
+}
+
+fn certificate(network: &NetworkSettings) -> Result<Option<reqwest::Certificate>> {
+    let Some(path) = &network.ca_certificate else { return Ok(None) };
+    let pem = fs::read(path).context(format!("Failed to read the CA certificate '{}'.", path.display()))?;
+    Ok(Some(reqwest::Certificate::from_pem(&pem).context(format!("'{}' is not a PEM certificate.", path.display()))?))
+}
+
+/// A client for small requests like API calls, which give up after the timeout.
+pub fn client() -> Result<reqwest::blocking::Client> {
+    let network = settings::current().network;
+    let mut builder = reqwest::blocking::Client::builder().connect_timeout(timeout(&network)).timeout(timeout(&network));
+    if let Some(proxy) = reqwest_proxy(&network)? {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(certificate) = certificate(&network)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    Ok(builder.build()?)
+}
+
+/// A client for downloads, which only time out while connecting since big files take a while.
+pub fn download_client() -> Result<reqwest::blocking::Client> {
+    let network = settings::current().network;
+    let mut builder = reqwest::blocking::Client::builder().connect_timeout(timeout(&network)).timeout(None);
+    if let Some(proxy) = reqwest_proxy(&network)? {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(certificate) = certificate(&network)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    Ok(builder.build()?)
+}
+
+/// The async counterpart of `download_client`, for the LFS downloads.
+pub fn async_client() -> Result<reqwest::Client> {
+    let network = settings::current().network;
+    let mut builder = reqwest::Client::builder().connect_timeout(timeout(&network));
+    if let Some(proxy) = reqwest_proxy(&network)? {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(certificate) = certificate(&network)? {
+        builder = builder.add_root_certificate(certificate);
+    }
+    Ok(builder.build()?)
+}
+
+// libgit2 keeps these process-wide, so they're set again before every connection in case the
+// settings changed.
+fn configure_git(network: &NetworkSettings) -> Result<()> {
+    let millis = i32::try_from(timeout(network).as_millis()).unwrap_or(i32::MAX);
+    // SAFETY: libgit2 is initialized by git2 before setting options, and these only store values.
+    unsafe {
+        git2::opts::set_server_connect_timeout_in_milliseconds(millis)?;
+        git2::opts::set_server_timeout_in_milliseconds(millis)?;
+        if let Some(path) = &network.ca_certificate {
+            git2::opts::set_ssl_cert_file(path).context(format!("Git can't use the CA certificate '{}' on this system.", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+// The proxy from the settings, or else whatever the system and Git are configured with.
+fn git_proxy_options(network: &NetworkSettings) -> ProxyOptions<'static> {
+    let mut proxy_opts = ProxyOptions::new();
+    match &network.proxy {
+        Some(url) => proxy_opts.url(url),
+        None => proxy_opts.auto(),
+    };
+    proxy_opts
+}
+
+/// Fetch options with the network settings applied; callers add their own callbacks.
+pub fn git_fetch_options<'a>() -> Result<FetchOptions<'a>> {
+    let network = settings::current().network;
+    configure_git(&network)?;
+    let mut fo = FetchOptions::new();
+    fo.proxy_options(git_proxy_options(&network));
+    Ok(fo)
+}
+
+/// Connects a remote for listing its branches, with the network settings applied.
+pub fn git_connect(remote: &mut Remote) -> Result<()> {
+    let network = settings::current().network;
+    configure_git(&network)?;
+    remote.connect_auth(git2::Direction::Fetch, None, Some(git_proxy_options(&network)))?;
+    Ok(())
+}
+
+// Whether an error is worth trying again: timeouts, dropped connections and busy servers.
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.is_server_error() || s.as_u16() == 429);
+        }
+        if let Some(e) = cause.downcast_ref::<git2::Error>() {
+            return matches!(e.class(), git2::ErrorClass::Net | git2::ErrorClass::Http | git2::ErrorClass::Os);
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            return matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::UnexpectedEof);
+        }
+        false
+    })
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(u64::from(attempt))
+}
+
+/// Runs a network operation, trying it again as many times as the settings allow when it fails
+/// in a way that might not happen again.
+pub fn retry<T>(mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+    let retries = settings::current().network.retries;
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                thread::sleep(retry_delay(attempt));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// `retry` for async operations.
+pub async fn retry_async<T, F, Fut>(mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let retries = settings::current().network.retries;
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                tokio::time::sleep(retry_delay(attempt)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+// The host and port a URL connects to. `None` for local paths, which need no network.
+fn host_and_port(url: &str) -> Option<(String, u16)> {
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (scheme, rest),
+        // scp-like Git remotes such as `git@github.com:owner/repo.git`.
+        None => {
+            let (host, _) = url.split_once(':')?;
+            if host.len() < 2 || host.contains(['/', '\\']) {
+                return None;
+            }
+            let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+            return Some((host.to_string(), 22));
+        }
+    };
+    let default_port = match scheme {
+        "file" => return None,
+        "http" => 80,
+        "ssh" => 22,
+        "git" => 9418,
+        _ => 443,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => (host, port.parse().ok()?),
+        _ => (authority, default_port),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (!host.is_empty()).then(|| (host.to_string(), port))
+}
+
+// Connections go to the proxy when there is one, so that's what has to be reachable.
+fn proxy_url(network: &NetworkSettings) -> Option<String> {
+    network.proxy.clone().or_else(|| {
+        ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy", "HTTP_PROXY", "http_proxy"]
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.trim().is_empty()))
+    })
+}
+
+/// Checks that the server behind `url` can be reached before trying to use it, so being offline
+/// fails fast with `Offline` instead of a timeout or a resolver error from deep inside a library.
+pub fn ensure_online(url: &str) -> Result<()> {
+    let network = settings::current().network;
+    let target = match proxy_url(&network) {
+        Some(proxy) if host_and_port(url).is_some() => host_and_port(&proxy),
+        _ => host_and_port(url),
+    };
+    let Some((host, port)) = target else { return Ok(()) };
+
+    let key = (host.clone(), port);
+    if REACHABLE.lock().unwrap().get(&key).is_some_and(|checked| checked.elapsed() < PROBE_CACHE) {
+        return Ok(());
+    }
+    let offline = || anyhow::Error::new(Offline { host: host.clone() });
+    let addresses: Vec<_> = (host.as_str(), port).to_socket_addrs().map_err(|_| offline())?.collect();
+    let wait = timeout(&network).min(PROBE_TIMEOUT);
+    // A refused connection still means the network works; the server will say what's wrong.
+    let reachable = addresses.iter().any(|address| match TcpStream::connect_timeout(address, wait) {
+        Ok(_) => true,
+        Err(e) => e.kind() == io::ErrorKind::ConnectionRefused,
+    });
+    if !reachable {
+        return Err(offline());
+    }
+    REACHABLE.lock().unwrap().insert(key, Instant::now());
+    Ok(())
+}
+
+/// Succeeds when any of the servers can be reached. One being down is that server's problem;
+/// all of them failing means the network is.
+pub fn check_connection(urls: &[String]) -> Result<()> {
+    let mut first_error = None;
+    for url in urls {
+        match ensure_online(url) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
@@ -0,0 +1,275 @@
+use anyhow::{Context, Result};
+use git2::{Commit, Repository};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Optional file at the root of the pack repository describing how the updater should treat it.
+pub const METADATA_FILE: &str = "modpack-updater.toml";
+
+// Used when the pack doesn't declare any client-only paths itself.
+// These mirror the client-side items applied from `configureddefaults`.
+const DEFAULT_CLIENT_ONLY: &[&str] = &[
+    "config/fancymenu/**",
+    "config/fog/**",
+    "config/customsplashscreen.json",
+    "config/raised.json",
+    "customsplashscreen/**",
+    "resourcepacks/**",
+    "shaderpacks/**",
+    "sodium-*",
+    "sodiumextrainformation.json",
+];
+
+// Mods that only run on the client, matched case-insensitively since jar names aren't consistent.
+// Also only used when the pack doesn't declare its own client-only paths.
+const DEFAULT_CLIENT_ONLY_MODS: &[&str] = &[
+    "mods/ambientsounds*.jar",
+    "mods/betterf3*.jar",
+    "mods/continuity*.jar",
+    "mods/controlling*.jar",
+    "mods/cullleaves*.jar",
+    "mods/customsplashscreen*.jar",
+    "mods/drippyloadingscreen*.jar",
+    "mods/embeddium*.jar",
+    "mods/entity_model_features*.jar",
+    "mods/entity_texture_features*.jar",
+    "mods/entityculling*.jar",
+    "mods/fancymenu*.jar",
+    "mods/immediatelyfast*.jar",
+    "mods/iris*.jar",
+    "mods/lambdynamiclights*.jar",
+    "mods/legendarytooltips*.jar",
+    "mods/mousetweaks*.jar",
+    "mods/notenoughanimations*.jar",
+    "mods/oculus*.jar",
+    "mods/presencefootsteps*.jar",
+    "mods/reeses-sodium-options*.jar",
+    "mods/rubidium*.jar",
+    "mods/skinlayers3d*.jar",
+    "mods/sodium*.jar",
+    "mods/sound-physics*.jar",
+    "mods/xaeros_minimap*.jar",
+    "mods/xaerosworldmap*.jar",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+impl Side {
+    /// A folder containing `server.properties` is treated as a dedicated server.
+    pub fn detect(instance_path: &Path) -> Side {
+        if instance_path.join("server.properties").is_file() {
+            Side::Server
+        } else {
+            Side::Client
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Client => write!(f, "client"),
+            Side::Server => write!(f, "server"),
+        }
+    }
+}
+
+/// Whether each optional group is enabled, by group id.
+pub type OptionalChoices = BTreeMap<String, bool>;
+
+/// Contents of `modpack-updater.toml`.
+///
+/// ```toml
+/// [sides]
+/// client = ["mods/sodium-*.jar", "config/fancymenu/**"]
+/// server = ["mods/spark-server-*.jar"]
+///
+/// [defaultconfigs]
+/// force = ["create-server.toml", "jei/*"]
+///
+/// [[optional]]
+/// id = "shaders"
+/// name = "Shaders"
+/// description = "Iris and the bundled shader packs. Needs a strong GPU."
+/// default = false
+/// paths = ["mods/iris-*.jar", "shaderpacks/**"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PackMetadata {
+    pub sides: SideRules,
+    pub defaultconfigs: DefaultConfigRules,
+    pub optional: Vec<OptionalGroup>,
+}
+
+/// How NeoForge's `defaultconfigs/` folder is applied. Files are only copied where they're
+/// missing unless they match `force`, in which case the pack's changes are applied on every update.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DefaultConfigRules {
+    /// Patterns relative to `defaultconfigs/`.
+    pub force: Vec<String>,
+}
+
+/// Files the player can choose to leave out, such as heavy shader or performance mods.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OptionalGroup {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Whether the group is installed until the player decides otherwise.
+    #[serde(default = "default_enabled")]
+    pub default: bool,
+    pub paths: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl OptionalGroup {
+    pub fn is_enabled(&self, choices: &OptionalChoices) -> bool {
+        choices.get(&self.id).copied().unwrap_or(self.default)
+    }
+}
+
+/// Path patterns (relative to the instance root) that only belong on one side.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SideRules {
+    pub client: Vec<String>,
+    pub server: Vec<String>,
+}
+
+impl PackMetadata {
+    /// Reads the metadata file from a commit's tree. A pack without one gets the defaults.
+    pub fn from_commit(repo: &Repository, commit: &Commit) -> Result<Self> {
+        let tree = commit.tree()?;
+        let entry = match tree.get_path(Path::new(METADATA_FILE)) {
+            Ok(entry) => entry,
+            Err(_) => return Ok(Self::default()),
+        };
+        let blob = entry.to_object(repo)?.peel_to_blob()?;
+        let content = std::str::from_utf8(blob.content()).context(format!("'{}' is not valid UTF-8.", METADATA_FILE))?;
+        toml::from_str(content).context(format!("Failed to parse '{}'.", METADATA_FILE))
+    }
+
+    /// The `defaultconfigs/` files that are applied even when the instance already has them.
+    pub fn forced_default_configs(&self) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.defaultconfigs.force {
+            builder.add(Glob::new(pattern).context(format!("Invalid path pattern '{}' in '{}'.", pattern, METADATA_FILE))?);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Every optional group's choice, falling back to the pack's default where the player hasn't chosen.
+    pub fn resolve_choices(&self, choices: &OptionalChoices) -> OptionalChoices {
+        self.optional.iter().map(|group| (group.id.clone(), group.is_enabled(choices))).collect()
+    }
+
+    /// Builds the set of paths that must not be present on the given side,
+    /// including the optional groups the player turned off.
+    pub fn excluded_paths(&self, side: Side, choices: &OptionalChoices) -> Result<GlobSet> {
+        let mut patterns: Vec<&str> = match side {
+            Side::Server if self.sides.client.is_empty() => DEFAULT_CLIENT_ONLY.to_vec(),
+            Side::Server => self.sides.client.iter().map(String::as_str).collect(),
+            Side::Client => self.sides.server.iter().map(String::as_str).collect(),
+        };
+        for group in self.optional.iter().filter(|group| !group.is_enabled(choices)) {
+            patterns.extend(group.paths.iter().map(String::as_str));
+        }
+
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern).context(format!("Invalid path pattern '{}' in '{}'.", pattern, METADATA_FILE))?);
+        }
+        if side == Side::Server && self.sides.client.is_empty() {
+            for pattern in DEFAULT_CLIENT_ONLY_MODS {
+                builder.add(GlobBuilder::new(pattern).case_insensitive(true).build()?);
+            }
+        }
+        Ok(builder.build()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"
+[sides]
+server = ["mods/spark-server-*.jar"]
+
+[[optional]]
+id = "shaders"
+name = "Shaders"
+default = false
+paths = ["mods/iris-*.jar", "shaderpacks/**"]
+
+[[optional]]
+id = "minimap"
+name = "Minimap"
+paths = ["mods/xaeros_minimap-*.jar"]
+"#;
+
+    fn choices(pairs: &[(&str, bool)]) -> OptionalChoices {
+        pairs.iter().map(|(id, enabled)| (id.to_string(), *enabled)).collect()
+    }
+
+    #[test]
+    fn server_without_side_rules_leaves_out_known_client_mods() {
+        let excluded = PackMetadata::default().excluded_paths(Side::Server, &OptionalChoices::new()).unwrap();
+        assert!(excluded.is_match("mods/Sodium-Fabric-0.5.8.jar"));
+        assert!(excluded.is_match("shaderpacks/BSL/shaders.properties"));
+        assert!(excluded.is_match("config/fog/settings.json"));
+        assert!(!excluded.is_match("mods/create-1.21.1-6.0.4.jar"));
+        assert!(!excluded.is_match("config/create-common.toml"));
+    }
+
+    #[test]
+    fn declared_side_rules_replace_the_defaults() {
+        let metadata = PackMetadata { sides: SideRules { client: vec!["mods/iris-*.jar".into()], server: Vec::new() }, ..Default::default() };
+        let excluded = metadata.excluded_paths(Side::Server, &OptionalChoices::new()).unwrap();
+        assert!(excluded.is_match("mods/iris-1.7.jar"));
+        assert!(!excluded.is_match("mods/sodium-0.5.jar"));
+        assert!(metadata.excluded_paths(Side::Client, &OptionalChoices::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn optional_groups_follow_the_players_choice_or_the_default() {
+        let metadata: PackMetadata = toml::from_str(METADATA).unwrap();
+
+        let excluded = metadata.excluded_paths(Side::Client, &OptionalChoices::new()).unwrap();
+        assert!(excluded.is_match("mods/spark-server-1.0.jar"));
+        assert!(excluded.is_match("mods/iris-1.7.jar"));
+        assert!(excluded.is_match("shaderpacks/BSL/shaders.properties"));
+        assert!(!excluded.is_match("mods/xaeros_minimap-24.0.jar"));
+
+        let excluded = metadata.excluded_paths(Side::Client, &choices(&[("shaders", true), ("minimap", false)])).unwrap();
+        assert!(!excluded.is_match("mods/iris-1.7.jar"));
+        assert!(excluded.is_match("mods/xaeros_minimap-24.0.jar"));
+    }
+
+    #[test]
+    fn resolve_choices_fills_in_the_defaults_and_drops_unknown_groups() {
+        let metadata: PackMetadata = toml::from_str(METADATA).unwrap();
+        let resolved = metadata.resolve_choices(&choices(&[("minimap", false), ("removed", true)]));
+        assert_eq!(resolved, choices(&[("shaders", false), ("minimap", false)]));
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        let metadata = PackMetadata { sides: SideRules { client: Vec::new(), server: vec!["mods/[broken".into()] }, ..Default::default() };
+        let error = metadata.excluded_paths(Side::Client, &OptionalChoices::new()).unwrap_err();
+        assert!(error.to_string().contains("mods/[broken"));
+    }
+}
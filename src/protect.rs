@@ -0,0 +1,102 @@
+use crate::configs::relative_files;
+use anyhow::{Context, Result};
+use git2::{Repository, Tree, TreeWalkMode, TreeWalkResult};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The player's own files, which an update never overwrites or deletes even if the pack tracks them.
+pub const BUILT_IN: &[&str] = &[
+    "saves/**",
+    "screenshots/**",
+    "options.txt",
+    "optionsof.txt",
+    "optionsshaders.txt",
+    "servers.dat",
+];
+
+/// Folder in the instance where protected files are backed up, one subfolder per update.
+pub const BACKUP_DIR: &str = ".modpack-updater/backups";
+
+/// The built-in protected paths plus the instance's own patterns.
+pub fn protected_paths(extra: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in BUILT_IN.iter().copied().chain(extra.iter().map(String::as_str)) {
+        builder.add(Glob::new(pattern).context(format!("Invalid protected path pattern '{}'.", pattern))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Protected files backed up before an update touches the working tree.
+pub struct Guard {
+    instance_path: PathBuf,
+    backup_dir: PathBuf,
+    files: Vec<String>,
+}
+
+impl Guard {
+    /// Backs up the protected files the update could change: tracked ones the new tree changes or
+    /// drops, and untracked ones inside `written_dirs`, the folders that get cleaned or written to.
+    pub fn backup(repo: &Repository, instance_path: &Path, new_tree: &Tree, protected: &GlobSet, written_dirs: &[String]) -> Result<Self> {
+        let mut candidates = BTreeSet::new();
+        for entry in repo.index()?.iter() {
+            candidates.insert(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+        new_tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                candidates.insert(format!("{}{}", dir, entry.name().unwrap_or_default()));
+            }
+            TreeWalkResult::Ok
+        })?;
+        for dir in written_dirs {
+            let dir_path = instance_path.join(dir);
+            if dir_path.is_dir() {
+                candidates.extend(relative_files(&dir_path)?.into_iter().map(|file| format!("{}/{}", dir, file)));
+            }
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let backup_dir = instance_path.join(BACKUP_DIR).join(timestamp.to_string());
+        let mut files = Vec::new();
+        for relative in candidates.into_iter().filter(|path| protected.is_match(path)) {
+            let path = instance_path.join(&relative);
+            let Ok(content) = fs::read(&path) else { continue };
+            let in_new_tree = new_tree.get_path(Path::new(&relative)).ok()
+                .and_then(|entry| entry.to_object(repo).ok()?.peel_to_blob().ok().map(|blob| blob.content().to_vec()));
+            if in_new_tree.as_deref() == Some(content.as_slice()) {
+                continue;
+            }
+            let backup = backup_dir.join(&relative);
+            if let Some(parent) = backup.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&backup, &content).context(format!("Failed to back up '{}'.", relative))?;
+            files.push(relative);
+        }
+        Ok(Self { instance_path: instance_path.to_path_buf(), backup_dir, files })
+    }
+
+    /// Puts back every backed-up file the update changed or removed. Returns them, relative to the
+    /// instance; the backup is kept only when something had to be restored.
+    pub fn restore(self) -> Result<Vec<String>> {
+        let mut restored = Vec::new();
+        for relative in self.files {
+            let backup = fs::read(self.backup_dir.join(&relative))?;
+            let path = self.instance_path.join(&relative);
+            if fs::read(&path).ok().as_deref() == Some(backup.as_slice()) {
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, backup).context(format!("Failed to restore '{}'.", relative))?;
+            restored.push(relative);
+        }
+        if restored.is_empty() && self.backup_dir.exists() {
+            fs::remove_dir_all(&self.backup_dir)?;
+        }
+        Ok(restored)
+    }
+}
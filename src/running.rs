@@ -0,0 +1,209 @@
+use crate::pack::Side;
+use anyhow::Result;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// What gave away that the game or server is still using an instance.
+#[derive(Debug)]
+pub enum InUseEvidence {
+    LockFile(PathBuf),
+    Process { pid: u32 },
+}
+
+/// Returned when an instance is still in use and must not be modified.
+#[derive(Debug)]
+pub struct InstanceInUse {
+    pub side: Side,
+    pub evidence: InUseEvidence,
+}
+
+impl fmt::Display for InstanceInUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subject = match self.side {
+            Side::Client => "Minecraft appears to be running on this instance",
+            Side::Server => "The server appears to be running",
+        };
+        match &self.evidence {
+            InUseEvidence::LockFile(path) => write!(f, "{} (lock held on '{}').", subject, path.display())?,
+            InUseEvidence::Process { pid } => write!(f, "{} (process {}).", subject, pid)?,
+        }
+        match self.side {
+            Side::Client => write!(f, "\nClose the game before updating."),
+            Side::Server => write!(f, "\nStop it before updating."),
+        }
+    }
+}
+
+impl std::error::Error for InstanceInUse {}
+
+/// Resolves the world folder of a dedicated server from `level-name` in `server.properties`.
+pub fn server_level_dir(instance_path: &Path) -> PathBuf {
+    let level_name = fs::read_to_string(instance_path.join("server.properties"))
+        .ok()
+        .and_then(|content| {
+            content.lines().find_map(|line| {
+                line.trim().strip_prefix("level-name=").map(|name| name.trim().to_string())
+            })
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "world".to_string());
+    instance_path.join(level_name)
+}
+
+/// The worlds of an instance: every save of a client, or the level of a dedicated server
+/// once it has been generated.
+pub fn world_dirs(instance_path: &Path, side: Side) -> Vec<PathBuf> {
+    let candidates = match side {
+        Side::Server => vec![server_level_dir(instance_path)],
+        Side::Client => fs::read_dir(instance_path.join("saves"))
+            .map(|entries| entries.filter_map(Result::ok).map(|entry| entry.path()).collect())
+            .unwrap_or_default(),
+    };
+    let mut worlds: Vec<PathBuf> = candidates.into_iter().filter(|dir| dir.join("level.dat").is_file()).collect();
+    worlds.sort();
+    worlds
+}
+
+/// Fails with [`InstanceInUse`] while the game or server is using the instance.
+pub fn ensure_not_running(instance_path: &Path, side: Side) -> Result<()> {
+    match find_running(instance_path, side) {
+        Some(in_use) => Err(in_use.into()),
+        None => Ok(()),
+    }
+}
+
+/// Looks for signs that the game or server currently has the instance open.
+pub fn find_running(instance_path: &Path, side: Side) -> Option<InstanceInUse> {
+    let found = |evidence| Some(InstanceInUse { side, evidence });
+
+    let lock_files = match side {
+        Side::Server => vec![
+            instance_path.join("server.lock"),
+            server_level_dir(instance_path).join("session.lock"),
+        ],
+        Side::Client => world_session_locks(&instance_path.join("saves")),
+    };
+    if let Some(lock_file) = lock_files.into_iter().find(|path| is_file_locked(path)) {
+        return found(InUseEvidence::LockFile(lock_file));
+    }
+
+    // The game keeps its log open for the whole session, even on the title screen.
+    let logs = instance_path.join("logs");
+    if let Some(log_file) = ["latest.log", "debug.log"].iter().map(|name| logs.join(name)).find(|path| is_file_in_use(path)) {
+        return found(InUseEvidence::LockFile(log_file));
+    }
+
+    if let Some(pid) = find_process_using(instance_path) {
+        return found(InUseEvidence::Process { pid });
+    }
+    None
+}
+
+fn world_session_locks(saves_dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(saves_dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path().join("session.lock"))
+                .filter(|lock| lock.is_file())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks whether another process holds a lock on the file.
+/// Minecraft locks `session.lock` through Java's `FileChannel.tryLock`,
+/// which maps to `fcntl` on Unix and `LockFileEx` on Windows.
+#[cfg(unix)]
+pub fn is_file_locked(path: &Path) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let Ok(file) = File::open(path) else { return false };
+    // SAFETY: `flock` is a plain C struct and F_GETLK only writes into it.
+    unsafe {
+        let mut lock: libc::flock = std::mem::zeroed();
+        lock.l_type = libc::F_WRLCK as libc::c_short;
+        lock.l_whence = libc::SEEK_SET as libc::c_short;
+        if libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) == -1 {
+            return false;
+        }
+        lock.l_type != libc::F_UNLCK as libc::c_short
+    }
+}
+
+#[cfg(windows)]
+pub fn is_file_locked(path: &Path) -> bool {
+    use fs2::FileExt;
+
+    if !path.exists() {
+        return false;
+    }
+    match File::open(path) {
+        Ok(file) => match file.try_lock_exclusive() {
+            Ok(()) => {
+                file.unlock().ok();
+                false
+            }
+            Err(_) => true,
+        },
+        // The game keeps the file open without sharing on some setups.
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn is_file_locked(_path: &Path) -> bool {
+    false
+}
+
+/// Checks whether any process has the file open. Only Windows can tell without a lock,
+/// by asking for exclusive access.
+#[cfg(windows)]
+fn is_file_in_use(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    path.is_file() && fs::OpenOptions::new().read(true).share_mode(0).open(path).is_err()
+}
+
+#[cfg(not(windows))]
+fn is_file_in_use(_path: &Path) -> bool {
+    false
+}
+
+/// Finds a process whose working directory or `--gameDir` argument is the instance.
+#[cfg(target_os = "linux")]
+fn find_process_using(instance_path: &Path) -> Option<u32> {
+    let instance = instance_path.canonicalize().ok()?;
+    let same_dir = |path: &Path| path.canonicalize().map(|p| p == instance).unwrap_or(false);
+
+    for entry in fs::read_dir("/proc").ok()?.filter_map(Result::ok) {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+        if pid == std::process::id() {
+            continue;
+        }
+        let Ok(cmdline) = fs::read(entry.path().join("cmdline")) else { continue };
+        let args: Vec<String> = cmdline
+            .split(|b| *b == 0)
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        if !args.first().is_some_and(|exe| exe.contains("java")) {
+            continue;
+        }
+
+        let game_dir = args.windows(2).find(|pair| pair[0] == "--gameDir").map(|pair| PathBuf::from(&pair[1]));
+        let uses_instance = match game_dir {
+            Some(dir) => same_dir(&dir),
+            None => fs::read_link(entry.path().join("cwd")).map(|cwd| same_dir(&cwd)).unwrap_or(false),
+        };
+        if uses_instance {
+            return Some(pid);
+        }
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn find_process_using(_instance_path: &Path) -> Option<u32> {
+    None
+}
@@ -1,13 +1,28 @@
-use crate::app::{App, AppState, RunMode, TutorialState};
+use crate::app::history::{self, HistoryEntry, UpdateResult};
+use crate::app::packs::Pack;
+use crate::app::settings::{self, Field, Settings, Theme};
+use crate::app::{App, AppState, InstanceStatus, RunMode, TutorialState};
+use crate::bulk::{self, BulkJob, BulkOutcome, BulkResult};
+use crate::changelog::{ChangelogFocus, ChangelogView, PackChangelog};
+use crate::configs::{ConfigChange, ConfigMode};
+use crate::markdown;
+use crate::mods::health::{Inspection, ModProblem};
+use crate::mods::DependencyKind;
 use crate::music::MusicPlayer;
+use crate::pack::{OptionalChoices, OptionalGroup, Side};
+use crate::protect;
+use crate::update::{AvailableRelease, UpdateChannel};
+use crate::worlds::WorldBackup;
 use lazy_static::lazy_static; // Added for static styles
 use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{block::Title, Block, Borders, Clear, Gauge, List, ListItem, Paragraph},
+    widgets::{block::Title, Block, Borders, Cell, Clear, Gauge, List, ListItem, Padding, Paragraph, Row, Table},
     Frame,
 };
+use std::path::Path;
+use tui_input::Input;
 
 const MINECRAFT_VERSION: &str = "1.21.1";
 const NEOFORGE_VERSION: &str = "21.1.192";
@@ -48,22 +63,96 @@ pub fn draw(f: &mut Frame, app: &mut App, music_player: &MusicPlayer) {
     } else {
         match &mut app.state {
 
-            AppState::AwaitingInput => draw_input_ui(f, app),
-            AppState::ConfirmReinit => draw_confirm_ui(f),
-            AppState::ConfirmUpdate { version } => draw_confirm_update_popup(f, version),
+            AppState::AwaitingInput => draw_input_ui(f, app, " Change Directory (Enter to confirm, Esc to cancel) "),
+            AppState::EnteringBundlePath { create: true, .. } => draw_input_ui(f, app, " Save a Bundle To (Enter to save, Esc to cancel) "),
+            AppState::EnteringBundlePath { create: false, .. } => draw_input_ui(f, app, " Install From a Bundle File (Enter to open, Esc to cancel) "),
+            AppState::CreatingBundle { message, progress } => draw_processing_ui(f, message, *progress),
+            AppState::PackSelection { list_state } => draw_pack_selection_popup(f, &app.packs, list_state),
+            AppState::RenamingInstance { .. } => draw_rename_ui(f, app),
+            AppState::ConfirmRemoveInstance { index } => {
+                if let Some(entry) = app.history.get(*index) {
+                    draw_remove_instance_popup(f, entry);
+                }
+            }
+            AppState::FetchingIncoming => draw_fetching_popup(f, "Fetching Incoming Changes..."),
+            AppState::ReviewIncoming { changelog, error, scroll } => draw_incoming_popup(f, changelog, error.as_deref(), *scroll),
+            AppState::ConfirmReinit => {
+                let side = app.confirmed_side();
+                draw_confirm_ui(f, side, app.pack_changelog.as_ref(), app.bundle.as_deref());
+            }
+            AppState::ConfirmUpdate { release } => draw_confirm_update_popup(f, release, app.settings.updates.channel),
             AppState::FetchingChangelog => draw_fetching_popup(f, "Fetching Changelog..."),
-            AppState::ViewingChangelog { content, scroll } => draw_changelog_popup(f, content, *scroll),
+            AppState::ViewingChangelog(view) => draw_changelog_popup(f, view),
             AppState::FetchingBranches => draw_fetching_popup(f, "Fetching Branches..."),
             AppState::BranchSelection { branches, list_state, selected_branch } => {
                 draw_branch_selection_popup(f, branches, list_state, selected_branch);
             }
+            AppState::InstanceInUse { message, .. } => draw_instance_in_use_popup(f, message),
             AppState::Processing { message, progress } => draw_processing_ui(f, message, *progress),
-            AppState::Finished(msg) => draw_finished_ui(f, msg),
+            AppState::ChoosingOptional { groups, choices, list_state, .. } => draw_optional_popup(f, groups, choices, list_state),
+            AppState::Finished(msg) => {
+                draw_finished_ui(f, msg, app.pack_changelog.as_ref(), &app.mod_problems, &app.config_changes, &app.protected_files, app.removable_duplicates.len());
+            }
+            AppState::ConfirmRemoveDuplicates { jars, .. } => draw_confirm_remove_duplicates_popup(f, jars),
+            AppState::ReadingMods { .. } => draw_fetching_popup(f, "Reading Mods..."),
+            AppState::ViewingMods { inspection, list_state, error } => draw_mods_popup(f, inspection, list_state, error.as_deref()),
+            AppState::ConfirmUpdateAll { jobs, skipped } => draw_confirm_update_all_popup(f, jobs, skipped, app.settings.updates.concurrency),
+            AppState::UpdatingAll { message, progress } => draw_processing_ui(f, message, *progress),
+            AppState::UpdateAllSummary { results, scroll } => draw_update_all_summary(f, results, *scroll),
+            AppState::WorldBackups { instance, backups, list_state, outcome } => {
+                let entry = app.history.iter().find(|e| &e.path == instance);
+                draw_world_backups_popup(f, entry, backups, list_state, outcome.as_ref());
+            }
+            AppState::ConfirmRestoreWorld { backup, .. } => draw_confirm_restore_world_popup(f, backup),
+            AppState::Settings { list_state, editing } => draw_settings_popup(f, &app.settings, app.settings_error.as_deref(), list_state, editing.as_ref()),
+            AppState::WorkingOnWorlds { message, progress, .. } => draw_processing_ui(f, message, *progress),
             AppState::ConfirmInvalidFolder { path } => draw_invalid_folder_popup(f, &path.display().to_string()),
             AppState::InsideInstanceFolderError => draw_inside_folder_error_popup(f),
             _ => {}
         }
     }
+
+    apply_theme(f, app.settings.appearance.theme);
+}
+
+/// Recolors the finished frame for the accessibility themes, so the screens keep one set of styles.
+fn apply_theme(f: &mut Frame, theme: Theme) {
+    if theme == Theme::Default {
+        return;
+    }
+    for cell in f.buffer_mut().content.iter_mut() {
+        match theme {
+            Theme::HighContrast => {
+                cell.fg = brighten(cell.fg);
+            }
+            Theme::Monochrome => {
+                if cell.fg == Color::DarkGray {
+                    cell.modifier.insert(Modifier::DIM);
+                }
+                // Highlights are only told apart by their background, so keep them visible.
+                if !matches!(cell.bg, Color::Reset | Color::Black) {
+                    cell.modifier.insert(Modifier::REVERSED);
+                }
+                cell.fg = Color::Reset;
+                cell.bg = Color::Reset;
+            }
+            Theme::Default => {}
+        }
+    }
+}
+
+fn brighten(color: Color) -> Color {
+    match color {
+        Color::DarkGray => Color::Gray,
+        Color::Gray => Color::White,
+        Color::Red => Color::LightRed,
+        Color::Green => Color::LightGreen,
+        Color::Yellow => Color::LightYellow,
+        Color::Blue => Color::LightBlue,
+        Color::Magenta => Color::LightMagenta,
+        Color::Cyan => Color::LightCyan,
+        other => other,
+    }
 }
 
 
@@ -100,6 +189,26 @@ fn draw_branch_selection_popup(f: &mut Frame, branches: &[String], list_state: &
     f.render_stateful_widget(list, area, list_state);
 }
 
+fn draw_pack_selection_popup(f: &mut Frame, packs: &[Pack], list_state: &mut ratatui::widgets::ListState) {
+    let items: Vec<ListItem> = packs.iter().map(|pack| {
+        ListItem::new(Line::from(vec![
+            Span::styled(pack.name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled(format!("  {}", pack.remote), Style::default().fg(Color::DarkGray)),
+        ]))
+    }).collect();
+
+    let popup_width = 70.min(f.size().width);
+    let popup_height = (packs.len() as u16 + 4).min(f.size().height);
+    let area = centered_rect(popup_width, popup_height, f.size());
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(" Which Pack Is This Instance For? ").title(Title::from(" Enter to choose, Esc to cancel ").position(ratatui::widgets::block::Position::Bottom)))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, list_state);
+}
+
 fn draw_fetching_popup(f: &mut Frame, message: &str) {
     let text = Text::from(vec![Line::from(""), Line::from(message), Line::from("")]);
     let block = Block::default().title(" Please Wait ").borders(Borders::ALL);
@@ -109,30 +218,137 @@ fn draw_fetching_popup(f: &mut Frame, message: &str) {
     f.render_widget(text_widget, area);
 }
 
-fn draw_changelog_popup(f: &mut Frame, content: &str, scroll: u16) {
-    let text = Text::from(content);
+fn draw_changelog_popup(f: &mut Frame, view: &mut ChangelogView) {
     let popup_width = (f.size().width as f32 * 0.8) as u16;
     let popup_height = (f.size().height as f32 * 0.8) as u16;
     let area = centered_rect(popup_width, popup_height, f.size());
-    let block = Block::default().title(" Changelog (↑/↓ to scroll, Esc to close) ").borders(Borders::ALL);
-    let paragraph = Paragraph::new(text).block(block).scroll((scroll, 0));
+    let mut title = vec![Span::raw(format!(" {} ", view.title))];
+    if let Some(fetched_at) = view.stale_since {
+        title.push(Span::styled(format!("· offline copy from {} ", history::format_age(fetched_at)), *styles::GOLD_STYLE));
+    }
+    match &view.query {
+        Some(query) if view.matches.is_empty() => title.push(Span::raw(format!("· no matches for '{}' ", query))),
+        Some(query) => title.push(Span::raw(format!("· '{}' {}/{} ", query, view.current_match + 1, view.matches.len()))),
+        None => {}
+    }
+    let block = Block::default().title(Line::from(title)).borders(Borders::ALL);
+    let inner = block.inner(area);
     f.render_widget(Clear, area);
-    f.render_widget(paragraph, area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default().direction(Direction::Vertical).constraints([Constraint::Min(1), Constraint::Length(1)]).split(inner);
+    let body = if view.focus == ChangelogFocus::Index {
+        let columns = Layout::default().direction(Direction::Horizontal).constraints([Constraint::Length((inner.width / 3).min(32)), Constraint::Min(1)]).split(chunks[0]);
+        let items: Vec<ListItem> = view.sections().iter().map(|heading| ListItem::new(heading.title.clone())).collect();
+        let index = List::new(items)
+            .block(Block::default().title(" Versions ").borders(Borders::RIGHT))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        f.render_stateful_widget(index, columns[0], &mut view.index_state);
+        columns[1]
+    } else {
+        chunks[0]
+    };
+
+    view.layout(body.width, body.height);
+    let scroll = view.scroll as usize;
+    let mut visible: Vec<Line> = view.rows.iter().skip(scroll).take(body.height as usize).cloned().collect();
+    // Highlight the rows of every line that matches the search.
+    for (match_index, line) in view.matches.iter().enumerate() {
+        let first = view.line_rows[*line];
+        let last = view.line_rows.get(line + 1).copied().unwrap_or(view.rows.len());
+        let bg = if match_index == view.current_match { Color::Yellow } else { Color::DarkGray };
+        for row in first.max(scroll)..last.min(scroll + visible.len()) {
+            let highlighted = std::mem::take(&mut visible[row - scroll]);
+            visible[row - scroll] = highlighted.patch_style(Style::default().bg(bg).fg(Color::Black));
+        }
+    }
+    f.render_widget(Paragraph::new(visible), body);
+
+    let footer = match view.focus {
+        ChangelogFocus::Searching => {
+            f.set_cursor(chunks[1].x + 1 + view.search.visual_cursor() as u16, chunks[1].y);
+            Line::from(vec![Span::styled("/", *styles::GOLD_STYLE), Span::raw(view.search.value().to_string())])
+        }
+        ChangelogFocus::Index => Line::from(vec![
+            Span::styled(" ↑/↓ ", *styles::KEY_STYLE_BLUE), Span::raw(" Select  "),
+            Span::styled(" Enter ", *styles::KEY_STYLE_GREEN), Span::raw(" Jump  "),
+            Span::styled(" Esc ", *styles::KEY_STYLE), Span::raw(" Back "),
+        ]),
+        ChangelogFocus::Reading => Line::from(vec![
+            Span::styled(" ↑/↓/PgUp/PgDn ", *styles::KEY_STYLE_BLUE), Span::raw(" Scroll  "),
+            Span::styled(" I ", *styles::KEY_STYLE_YELLOW), Span::raw(" Versions  "),
+            Span::styled(" / ", *styles::KEY_STYLE_CYAN), Span::raw(" Search  "),
+            Span::styled(" N ", *styles::KEY_STYLE), Span::raw(" Next  "),
+            Span::styled(" Esc ", *styles::KEY_STYLE_RED), Span::raw(" Close "),
+        ]),
+    };
+    let alignment = if view.focus == ChangelogFocus::Searching { Alignment::Left } else { Alignment::Center };
+    f.render_widget(Paragraph::new(footer).alignment(alignment), chunks[1]);
 }
 
-fn draw_confirm_update_popup(f: &mut Frame, version: &str) {
-    let text = Text::from(vec![
-        Line::from(vec![Span::raw("A new version ("), Span::styled(version, styles::GREEN_STYLE.add_modifier(Modifier::BOLD)), Span::raw(") is available!")]),
+fn draw_incoming_popup(f: &mut Frame, changelog: &PackChangelog, error: Option<&str>, scroll: u16) {
+    let mut lines = Vec::new();
+    if let Some(error) = error {
+        lines.push(Line::from(Span::styled("Could not load the incoming changes:", *styles::RED_STYLE)));
+        lines.extend(error.lines().map(|l| Line::from(l.to_string())));
+    } else if changelog.commits.is_empty() {
+        lines.push(Line::from("No new commits. The update will verify and repair your files."));
+    } else {
+        lines.push(Line::from(Span::styled(format!("{} will be applied:", changelog.summary()), *styles::GOLD_STYLE)));
+        lines.push(Line::from(""));
+        if !changelog.mods.is_empty() {
+            lines.extend(mod_change_lines(changelog, usize::MAX));
+            lines.push(Line::from(""));
+        }
+        for commit in &changelog.commits {
+            let (id, summary) = commit.split_once(' ').unwrap_or((commit, ""));
+            lines.push(Line::from(vec![Span::styled(id.to_string(), *styles::CYAN_STYLE), Span::raw(format!(" {}", summary))]));
+        }
+    }
+
+    let popup_width = (f.size().width as f32 * 0.7) as u16;
+    let popup_height = (lines.len() as u16 + 2).clamp(5, (f.size().height as f32 * 0.7) as u16);
+    let area = centered_rect(popup_width, popup_height, f.size());
+    let block = Block::default()
+        .title(" Incoming Changes ")
+        .title(Title::from(" Enter to continue, Esc to cancel ").position(ratatui::widgets::block::Position::Bottom))
+        .borders(Borders::ALL);
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines).block(block).scroll((scroll, 0)), area);
+}
+
+fn draw_confirm_update_popup(f: &mut Frame, release: &AvailableRelease, channel: UpdateChannel) {
+    const NOTES_WIDTH: usize = 70;
+    let kind = if release.prerelease { "beta version" } else { "version" };
+    let mut lines = vec![
+        Line::from(vec![Span::raw(format!("A new {} (", kind)), Span::styled(release.version.as_str(), styles::GREEN_STYLE.add_modifier(Modifier::BOLD)), Span::raw(") is available!")]).alignment(Alignment::Center),
+        Line::from(Span::styled(format!("Update channel: {} (press U on the start screen to switch)", channel), Style::default().fg(Color::DarkGray))).alignment(Alignment::Center),
         Line::from(""),
-        Line::from("Would you like to update now?"),
+    ];
+    if !release.notes.trim().is_empty() {
+        // The notes get whatever height the rest of the popup leaves.
+        let room = (f.size().height as usize).saturating_sub(12).clamp(3, 20);
+        let (notes, _) = markdown::wrap(&markdown::render(&release.notes), NOTES_WIDTH);
+        let shown = notes.len().min(room);
+        let hidden = notes.len() - shown;
+        lines.extend(notes.into_iter().take(shown));
+        if hidden > 0 {
+            lines.push(Line::from(Span::styled(format!("… {} more lines on the release page", hidden), Style::default().fg(Color::DarkGray))));
+        }
+        lines.push(Line::from(""));
+    }
+    lines.extend([
+        Line::from("Would you like to update now?").alignment(Alignment::Center),
         Line::from(""),
-        Line::from(vec![Span::styled(" Y ", *styles::KEY_STYLE_GREEN), Span::raw(" Yes "), Span::styled(" Esc ", *styles::KEY_STYLE), Span::raw(" No (update on next launch) ")]),
+        Line::from(vec![Span::styled(" Y ", *styles::KEY_STYLE_GREEN), Span::raw(" Yes "), Span::styled(" Esc ", *styles::KEY_STYLE), Span::raw(" No (update on next launch) ")]).alignment(Alignment::Center),
     ]);
-    let popup_width = (text.width() + 4).min(f.size().width.into());
+    let text = Text::from(lines);
+    let popup_width = (text.width() + 6).min(f.size().width.into());
     let popup_height = (text.height() as u16 + 2).min(f.size().height);
     let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
-    let block = Block::default().title(" Update Available ").borders(Borders::ALL).border_style(*styles::GREEN_STYLE);
-    let text_widget = Paragraph::new(text).block(block).alignment(Alignment::Center);
+    let block = Block::default().title(" Update Available ").borders(Borders::ALL).border_style(*styles::GREEN_STYLE).padding(Padding::horizontal(2));
+    let text_widget = Paragraph::new(text).block(block);
     f.render_widget(Clear, area);
     f.render_widget(text_widget, area);
 }
@@ -249,6 +465,24 @@ fn draw_invalid_folder_popup(f: &mut Frame, path_str: &str) {
     f.render_widget(text_widget, area);
 }
 
+fn draw_instance_in_use_popup(f: &mut Frame, message: &str) {
+    let mut lines: Vec<Line> = message.lines().map(|line| Line::from(Span::styled(line.to_string(), *styles::RED_STYLE))).collect();
+    lines.extend(vec![
+        Line::from(""),
+        Line::from("Updating now could corrupt your session or fail to replace mods."),
+        Line::from(""),
+        Line::from(vec![Span::styled(" R ", *styles::KEY_STYLE_GREEN), Span::raw(" Retry "), Span::styled(" Esc ", *styles::KEY_STYLE), Span::raw(" Cancel ")]),
+    ]);
+    let text = Text::from(lines);
+    let popup_width = (text.width() + 4).min(f.size().width.into());
+    let popup_height = (text.height() as u16 + 2).min(f.size().height);
+    let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
+    let block = Block::default().title(" Instance In Use ").borders(Borders::ALL).border_style(*styles::RED_STYLE);
+    let text_widget = Paragraph::new(text).block(block).alignment(Alignment::Center);
+    f.render_widget(Clear, area);
+    f.render_widget(text_widget, area);
+}
+
 fn draw_music_bar(f: &mut Frame, area: Rect, music_player: &MusicPlayer, is_dimmed: bool) {
     let (title, artist, song_style) = music_player.get_current_song_info();
     let dimmed_style = Style::default().fg(Color::DarkGray);
@@ -270,12 +504,16 @@ fn draw_music_bar(f: &mut Frame, area: Rect, music_player: &MusicPlayer, is_dimm
 
 fn draw_startup_ui(f: &mut Frame, app: &mut App, music_player: &MusicPlayer, is_dimmed: bool) {
     let size = f.size();
-    let layout = Layout::default().direction(Direction::Vertical).constraints([Constraint::Min(1), Constraint::Length(1), Constraint::Length(2)]).split(size);
+    let layout = Layout::default().direction(Direction::Vertical).constraints([Constraint::Min(1), Constraint::Length(1), Constraint::Length(4)]).split(size);
     let header_style = if is_dimmed { Style::default().fg(Color::DarkGray) } else { Style::default() };
-    let mut items: Vec<ListItem> = app.history.iter().map(|p| ListItem::new(Span::styled(p.display().to_string(), header_style))).collect();
+    let mut items: Vec<ListItem> = app.history.iter().map(|entry| history_item(entry, app.instance_statuses.get(&entry.path), app.status_rx.is_some(), is_dimmed)).collect();
     let new_instance_style = if is_dimmed { header_style } else { *styles::CYAN_STYLE };
     items.push(ListItem::new(Span::styled("Specify a new Instance...", new_instance_style)));
-    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(" Select an Instance to Update ").style(header_style)).highlight_style(if is_dimmed { header_style } else { Style::default().add_modifier(Modifier::REVERSED) }).highlight_symbol(if is_dimmed { " " } else { "> " });
+    let mut block = Block::default().borders(Borders::ALL).title(" Select an Instance to Update ").style(header_style);
+    if let Some(offline) = &app.offline {
+        block = block.title(Title::from(Span::styled(format!(" {} ", offline), if is_dimmed { header_style } else { *styles::RED_STYLE })).position(ratatui::widgets::block::Position::Bottom));
+    }
+    let list = List::new(items).block(block).highlight_style(if is_dimmed { header_style } else { Style::default().add_modifier(Modifier::REVERSED) }).highlight_symbol(if is_dimmed { " " } else { "> " });
     f.render_stateful_widget(list, layout[0], &mut app.history_state);
     draw_music_bar(f, layout[1], music_player, is_dimmed);
     const MUSIC_TOOLTIP_WIDTH: usize = 13;
@@ -290,10 +528,234 @@ fn draw_startup_ui(f: &mut Frame, app: &mut App, music_player: &MusicPlayer, is_
             Span::styled(" P ", if is_dimmed { header_style } else { *styles::KEY_STYLE_CYAN }), Span::raw(&music_status_tooltip),
             Span::styled(" Q/Esc ", if is_dimmed { header_style } else { *styles::KEY_STYLE_RED }), Span::raw(" Quit   "),
         ]),
+        Line::from(vec![
+            Span::raw("   "), Span::styled(" Shift+↑/↓ ", if is_dimmed { header_style } else { *styles::KEY_STYLE_BLUE }), Span::raw(" Move   "),
+            Span::styled(" R ", if is_dimmed { header_style } else { *styles::KEY_STYLE }), Span::raw(" Rename   "),
+            Span::styled(" T ", if is_dimmed { header_style } else { *styles::KEY_STYLE }), Span::raw(" Pin/Unpin   "),
+            Span::styled(" Del ", if is_dimmed { header_style } else { *styles::KEY_STYLE_RED }), Span::raw(" Remove   "),
+            Span::styled(" A ", if is_dimmed { header_style } else { *styles::KEY_STYLE_GREEN }), Span::raw(" Update All   "),
+            Span::styled(" X/I ", if is_dimmed { header_style } else { *styles::KEY_STYLE }), Span::raw(" Offline Bundle   "),
+        ]),
+        Line::from(vec![
+            Span::raw("   "), Span::styled(" M ", if is_dimmed { header_style } else { *styles::KEY_STYLE_YELLOW }), Span::raw(" Mods   "),
+            Span::styled(" K ", if is_dimmed { header_style } else { *styles::KEY_STYLE }), Span::raw(" Config Mode   "),
+            Span::styled(" B ", if is_dimmed { header_style } else { *styles::KEY_STYLE }), Span::raw(" World Backups   "),
+            Span::styled(" U ", if is_dimmed { header_style } else { *styles::KEY_STYLE }), Span::raw(format!(" Update Channel: {}   ", app.settings.updates.channel)),
+            Span::styled(" S ", if is_dimmed { header_style } else { *styles::KEY_STYLE_CYAN }), Span::raw(" Settings   "),
+        ]),
     ];
     f.render_widget(Paragraph::new(footer_lines).style(header_style), layout[2]);
 }
 
+// Two lines per instance: its name, pack and status, then where it lives and how its last update went.
+fn history_item(entry: &HistoryEntry, status: Option<&InstanceStatus>, checking: bool, is_dimmed: bool) -> ListItem<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let style = |s: Style| if is_dimmed { dim } else { s };
+
+    let mut title = vec![Span::styled(entry.display_name(), style(Style::default().add_modifier(Modifier::BOLD)))];
+    if let Some(pack) = &entry.pack {
+        let branch = entry.branch.as_deref().map(|b| format!(" · {}", b)).unwrap_or_default();
+        title.push(Span::styled(format!("  [{}{}]", pack, branch), dim));
+    }
+    match status {
+        Some(status) => {
+            let status_style = match status {
+                InstanceStatus::Pinned => *styles::CYAN_STYLE,
+                InstanceStatus::UpToDate { dirty: 0 } => *styles::GREEN_STYLE,
+                InstanceStatus::UpToDate { .. } | InstanceStatus::Behind { .. } => *styles::GOLD_STYLE,
+                InstanceStatus::Unknown(_) | InstanceStatus::Offline => dim,
+            };
+            title.push(Span::styled(format!("  {}", status.label()), style(status_style)));
+        }
+        None if entry.pinned => title.push(Span::styled("  pinned", style(*styles::CYAN_STYLE))),
+        None if checking && entry.is_available() => title.push(Span::styled("  checking...", dim)),
+        None => {}
+    }
+
+    let mut details = vec![Span::styled(format!("    {}", entry.path.display()), dim)];
+    if !entry.is_available() {
+        details.push(Span::styled("  (missing)", style(*styles::RED_STYLE)));
+    }
+    if let Some(timestamp) = entry.last_updated {
+        let commit = entry.last_commit.as_deref().map(|c| format!(" at {}", c)).unwrap_or_default();
+        details.push(Span::styled(format!("  · updated {}{}", history::format_age(timestamp), commit), dim));
+    }
+    if entry.config_mode == ConfigMode::Reset {
+        details.push(Span::styled(format!("  · configs: {}", entry.config_mode), dim));
+    }
+    match &entry.last_result {
+        Some(UpdateResult::Success) => details.push(Span::styled("  ✓", style(*styles::GREEN_STYLE))),
+        Some(UpdateResult::Failed { message }) => details.push(Span::styled(format!("  ✗ {}", message), style(*styles::RED_STYLE))),
+        None => {}
+    }
+
+    ListItem::new(vec![Line::from(title), Line::from(details)])
+}
+
+fn draw_mods_popup(f: &mut Frame, inspection: &Inspection, list_state: &mut ratatui::widgets::ListState, error: Option<&str>) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let jars = &inspection.jars;
+    let area = centered_rect(f.size().width * 85 / 100, f.size().height * 85 / 100, f.size());
+    let problems_height = if inspection.problems.is_empty() { 0 } else { (inspection.problems.len() as u16 + 2).min(8) };
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(problems_height), Constraint::Length(10)])
+        .split(area);
+
+    let items: Vec<ListItem> = jars.iter().map(|jar| {
+        let line = match (jar.mods.first(), &jar.error) {
+            (_, Some(_)) => Line::from(vec![Span::styled(jar.file_name(), *styles::RED_STYLE), Span::styled("  unreadable", dim)]),
+            (None, None) => Line::from(vec![Span::raw(jar.file_name()), Span::styled("  no mod metadata", dim)]),
+            (Some(info), None) => {
+                let mut spans = vec![
+                    Span::styled(info.name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(format!(" {}", info.version)),
+                    Span::styled(format!("  {} · {}", info.loader, jar.file_name()), dim),
+                ];
+                if jar.mods.len() > 1 {
+                    spans.push(Span::styled(format!("  +{} more", jar.mods.len() - 1), dim));
+                }
+                Line::from(spans)
+            }
+        };
+        ListItem::new(line)
+    }).collect();
+    let declared: usize = jars.iter().map(|jar| jar.mods.len()).sum();
+    let list = List::new(items)
+        .block(Block::default().title(format!(" Mods ({} jars, {} mods) ", jars.len(), declared)).borders(Borders::ALL))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, layout[0], list_state);
+
+    let mut details = Vec::new();
+    if let Some(error) = error {
+        details.push(Line::from(Span::styled(error.to_string(), *styles::RED_STYLE)));
+    }
+    if let Some(jar) = list_state.selected().and_then(|i| jars.get(i)) {
+        if let Some(error) = &jar.error {
+            details.push(Line::from(Span::styled(error.clone(), *styles::RED_STYLE)));
+        }
+        for info in &jar.mods {
+            let loader_version = info.loader_version.as_deref().map(|v| format!(" {}", v)).unwrap_or_default();
+            details.push(Line::from(vec![
+                Span::styled(info.mod_id.clone(), *styles::CYAN_STYLE),
+                Span::raw(format!(" {}  ", info.version)),
+                Span::styled(format!("{}{}", info.loader, loader_version), dim),
+            ]));
+            for dependency in &info.dependencies {
+                let (marker, style) = match dependency.kind {
+                    DependencyKind::Required => ("requires", Style::default()),
+                    DependencyKind::Optional => ("optional", dim),
+                    DependencyKind::Incompatible => ("breaks", *styles::RED_STYLE),
+                };
+                details.push(Line::from(Span::styled(format!("  {} {} {}", marker, dependency.mod_id, dependency.version_range), style)));
+            }
+        }
+    }
+    let hint = if inspection.removable.is_empty() {
+        " ↑/↓ Select, Esc to close ".to_string()
+    } else {
+        format!(" ↑/↓ Select, D to remove {} untracked duplicate(s), Esc to close ", inspection.removable.len())
+    };
+    let block = Block::default()
+        .title(" Details ")
+        .title(Title::from(hint).position(ratatui::widgets::block::Position::Bottom))
+        .borders(Borders::ALL);
+    f.render_widget(Paragraph::new(details).block(block).wrap(ratatui::widgets::Wrap { trim: false }), layout[2]);
+
+    if !inspection.problems.is_empty() {
+        let lines: Vec<Line> = inspection.problems.iter().map(|problem| Line::from(problem.to_string())).collect();
+        let block = Block::default()
+            .title(Span::styled(format!(" ⚠ {} Problem(s) ", inspection.problems.len()), *styles::RED_STYLE))
+            .borders(Borders::ALL);
+        f.render_widget(Paragraph::new(lines).block(block).wrap(ratatui::widgets::Wrap { trim: true }), layout[1]);
+    }
+}
+
+fn draw_confirm_update_all_popup(f: &mut Frame, jobs: &[BulkJob], skipped: &[BulkResult], concurrency: usize) {
+    let mut lines = vec![Line::from(format!("Update {} instance(s), {} at a time?", jobs.len(), concurrency.min(jobs.len()))), Line::from("")];
+    for job in jobs {
+        lines.push(Line::from(vec![
+            Span::styled(job.name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled(format!("  [{} · {}]", job.options.pack.name, job.branch), Style::default().fg(Color::DarkGray)),
+        ]));
+    }
+    if !skipped.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(format!("{} instance(s) will be skipped (pinned, missing or without a known pack).", skipped.len()), Style::default().fg(Color::DarkGray))));
+    }
+    lines.extend(vec![
+        Line::from(""),
+        Line::from("A failed instance doesn't stop the others."),
+        Line::from(""),
+        Line::from(vec![Span::styled(" Y ", *styles::KEY_STYLE_GREEN), Span::raw(" Yes "), Span::styled(" N ", *styles::KEY_STYLE_RED), Span::raw(" No ")]),
+    ]);
+    let text = Text::from(lines);
+    let popup_width = (text.width() + 4).min(f.size().width.into());
+    let popup_height = (text.height() as u16 + 2).min(f.size().height);
+    let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
+    let block = Block::default().title(" Update All ").borders(Borders::ALL);
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(text).block(block).alignment(Alignment::Center), area);
+}
+
+fn draw_update_all_summary(f: &mut Frame, results: &[BulkResult], scroll: u16) {
+    let area = centered_rect(f.size().width * 80 / 100, (results.len() as u16 + 6).max(7), f.size());
+    let layout = Layout::default().direction(Direction::Vertical).margin(1).constraints([Constraint::Min(1), Constraint::Length(1)]).split(area);
+    let header = Row::new(vec!["Instance", "Pack", "Result"]).style(Style::default().add_modifier(Modifier::BOLD)).bottom_margin(1);
+    let rows: Vec<Row> = results.iter().skip(scroll as usize).map(|result| {
+        let style = match result.outcome {
+            BulkOutcome::Updated { .. } => *styles::GREEN_STYLE,
+            BulkOutcome::Failed(_) => *styles::RED_STYLE,
+            BulkOutcome::Skipped(_) => Style::default().fg(Color::DarkGray),
+        };
+        Row::new(vec![
+            Cell::from(result.name.clone()),
+            Cell::from(result.pack.clone().unwrap_or_default()),
+            Cell::from(Span::styled(bulk::outcome_label(&result.outcome), style)),
+        ])
+    }).collect();
+    let widths = [Constraint::Percentage(30), Constraint::Percentage(20), Constraint::Percentage(50)];
+    let failed = results.iter().filter(|r| matches!(r.outcome, BulkOutcome::Failed(_))).count();
+    let title = if failed == 0 { " Update All: Done ".to_string() } else { format!(" Update All: {} Failed ", failed) };
+    let block = Block::default().title(title).borders(Borders::ALL).border_style(if failed == 0 { Style::default() } else { *styles::RED_STYLE });
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    if results.is_empty() {
+        f.render_widget(Paragraph::new("No instances to update.").alignment(Alignment::Center), layout[0]);
+    } else {
+        f.render_widget(Table::new(rows, widths).header(header).column_spacing(2), layout[0]);
+    }
+    let footer = Line::from(vec![Span::styled(" ↑/↓ ", *styles::KEY_STYLE_BLUE), Span::raw(" Scroll "), Span::styled(" Enter ", *styles::KEY_STYLE_GREEN), Span::raw(" Close ")]);
+    f.render_widget(Paragraph::new(footer).alignment(Alignment::Center), layout[1]);
+}
+
+fn draw_rename_ui(f: &mut Frame, app: &App) {
+    let area = centered_rect(f.size().width * 60 / 100, 3, f.size());
+    f.render_widget(Clear, area);
+    let block = Block::default().title(" Rename Instance (Enter to save, empty to reset, Esc to cancel) ").borders(Borders::ALL);
+    f.render_widget(Paragraph::new(app.input.value()).block(block), area);
+    f.set_cursor(area.x + app.input.visual_cursor() as u16 + 1, area.y + 1);
+}
+
+fn draw_remove_instance_popup(f: &mut Frame, entry: &HistoryEntry) {
+    let text = Text::from(vec![
+        Line::from(vec![Span::raw("Remove '"), Span::styled(entry.display_name(), Style::default().add_modifier(Modifier::BOLD)), Span::raw("' from the list?")]),
+        Line::from(Span::styled(entry.path.display().to_string(), Style::default().fg(Color::DarkGray))),
+        Line::from(""),
+        Line::from("The instance folder itself is not deleted."),
+        Line::from(""),
+        Line::from(vec![Span::styled(" Y ", *styles::KEY_STYLE_RED), Span::raw(" Remove "), Span::styled(" N ", *styles::KEY_STYLE), Span::raw(" Keep ")]),
+    ]);
+    let popup_width = (text.width() + 4).min(f.size().width.into());
+    let popup_height = (text.height() as u16 + 2).min(f.size().height);
+    let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
+    let block = Block::default().title(" Remove Instance ").borders(Borders::ALL).border_style(*styles::RED_STYLE);
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(text).block(block).alignment(Alignment::Center), area);
+}
+
 fn draw_browsing_ui(f: &mut Frame, app: &mut App, music_player: &MusicPlayer, is_dimmed: bool) {
     let size = f.size();
     let layout = Layout::default().direction(Direction::Vertical).constraints([Constraint::Length(4), Constraint::Min(1), Constraint::Length(1), Constraint::Length(2)]).split(size);
@@ -354,12 +816,12 @@ fn centered_rect(width: u16, height: u16, r: Rect) -> Rect {
     Layout::default().direction(Direction::Horizontal).constraints([Constraint::Percentage((100 - r.width.min(width) * 100 / r.width) / 2), Constraint::Length(width), Constraint::Percentage((100 - r.width.min(width) * 100 / r.width) / 2)]).split(popup_layout[1])[1]
 }
 
-fn draw_input_ui(f: &mut Frame, app: &App) {
+fn draw_input_ui(f: &mut Frame, app: &App, title: &str) {
     let popup_width = 80;
     let popup_height = if app.input_error.is_some() { 5 } else { 3 };
     let area = centered_rect(f.size().width * popup_width / 100, popup_height, f.size());
     f.render_widget(Clear, area);
-    let block = Block::default().title(" Change Directory (Enter to confirm, Esc to cancel) ").borders(Borders::ALL);
+    let block = Block::default().title(title).borders(Borders::ALL);
     if let Some(err) = &app.input_error {
         let input_chunks = Layout::default().direction(Direction::Vertical).constraints([Constraint::Length(3), Constraint::Length(1)]).split(area);
         let input_widget = Paragraph::new(app.input.value()).block(block);
@@ -387,16 +849,51 @@ fn draw_processing_ui(f: &mut Frame, message: &str, progress: f64) {
     f.render_widget(gauge, inner_chunks[2]);
 }
 
-fn draw_confirm_ui(f: &mut Frame) {
-    let text = Text::from(vec![
+// Added, removed and updated mods in green, red and gold, cut off after `limit` lines.
+fn mod_change_lines(changelog: &PackChangelog, limit: usize) -> Vec<Line<'static>> {
+    let mod_lines = changelog.mod_lines();
+    let mut lines: Vec<Line> = mod_lines.iter().take(limit).map(|line| {
+        let style = match line.chars().next() {
+            Some('+') => *styles::GREEN_STYLE,
+            Some('-') => *styles::RED_STYLE,
+            _ => *styles::GOLD_STYLE,
+        };
+        Line::from(Span::styled(line.clone(), style))
+    }).collect();
+    if mod_lines.len() > limit {
+        lines.push(Line::from(Span::styled(format!("... and {} more", mod_lines.len() - limit), Style::default().fg(Color::DarkGray))));
+    }
+    lines
+}
+
+fn draw_confirm_ui(f: &mut Frame, side: Side, changelog: Option<&PackChangelog>, bundle: Option<&Path>) {
+    let source = if bundle.is_some() { "This will merge the changes in the bundle," } else { "This will fetch and merge changes from the remote," };
+    let mut lines = vec![
         Line::from("A .git folder will be created or updated."),
         Line::from(""),
-        Line::from("This will fetch and merge changes from the remote,"),
+        Line::from(source),
         Line::from("updating files tracked by the repository."),
         Line::from("Untracked files will not be affected."),
         Line::from(""),
-        Line::from(vec![Span::styled("Continue? ", Style::default()), Span::styled(" Y ", *styles::KEY_STYLE_GREEN), Span::raw(" Yes "), Span::styled(" N ", *styles::KEY_STYLE_RED), Span::raw(" No ")]),
-    ]);
+    ];
+    if let Some(bundle) = bundle {
+        let name = bundle.file_name().unwrap_or(bundle.as_os_str()).to_string_lossy().into_owned();
+        lines.extend(vec![Line::from(vec![Span::styled("Bundle: ", *styles::GOLD_STYLE), Span::raw(name)]), Line::from("")]);
+    }
+    if side == Side::Server {
+        lines.extend(vec![
+            Line::from(vec![Span::styled("Server mode: ", *styles::GOLD_STYLE), Span::raw("client-only files are skipped")]),
+            Line::from("and the world folder is never touched."),
+            Line::from(""),
+        ]);
+    }
+    if let Some(changelog) = changelog.filter(|c| !c.commits.is_empty()) {
+        lines.push(Line::from(vec![Span::styled("Incoming: ", *styles::GOLD_STYLE), Span::raw(changelog.summary())]));
+        lines.extend(mod_change_lines(changelog, 6));
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(vec![Span::styled("Continue? ", Style::default()), Span::styled(" Y ", *styles::KEY_STYLE_GREEN), Span::raw(" Yes "), Span::styled(" N ", *styles::KEY_STYLE_RED), Span::raw(" No ")]));
+    let text = Text::from(lines);
     let popup_width = (text.width() + 4).min(f.size().width.into());
     let popup_height = (text.height() as u16 + 2).min(f.size().height);
     let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
@@ -406,8 +903,195 @@ fn draw_confirm_ui(f: &mut Frame) {
     f.render_widget(text_widget, area);
 }
 
-fn draw_finished_ui(f: &mut Frame, message: &str) {
-    let text = Text::from(message);
+fn draw_optional_popup(f: &mut Frame, groups: &[OptionalGroup], choices: &OptionalChoices, list_state: &mut ratatui::widgets::ListState) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let items: Vec<ListItem> = groups.iter().map(|group| {
+        let (marker, style) = if group.is_enabled(choices) { ("[x] ", *styles::GREEN_STYLE) } else { ("[ ] ", dim) };
+        let mut lines = vec![Line::from(vec![Span::styled(marker, style), Span::styled(group.name.clone(), Style::default().add_modifier(Modifier::BOLD))])];
+        if !group.description.is_empty() {
+            lines.push(Line::from(Span::styled(format!("    {}", group.description), dim)));
+        }
+        ListItem::new(lines)
+    }).collect();
+
+    let rows: u16 = groups.iter().map(|group| if group.description.is_empty() { 1 } else { 2 }).sum();
+    let width = groups.iter().map(|group| group.name.len().max(group.description.len()) + 10).max().unwrap_or(0).clamp(50, f.size().width as usize);
+    let area = centered_rect(width as u16, (rows + 2).min(f.size().height), f.size());
+    let block = Block::default()
+        .title(" Optional Mods ")
+        .title(Title::from(" Space Toggle, Enter Continue, Esc Cancel ").position(ratatui::widgets::block::Position::Bottom))
+        .borders(Borders::ALL);
+    let list = List::new(items).block(block).highlight_style(Style::default().add_modifier(Modifier::REVERSED)).highlight_symbol("> ");
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, area, list_state);
+}
+
+fn draw_confirm_remove_duplicates_popup(f: &mut Frame, jars: &[std::path::PathBuf]) {
+    let mut lines = vec![Line::from("Remove these stray copies? The pack tracks another copy of each mod."), Line::from("")];
+    lines.extend(jars.iter().map(|jar| Line::from(Span::styled(jar.display().to_string(), *styles::RED_STYLE))));
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![Span::styled(" Y ", *styles::KEY_STYLE_GREEN), Span::raw(" Remove "), Span::styled(" N ", *styles::KEY_STYLE_RED), Span::raw(" Keep ")]));
+    let text = Text::from(lines);
+    let popup_width = (text.width() + 4).min(f.size().width.into());
+    let popup_height = (text.height() as u16 + 2).min(f.size().height);
+    let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
+    f.render_widget(Clear, area);
+    let block = Block::default().title(" Remove Duplicate Mods ").borders(Borders::ALL);
+    f.render_widget(Paragraph::new(text).block(block).alignment(Alignment::Center), area);
+}
+
+fn draw_world_backups_popup(f: &mut Frame, entry: Option<&HistoryEntry>, backups: &[WorldBackup], list_state: &mut ratatui::widgets::ListState, outcome: Option<&Result<String, String>>) {
+    let dim = Style::default().fg(Color::DarkGray);
+    let area = centered_rect(f.size().width * 80 / 100, f.size().height * 80 / 100, f.size());
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(3)])
+        .split(area);
+
+    let (name, automatic) = match entry {
+        Some(entry) if entry.backup_worlds => (entry.display_name(), format!("on, keeping the last {} of each world", entry.keep_world_backups)),
+        Some(entry) => (entry.display_name(), "off".to_string()),
+        None => (String::new(), "off".to_string()),
+    };
+    let mut header = vec![Line::from(vec![Span::raw("Back up worlds before updates that change mods: "), Span::styled(automatic, *styles::GOLD_STYLE)])];
+    match outcome {
+        Some(Ok(message)) => header.push(Line::from(Span::styled(message.clone(), *styles::GREEN_STYLE))),
+        Some(Err(message)) => header.push(Line::from(Span::styled(message.clone(), *styles::RED_STYLE))),
+        None => {}
+    }
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(header).block(Block::default().title(format!(" World Backups: {} ", name)).borders(Borders::ALL)).wrap(ratatui::widgets::Wrap { trim: true }), layout[0]);
+
+    let items: Vec<ListItem> = if backups.is_empty() {
+        vec![ListItem::new(Span::styled("No backups yet. Press B to back up the worlds now.", dim))]
+    } else {
+        backups.iter().map(|backup| ListItem::new(Line::from(vec![
+            Span::styled(backup.world.clone(), Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(format!("  {} UTC", history::format_date(backup.created))),
+            Span::styled(format!("  ({}, {:.1} MB)", history::format_age(backup.created), backup.size as f64 / (1024.0 * 1024.0)), dim),
+        ]))).collect()
+    };
+    let hint = " ↑/↓ Select, Enter Restore, B Back up now, A Toggle automatic backups, Esc to close ";
+    let list = List::new(items)
+        .block(Block::default().title(Title::from(hint).position(ratatui::widgets::block::Position::Bottom)).borders(Borders::ALL))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    f.render_stateful_widget(list, layout[1], list_state);
+}
+
+fn draw_settings_popup(f: &mut Frame, settings: &Settings, error: Option<&str>, list_state: &mut ratatui::widgets::ListState, editing: Option<&Input>) {
+    const LABEL_WIDTH: usize = 22;
+    let dim = Style::default().fg(Color::DarkGray);
+    let list_height = Field::ALL.len() as u16 + 2;
+    let area = centered_rect(f.size().width * 70 / 100, (list_height + 7).min(f.size().height), f.size());
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(list_height), Constraint::Min(3)])
+        .split(area);
+    let selected = list_state.selected().unwrap_or(0);
+
+    let items: Vec<ListItem> = Field::ALL.iter().enumerate().map(|(i, field)| {
+        let value = match editing {
+            Some(input) if i == selected => Span::styled(input.value().to_string(), *styles::CYAN_STYLE),
+            _ => Span::styled(field.value(settings), *styles::GOLD_STYLE),
+        };
+        ListItem::new(Line::from(vec![Span::raw(format!("{:<width$}", field.label(), width = LABEL_WIDTH)), value]))
+    }).collect();
+    let hint = if editing.is_some() { " Enter Save, Esc Cancel " } else { " ↑/↓ Select, ←/→ or Enter Change, Esc to close " };
+    let list = List::new(items)
+        .block(Block::default().title(" Settings ").title(Title::from(hint).position(ratatui::widgets::block::Position::Bottom)).borders(Borders::ALL))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(list, layout[0], list_state);
+    if let Some(input) = editing {
+        let x = layout[0].x + 1 + 2 + LABEL_WIDTH as u16 + input.visual_cursor() as u16;
+        f.set_cursor(x.min(layout[0].right().saturating_sub(2)), layout[0].y + 1 + selected as u16);
+    }
+
+    let mut details = vec![Line::from(Field::ALL[selected].hint())];
+    if let Some(error) = error {
+        details.push(Line::from(Span::styled(format!("⚠ {}", error), *styles::RED_STYLE)));
+    }
+    if let Ok(path) = settings::get_settings_path() {
+        details.push(Line::from(Span::styled(format!("Saved to {}", path.display()), dim)));
+    }
+    f.render_widget(Paragraph::new(details).block(Block::default().borders(Borders::ALL)).wrap(ratatui::widgets::Wrap { trim: true }), layout[1]);
+}
+
+fn draw_confirm_restore_world_popup(f: &mut Frame, backup: &WorldBackup) {
+    let text = Text::from(vec![
+        Line::from(vec![Span::raw("Restore "), Span::styled(backup.world.clone(), Style::default().add_modifier(Modifier::BOLD)), Span::raw(format!(" from {} UTC?", history::format_date(backup.created)))]),
+        Line::from(""),
+        Line::from("The world as it is now is backed up first, so this can be undone."),
+        Line::from(""),
+        Line::from(vec![Span::styled(" Y ", *styles::KEY_STYLE_GREEN), Span::raw(" Restore "), Span::styled(" N ", *styles::KEY_STYLE_RED), Span::raw(" Cancel ")]),
+    ]);
+    let popup_width = (text.width() + 4).min(f.size().width.into());
+    let popup_height = (text.height() as u16 + 2).min(f.size().height);
+    let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
+    f.render_widget(Clear, area);
+    let block = Block::default().title(" Restore World Backup ").borders(Borders::ALL);
+    f.render_widget(Paragraph::new(text).block(block).alignment(Alignment::Center), area);
+}
+
+/// The last line of the finished message; extra details go above it.
+pub const CLOSE_HINT: &str = "Press Enter to close.";
+
+fn draw_finished_ui(f: &mut Frame, message: &str, changelog: Option<&PackChangelog>, problems: &[ModProblem], configs: &[ConfigChange], protected: &[String], removable: usize) {
+    let changelog = changelog.filter(|c| !c.commits.is_empty());
+    let mut text = Text::from(message);
+    if changelog.is_some() || !problems.is_empty() || !configs.is_empty() || !protected.is_empty() {
+        // The details go above the closing hint, leaving room for the message and borders.
+        let body = message.trim_end_matches(CLOSE_HINT).trim_end();
+        text = Text::from(body);
+        let mut room = (f.size().height as usize).saturating_sub(text.height() + 10).max(2);
+        let mut lines = Vec::new();
+        if !problems.is_empty() {
+            let shown = problems.len().min(room / 2).max(1);
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(format!("⚠ {} problem(s) with the installed mods:", problems.len()), *styles::RED_STYLE)));
+            lines.extend(problems.iter().take(shown).map(|problem| Line::from(problem.to_string())));
+            if problems.len() > shown {
+                lines.push(Line::from(Span::styled(format!("… and {} more (see Mods)", problems.len() - shown), Style::default().fg(Color::DarkGray))));
+            }
+            room = room.saturating_sub(shown + 2);
+        }
+        if !configs.is_empty() {
+            let shown = configs.len().min(room / 2).max(1);
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled("Configs reset to the pack's new defaults:", *styles::GOLD_STYLE)));
+            lines.extend(configs.iter().take(shown).map(|change| Line::from(change.to_string())));
+            if configs.len() > shown {
+                lines.push(Line::from(Span::styled(format!("… and {} more files", configs.len() - shown), Style::default().fg(Color::DarkGray))));
+            }
+            room = room.saturating_sub(shown + 2);
+        }
+        if !protected.is_empty() {
+            let shown = protected.len().min(room / 2).max(1);
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(format!("Kept your files the pack would have replaced (backed up in {}):", protect::BACKUP_DIR), *styles::GOLD_STYLE)));
+            lines.extend(protected.iter().take(shown).map(|file| Line::from(file.as_str())));
+            if protected.len() > shown {
+                lines.push(Line::from(Span::styled(format!("… and {} more files", protected.len() - shown), Style::default().fg(Color::DarkGray))));
+            }
+            room = room.saturating_sub(shown + 2);
+        }
+        if let Some(changelog) = changelog {
+            lines.extend(vec![Line::from(""), Line::from(vec![Span::styled("What changed: ", *styles::GOLD_STYLE), Span::raw(changelog.summary())])]);
+            lines.extend(mod_change_lines(changelog, room.clamp(1, 12)));
+        }
+        if removable > 0 {
+            lines.extend(vec![
+                Line::from(""),
+                Line::from(vec![Span::styled(" D ", *styles::KEY_STYLE_RED), Span::raw(format!(" Remove {} untracked duplicate jar(s)", removable))]),
+            ]);
+        }
+        if body.len() < message.len() {
+            lines.extend(vec![Line::from(""), Line::from(CLOSE_HINT)]);
+        }
+        text.extend(lines);
+    }
     let popup_width = (text.width() + 4).min(f.size().width.into());
     let popup_height = (text.height() as u16 + 2).min(f.size().height);
     let area = centered_rect(popup_width.try_into().unwrap(), popup_height, f.size());
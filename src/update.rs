@@ -1,47 +1,396 @@
 use crate::app::UpdateStatus;
-use anyhow::Result;
-use self_update::backends::github::Update;
+use crate::net;
+use anyhow::{bail, Context, Result};
+use reqwest::header;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use self_update::update::ReleaseAsset;
+use self_update::{Extract, Move, TempDir};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
 
-const REPO_OWNER: &str = "minecraftwithtwink";
-const REPO_NAME: &str = "Modpack-Updater";
+pub mod config;
+pub mod rollback;
+
+use config::{UpdateConfig, UpdateSource};
+
+
+/// The hex-encoded Ed25519 public key release assets are signed with, set when building a release.
+/// Builds without one refuse to update themselves.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("MODPACK_UPDATER_RELEASE_KEY");
+
+/// Every release asset is published with a detached signature: `<asset name>.sig`, holding the
+/// hex-encoded Ed25519 signature of the asset's bytes.
+const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// Which releases the updater offers to install.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    /// Prereleases too, for testing updater betas.
+    Beta,
+}
+
+impl UpdateChannel {
+    pub fn toggled(self) -> Self {
+        match self {
+            UpdateChannel::Stable => UpdateChannel::Beta,
+            UpdateChannel::Beta => UpdateChannel::Stable,
+        }
+    }
+}
+
+impl fmt::Display for UpdateChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateChannel::Stable => write!(f, "stable"),
+            UpdateChannel::Beta => write!(f, "beta"),
+        }
+    }
+}
+
+/// A release newer than the running version.
+#[derive(Debug, Clone)]
+pub struct AvailableRelease {
+    pub version: String,
+    pub prerelease: bool,
+    /// The release's notes, in Markdown.
+    pub notes: String,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+/// Progress of a self-update running in the background.
+pub enum SelfUpdateProgress {
+    Update(String, f64),
+    Done(Result<(), String>),
+}
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(Deserialize)]
+struct GithubAsset {
+    name: String,
+    /// The API URL, which serves the file itself when asked for `application/octet-stream`.
+    url: String,
+}
+
+/// The file a `manifest` update source points at.
+#[derive(Deserialize)]
+struct Manifest {
+    releases: Vec<ManifestRelease>,
+}
+
+#[derive(Deserialize)]
+struct ManifestRelease {
+    version: String,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    notes: String,
+    assets: Vec<ManifestAsset>,
+}
+
+#[derive(Deserialize)]
+struct ManifestAsset {
+    name: String,
+    url: String,
+}
+
+// Every published release from the source, drafts left out.
+fn list_releases(source: &UpdateSource) -> Result<Vec<AvailableRelease>> {
+    let client = net::client()?;
+    match source {
+        UpdateSource::Github { owner, repo, api } => {
+            // GitHub's "latest release" never includes prereleases, so the whole list is read instead.
+            let url = format!("{}/repos/{}/{}/releases?per_page=30", api.trim_end_matches('/'), owner, repo);
+            net::ensure_online(&url)?;
+            let body = net::retry(|| {
+                Ok(client
+                    .get(&url)
+                    .header(header::USER_AGENT, "modpack-updater")
+                    .header(header::ACCEPT, "application/vnd.github+json")
+                    .send()?
+                    .error_for_status()?
+                    .text()?)
+            })?;
+            let releases: Vec<GithubRelease> = serde_json::from_str(&body)?;
+            Ok(releases.into_iter().filter(|r| !r.draft).map(|release| AvailableRelease {
+                version: release.tag_name.trim_start_matches('v').to_string(),
+                prerelease: release.prerelease,
+                notes: release.body.unwrap_or_default(),
+                assets: release.assets.into_iter().map(|a| ReleaseAsset { name: a.name, download_url: a.url }).collect(),
+            }).collect())
+        }
+        UpdateSource::Manifest { url } => {
+            net::ensure_online(url)?;
+            let body = net::retry(|| Ok(client.get(url).header(header::USER_AGENT, "modpack-updater").send()?.error_for_status()?.text()?))?;
+            let manifest: Manifest = serde_json::from_str(&body).context(format!("'{}' is not a valid release manifest.", url))?;
+            Ok(manifest.releases.into_iter().map(|release| AvailableRelease {
+                version: release.version.trim_start_matches('v').to_string(),
+                prerelease: release.prerelease,
+                notes: release.notes,
+                assets: release.assets.into_iter().map(|a| ReleaseAsset { name: a.name, download_url: a.url }).collect(),
+            }).collect())
+        }
+    }
+}
+
+// The newest release on the channel, if it's newer than the running version.
+fn newest_release(source: &UpdateSource, channel: UpdateChannel) -> Result<Option<AvailableRelease>> {
+    let mut newest: Option<AvailableRelease> = None;
+    for release in list_releases(source)?.into_iter().filter(|r| channel == UpdateChannel::Beta || !r.prerelease) {
+        let baseline = newest.as_ref().map_or(env!("CARGO_PKG_VERSION"), |n| n.version.as_str());
+        if self_update::version::bump_is_greater(baseline, &release.version).unwrap_or(false) && !rollback::is_rolled_back(&release.version) {
+            newest = Some(release);
+        }
+    }
+    Ok(newest)
+}
 
 /// Checks for updates in the background and sends the result over a channel.
-pub fn check_for_updates_background(tx: Sender<UpdateStatus>) {
-    let result = (|| -> Result<UpdateStatus> {
-        let latest_release = Update::configure()
-            .repo_owner(REPO_OWNER)
-            .repo_name(REPO_NAME)
-            .bin_name("modpack-updater")
-            .current_version(env!("CARGO_PKG_VERSION"))
-            .build()?
-            .get_latest_release()?;
-
-        // This now correctly checks if the LATEST release version is greater than our CURRENT version.
-        if self_update::version::bump_is_greater(env!("CARGO_PKG_VERSION"), &latest_release.version)? {
-            Ok(UpdateStatus::UpdateAvailable(latest_release.version))
-        } else {
-            Ok(UpdateStatus::UpToDate)
-        }
-    })();
-
-    match result {
-        Ok(status) => tx.send(status).ok(),
-        Err(_err) => tx.send(UpdateStatus::Error).ok(),
+pub fn check_for_updates_background(config: UpdateConfig, channel: UpdateChannel, tx: Sender<UpdateStatus>) {
+    let status = match newest_release(&config.source, channel) {
+        Ok(Some(release)) => UpdateStatus::UpdateAvailable(release),
+        Ok(None) => {
+            config::record_check();
+            UpdateStatus::UpToDate
+        }
+        Err(_err) => UpdateStatus::Error,
     };
+    tx.send(status).ok();
 }
 
-/// Performs the self-update, showing progress to the console.
-pub fn perform_update() -> Result<()> {
-    Update::configure()
-        .repo_owner(REPO_OWNER)
-        .repo_name(REPO_NAME)
-        .bin_name("modpack-updater")
-        .current_version(env!("CARGO_PKG_VERSION"))
-        .show_download_progress(true)
-        .show_output(true)
-        .no_confirm(true)
-        .build()?
-        .update()?;
+pub fn perform_update_threaded(release: AvailableRelease, exe: PathBuf, tx: Sender<SelfUpdateProgress>) {
+    let progress = |message: String, ratio: f64| {
+        tx.send(SelfUpdateProgress::Update(message, ratio)).ok();
+    };
+    let result = perform_update(&release, &exe, &progress);
+    tx.send(SelfUpdateProgress::Done(result.map_err(|e| format!("{:#}", e)))).ok();
+}
+
+/// Downloads the release, checks its signature and replaces `exe` with it. Nothing is installed
+/// unless the signature matches the key this build was made with. The replaced binary is kept
+/// beside the new one so `--rollback` can restore it.
+pub fn perform_update(release: &AvailableRelease, exe: &Path, progress: &dyn Fn(String, f64)) -> Result<()> {
+    let public_key = RELEASE_PUBLIC_KEY.context("This build has no release signing key, so it can't verify updates. Download the new version manually.")?;
+    let public_key = decode_hex(public_key).context("The release signing key built into this version is not valid hex.")?;
+    install_release(release, exe, &public_key, progress)
+}
+
+// Everything `perform_update` does once it has the key, so tests can sign releases with their own.
+fn install_release(release: &AvailableRelease, exe: &Path, public_key: &[u8], progress: &dyn Fn(String, f64)) -> Result<()> {
+    let target = self_update::get_target();
+    let asset = release.assets.iter().find(|a| a.name.contains(target) && !a.name.ends_with(SIGNATURE_SUFFIX))
+        .context(format!("Release {} has no download for {}.", release.version, target))?;
+    let signature_name = format!("{}{}", asset.name, SIGNATURE_SUFFIX);
+    let signature_asset = release.assets.iter().find(|a| a.name == signature_name)
+        .context(format!("Release {} publishes no signature for '{}'. Refusing to install it.", release.version, asset.name))?;
+
+    progress(format!("Downloading {}...", asset.name), 0.0);
+    let download_dir = TempDir::new()?;
+    let archive_path = download_dir.path().join(&asset.name);
+    let archive = download(asset, &|ratio| progress(format!("Downloading {}...", asset.name), ratio * 0.9))?;
+    fs::write(&archive_path, archive)?;
+    let signature = String::from_utf8(download(signature_asset, &|_| {})?).context("The release signature is not text.")?;
+
+    progress("Verifying signature...".to_string(), 0.9);
+    verify_signature(&fs::read(&archive_path)?, &signature, public_key)
+        .context(format!("'{}' does not match its signature. It may have been tampered with, so it was not installed.", asset.name))?;
+
+    let bin_name = format!("modpack-updater{}", env::consts::EXE_SUFFIX);
+    Extract::from_source(&archive_path).extract_file(download_dir.path(), &bin_name)?;
+    let new_exe = download_dir.path().join(&bin_name);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755))?;
+    }
+
+    progress("Replacing the running version...".to_string(), 0.95);
+    rollback::keep_previous(exe)?;
+    let temp_dir = TempDir::new_in(exe.parent().context("The running executable has no parent folder.")?)?;
+    Move::from_source(&new_exe)
+        .replace_using_temp(&temp_dir.path().join("replaced"))
+        .to_dest(exe)?;
+    rollback::record_install(&release.version)?;
+    progress(format!("Installed version {}.", release.version), 1.0);
     Ok(())
-}
\ No newline at end of file
+}
+
+// Reports the share downloaded so far, when the server says how big the asset is. A dropped
+// download starts over.
+fn download(asset: &ReleaseAsset, progress: &dyn Fn(f64)) -> Result<Vec<u8>> {
+    net::ensure_online(&asset.download_url)?;
+    let client = net::download_client()?;
+    net::retry(|| download_with(&client, asset, progress))
+}
+
+fn download_with(client: &reqwest::blocking::Client, asset: &ReleaseAsset, progress: &dyn Fn(f64)) -> Result<Vec<u8>> {
+    let mut response = client
+        .get(&asset.download_url)
+        .header(header::USER_AGENT, "modpack-updater")
+        .header(header::ACCEPT, "application/octet-stream")
+        .send()?
+        .error_for_status()
+        .context(format!("Failed to download '{}'.", asset.name))?;
+    let total = response.content_length().unwrap_or(0);
+    let mut data = Vec::with_capacity(total as usize);
+    let mut chunk = [0; 64 * 1024];
+    loop {
+        let read = response.read(&mut chunk).context(format!("Failed to download '{}'.", asset.name))?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..read]);
+        if total > 0 {
+            progress((data.len() as f64 / total as f64).min(1.0));
+        }
+    }
+    Ok(data)
+}
+
+fn verify_signature(data: &[u8], signature_hex: &str, public_key: &[u8]) -> Result<()> {
+    let signature = decode_hex(signature_hex).context("The signature is not valid hex.")?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(data, &signature)
+        .map_err(|_| anyhow::anyhow!("Signature mismatch."))
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        bail!("Odd number of hex digits.");
+    }
+    digits.chunks(2)
+        .map(|pair| Ok(u8::from_str_radix(std::str::from_utf8(pair)?, 16)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Once;
+    use std::thread;
+
+    const RUNNING: &[u8] = b"the running version";
+    const RELEASED: &[u8] = b"the released version";
+
+    // Serves each file at `/<name>` for the rest of the test run; anything else is a 404.
+    fn serve(files: HashMap<String, Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for mut stream in listener.incoming().filter_map(Result::ok) {
+                let mut request = BufReader::new(&stream);
+                let mut request_line = String::new();
+                request.read_line(&mut request_line).ok();
+                let mut header = String::new();
+                while request.read_line(&mut header).is_ok_and(|read| read > 2) {
+                    header.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/").trim_start_matches('/');
+                let (status, body) = match files.get(path) {
+                    Some(body) => ("200 OK", body.as_slice()),
+                    None => ("404 Not Found", &[][..]),
+                };
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len()).ok();
+                stream.write_all(body).ok();
+            }
+        });
+        address
+    }
+
+    // Keeps the rollback records a successful install writes out of the real config folder.
+    fn use_temporary_config() {
+        static CONFIG: Once = Once::new();
+        CONFIG.call_once(|| env::set_var("XDG_CONFIG_HOME", env::temp_dir().join(format!("modpack-updater-tests-{}", std::process::id()))));
+    }
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn signature(key: &Ed25519KeyPair, data: &[u8]) -> Vec<u8> {
+        key.sign(data).as_ref().iter().map(|byte| format!("{:02x}", byte)).collect::<String>().into_bytes()
+    }
+
+    // A plain binary named for this target, which the updater installs as it is.
+    fn asset_name() -> String {
+        format!("modpack-updater-{}", self_update::get_target())
+    }
+
+    // Publishes `files` as a release and installs it over a fake running executable. Returns how the
+    // install went and what the executable holds afterwards.
+    fn install(files: HashMap<String, Vec<u8>>, key: &Ed25519KeyPair) -> (Result<()>, Vec<u8>) {
+        use_temporary_config();
+        let assets = files.keys().cloned().collect::<Vec<_>>();
+        let server = serve(files);
+        let release = AvailableRelease {
+            version: "99.0.0".to_string(),
+            prerelease: false,
+            notes: String::new(),
+            assets: assets.into_iter().map(|name| ReleaseAsset { download_url: format!("{}/{}", server, name), name }).collect(),
+        };
+        let dir = TempDir::new().unwrap();
+        let exe = dir.path().join("modpack-updater");
+        fs::write(&exe, RUNNING).unwrap();
+        let result = install_release(&release, &exe, key.public_key().as_ref(), &|_, _| {});
+        (result, fs::read(&exe).unwrap())
+    }
+
+    #[test]
+    fn installs_a_correctly_signed_release() {
+        let key = key_pair();
+        let files = HashMap::from([
+            (asset_name(), RELEASED.to_vec()),
+            (format!("{}{}", asset_name(), SIGNATURE_SUFFIX), signature(&key, RELEASED)),
+        ]);
+        let (result, exe) = install(files, &key);
+        result.unwrap();
+        assert_eq!(exe, RELEASED);
+    }
+
+    #[test]
+    fn refuses_a_tampered_release() {
+        let key = key_pair();
+        let files = HashMap::from([
+            (asset_name(), b"a tampered version".to_vec()),
+            (format!("{}{}", asset_name(), SIGNATURE_SUFFIX), signature(&key, RELEASED)),
+        ]);
+        let (result, exe) = install(files, &key);
+        assert!(format!("{:#}", result.unwrap_err()).contains("does not match its signature"));
+        assert_eq!(exe, RUNNING);
+    }
+
+    #[test]
+    fn refuses_a_release_without_a_signature() {
+        let key = key_pair();
+        let files = HashMap::from([(asset_name(), RELEASED.to_vec())]);
+        let (result, exe) = install(files, &key);
+        assert!(format!("{:#}", result.unwrap_err()).contains("publishes no signature"));
+        assert_eq!(exe, RUNNING);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::app::history::{self, get_config_dir};
+use crate::changelog::CHANGELOG_URL;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+const DEFAULT_REPO_OWNER: &str = "minecraftwithtwink";
+const DEFAULT_REPO_NAME: &str = "Modpack-Updater";
+const DEFAULT_GITHUB_API: &str = "https://api.github.com";
+const DAY: u64 = 24 * 60 * 60;
+
+/// Where the updater looks for new versions of itself and how often, the `[self_update]` section
+/// of `settings.toml`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpdateConfig {
+    #[serde(default)]
+    pub check: CheckFrequency,
+    /// Raw URL of the updater's own changelog, shown with `C` and after a self-update.
+    #[serde(default = "default_changelog_url")]
+    pub changelog_url: String,
+    #[serde(default)]
+    pub source: UpdateSource,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckFrequency {
+    /// On every launch.
+    #[default]
+    Launch,
+    /// At most once a day, until a check finds a new version.
+    Daily,
+    Never,
+}
+
+impl CheckFrequency {
+    pub fn next(self) -> Self {
+        match self {
+            CheckFrequency::Launch => CheckFrequency::Daily,
+            CheckFrequency::Daily => CheckFrequency::Never,
+            CheckFrequency::Never => CheckFrequency::Launch,
+        }
+    }
+}
+
+impl fmt::Display for CheckFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckFrequency::Launch => write!(f, "every launch"),
+            CheckFrequency::Daily => write!(f, "daily"),
+            CheckFrequency::Never => write!(f, "never"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UpdateSource {
+    /// The releases of a GitHub repository.
+    Github {
+        owner: String,
+        repo: String,
+        #[serde(default = "default_github_api")]
+        api: String,
+    },
+    /// A JSON file listing the releases, for updaters hosted anywhere else.
+    Manifest { url: String },
+}
+
+impl Default for UpdateSource {
+    fn default() -> Self {
+        UpdateSource::Github {
+            owner: DEFAULT_REPO_OWNER.to_string(),
+            repo: DEFAULT_REPO_NAME.to_string(),
+            api: default_github_api(),
+        }
+    }
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self { check: CheckFrequency::default(), changelog_url: default_changelog_url(), source: UpdateSource::default() }
+    }
+}
+
+fn default_changelog_url() -> String {
+    CHANGELOG_URL.to_string()
+}
+
+fn default_github_api() -> String {
+    DEFAULT_GITHUB_API.to_string()
+}
+
+// When the last check that found nothing new ran, for daily checks.
+fn get_last_check_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("last_update_check"))
+}
+
+impl UpdateConfig {
+    pub fn validate(&self) -> Result<()> {
+        let urls = match &self.source {
+            UpdateSource::Github { owner, repo, api } => {
+                if owner.trim().is_empty() || repo.trim().is_empty() {
+                    bail!("self_update.source needs an owner and a repo for GitHub.");
+                }
+                vec![api, &self.changelog_url]
+            }
+            UpdateSource::Manifest { url } => vec![url, &self.changelog_url],
+        };
+        if let Some(url) = urls.into_iter().find(|url| !url.starts_with("https://") && !url.starts_with("http://")) {
+            bail!("'{}' in [self_update] is not an http(s) URL.", url);
+        }
+        Ok(())
+    }
+
+    /// Whether the automatic check should run on this launch.
+    pub fn is_due(&self) -> bool {
+        match self.check {
+            CheckFrequency::Launch => true,
+            CheckFrequency::Never => false,
+            CheckFrequency::Daily => {
+                let last = get_last_check_path()
+                    .and_then(|path| Ok(fs::read_to_string(path)?))
+                    .ok()
+                    .and_then(|content| content.trim().parse::<u64>().ok());
+                last.is_none_or(|last| history::now().saturating_sub(last) >= DAY)
+            }
+        }
+    }
+}
+
+/// Remembers that a check found nothing new, so daily checks skip the rest of the day.
+pub fn record_check() {
+    if let Ok(path) = get_last_check_path() {
+        fs::write(path, history::now().to_string()).ok();
+    }
+}
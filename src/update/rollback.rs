@@ -0,0 +1,155 @@
+use crate::app::history::{self, get_config_dir};
+use anyhow::{bail, Context, Result};
+use self_update::{Move, TempDir};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Appended to the executable's file name for the copy of the version a self-update replaced.
+const PREVIOUS_SUFFIX: &str = ".previous";
+
+/// A version of the updater that ran on this machine, as recorded in `versions.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledVersion {
+    pub version: String,
+    pub installed: u64,
+    /// The version this one replaced, whose binary is kept beside the executable.
+    #[serde(default)]
+    pub previous: Option<String>,
+    /// Whether the interface ever finished starting. Versions installed by hand are trusted.
+    #[serde(default)]
+    pub started: bool,
+    #[serde(default)]
+    pub launches: u32,
+    #[serde(default)]
+    pub rolled_back: bool,
+}
+
+/// A self-updated version that was launched before but never finished starting.
+pub struct FailedLaunch {
+    pub version: String,
+    pub previous: String,
+}
+
+fn get_versions_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("versions.json"))
+}
+
+/// Every version recorded on this machine, oldest first.
+pub fn load() -> Vec<InstalledVersion> {
+    get_versions_path()
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(versions: &[InstalledVersion]) -> Result<()> {
+    fs::write(get_versions_path()?, serde_json::to_string_pretty(versions)?)?;
+    Ok(())
+}
+
+pub fn previous_binary_path(exe: &Path) -> PathBuf {
+    let mut name = exe.file_name().unwrap_or_default().to_os_string();
+    name.push(PREVIOUS_SUFFIX);
+    exe.with_file_name(name)
+}
+
+/// Copies the running executable beside itself, so a self-update can be undone.
+pub fn keep_previous(exe: &Path) -> Result<()> {
+    let previous = previous_binary_path(exe);
+    fs::copy(exe, &previous).context(format!("Failed to keep the current version as '{}'.", previous.display()))?;
+    Ok(())
+}
+
+/// Records that `version` replaced the running one. It counts as broken until it starts.
+pub fn record_install(version: &str) -> Result<()> {
+    let mut versions = load();
+    versions.push(InstalledVersion {
+        version: version.to_string(),
+        installed: history::now(),
+        previous: Some(env!("CARGO_PKG_VERSION").to_string()),
+        started: false,
+        launches: 0,
+        rolled_back: false,
+    });
+    save(&versions)
+}
+
+/// Counts a launch of the running version. Returns the failed launch when a self-updated version
+/// was already launched once without ever starting and its predecessor can still be restored.
+pub fn record_launch() -> Option<FailedLaunch> {
+    let current = env!("CARGO_PKG_VERSION");
+    let mut versions = load();
+    let Some(record) = versions.iter_mut().rev().find(|v| v.version == current) else {
+        versions.push(InstalledVersion {
+            version: current.to_string(),
+            installed: history::now(),
+            previous: None,
+            started: true,
+            launches: 1,
+            rolled_back: false,
+        });
+        save(&versions).ok();
+        return None;
+    };
+    record.launches += 1;
+    let failed = match (&record.previous, record.started, record.launches) {
+        (Some(previous), false, launches) if launches > 1 && has_previous_binary() => {
+            Some(FailedLaunch { version: record.version.clone(), previous: previous.clone() })
+        }
+        _ => None,
+    };
+    save(&versions).ok();
+    failed
+}
+
+/// Marks the running version as good once the interface is up.
+pub fn record_started() {
+    let current = env!("CARGO_PKG_VERSION");
+    let mut versions = load();
+    if let Some(record) = versions.iter_mut().rev().find(|v| v.version == current) {
+        if !record.started {
+            record.started = true;
+            save(&versions).ok();
+        }
+    }
+}
+
+/// Whether `version` was rolled back on this machine, so it shouldn't be offered again.
+pub fn is_rolled_back(version: &str) -> bool {
+    load().iter().any(|v| v.version == version && v.rolled_back)
+}
+
+fn has_previous_binary() -> bool {
+    env::current_exe().map(|exe| previous_binary_path(&exe).is_file()).unwrap_or(false)
+}
+
+/// Puts the version kept by the last self-update back in place of the running executable.
+/// Returns which version that was, for messages.
+pub fn rollback() -> Result<String> {
+    let exe = env::current_exe()?;
+    let previous = previous_binary_path(&exe);
+    if !previous.is_file() {
+        bail!("No previous version is kept beside '{}', so there is nothing to roll back to.", exe.display());
+    }
+
+    let temp_dir = TempDir::new_in(exe.parent().context("The running executable has no parent folder.")?)?;
+    Move::from_source(&previous)
+        .replace_using_temp(&temp_dir.path().join("replaced"))
+        .to_dest(&exe)
+        .context("Failed to restore the previous version.")?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let mut versions = load();
+    let restored = match versions.iter_mut().rev().find(|v| v.version == current) {
+        Some(record) => {
+            record.rolled_back = true;
+            record.previous.clone()
+        }
+        None => None,
+    };
+    save(&versions)?;
+    Ok(restored.map_or_else(|| "the previous version".to_string(), |version| format!("version {}", version)))
+}
@@ -0,0 +1,156 @@
+use crate::app::history;
+use crate::configs::relative_files;
+use crate::pack::Side;
+use crate::running;
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Folder in the instance holding world backups, one subfolder per world.
+pub const BACKUP_DIR: &str = ".modpack-updater/world-backups";
+
+/// How many backups of each world are kept unless the instance says otherwise.
+pub const DEFAULT_KEEP: usize = 5;
+
+/// A zipped copy of a world, named after the Unix time it was taken at.
+#[derive(Debug, Clone)]
+pub struct WorldBackup {
+    pub world: String,
+    pub created: u64,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Progress of a backup or restore started from the World Backups screen.
+pub enum WorldProgress {
+    Update(String, f64),
+    Done(Result<String, String>),
+}
+
+/// Zips every world of the instance and drops all but the newest `keep` backups of each.
+pub fn backup_all(instance_path: &Path, side: Side, keep: usize, progress: &dyn Fn(String, f64)) -> Result<Vec<WorldBackup>> {
+    let mut backups = Vec::new();
+    for world_dir in running::world_dirs(instance_path, side) {
+        let backup = backup_world(instance_path, &world_dir, progress)?;
+        prune(instance_path, &backup.world, keep)?;
+        backups.push(backup);
+    }
+    Ok(backups)
+}
+
+/// Every backup in the instance, grouped by world with the newest first.
+pub fn list(instance_path: &Path) -> Vec<WorldBackup> {
+    let Ok(worlds) = fs::read_dir(instance_path.join(BACKUP_DIR)) else { return Vec::new() };
+    let mut backups: Vec<WorldBackup> = worlds
+        .filter_map(Result::ok)
+        .filter_map(|world| Some((world.file_name().to_string_lossy().into_owned(), fs::read_dir(world.path()).ok()?)))
+        .flat_map(|(world, entries)| {
+            entries.filter_map(Result::ok).filter_map(move |entry| {
+                let path = entry.path();
+                if path.extension()? != "zip" {
+                    return None;
+                }
+                let created = path.file_stem()?.to_str()?.parse().ok()?;
+                let size = entry.metadata().ok()?.len();
+                Some(WorldBackup { world: world.clone(), created, path, size })
+            })
+        })
+        .collect();
+    backups.sort_by(|a, b| a.world.cmp(&b.world).then(b.created.cmp(&a.created)));
+    backups
+}
+
+pub fn backup_threaded(instance_path: PathBuf, side: Side, keep: usize, tx: Sender<WorldProgress>) {
+    let progress = |message: String, ratio: f64| {
+        tx.send(WorldProgress::Update(message, ratio)).ok();
+    };
+    let result = running::ensure_not_running(&instance_path, side)
+        .and_then(|()| backup_all(&instance_path, side, keep, &progress))
+        .map(|backups| match backups.len() {
+            0 => "There are no worlds to back up yet.".to_string(),
+            count => format!("Backed up {} world(s).", count),
+        });
+    tx.send(WorldProgress::Done(result.map_err(|e| format!("{:#}", e)))).ok();
+}
+
+/// Replaces a world with one of its backups. The world as it is now is backed up first.
+pub fn restore_threaded(instance_path: PathBuf, side: Side, backup: WorldBackup, tx: Sender<WorldProgress>) {
+    let progress = |message: String, ratio: f64| {
+        tx.send(WorldProgress::Update(message, ratio)).ok();
+    };
+    let result = (|| -> Result<String> {
+        running::ensure_not_running(&instance_path, side)?;
+        let target = match side {
+            Side::Client => instance_path.join("saves").join(&backup.world),
+            Side::Server => instance_path.join(&backup.world),
+        };
+        if target.join("level.dat").is_file() {
+            backup_world(&instance_path, &target, &progress)?;
+        }
+
+        // Extracted next to the world first, so a broken zip leaves the world untouched.
+        let staging = target.with_file_name(format!("{}.restoring", backup.world));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        let mut archive = ZipArchive::new(File::open(&backup.path)?).context(format!("'{}' is not a valid backup.", backup.path.display()))?;
+        let count = archive.len();
+        for i in 0..count {
+            let mut file = archive.by_index(i)?;
+            let Some(relative) = file.enclosed_name() else { continue };
+            let dest = staging.join(relative);
+            if file.is_dir() {
+                fs::create_dir_all(&dest)?;
+                continue;
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&dest)?)?;
+            progress(format!("Restoring '{}': {}/{} files", backup.world, i + 1, count), (i + 1) as f64 / count as f64);
+        }
+        if target.exists() {
+            fs::remove_dir_all(&target).context(format!("Failed to remove the current '{}'.", backup.world))?;
+        }
+        fs::rename(&staging, &target)?;
+        Ok(format!("Restored '{}' from {} UTC.", backup.world, history::format_date(backup.created)))
+    })();
+    tx.send(WorldProgress::Done(result.map_err(|e| format!("{:#}", e)))).ok();
+}
+
+fn backup_world(instance_path: &Path, world_dir: &Path, progress: &dyn Fn(String, f64)) -> Result<WorldBackup> {
+    let world = world_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "world".to_string());
+    let dir = instance_path.join(BACKUP_DIR).join(&world);
+    fs::create_dir_all(&dir)?;
+    let mut created = history::now();
+    while dir.join(format!("{}.zip", created)).exists() {
+        created += 1;
+    }
+    let path = dir.join(format!("{}.zip", created));
+
+    // Written under another name until it's complete, so an interrupted backup is never listed.
+    let partial = path.with_extension("zip.partial");
+    let files: Vec<String> = relative_files(world_dir)?.into_iter().filter(|file| file != "session.lock").collect();
+    let mut zip = ZipWriter::new(File::create(&partial).context(format!("Failed to create '{}'.", partial.display()))?);
+    let options = SimpleFileOptions::default().large_file(true);
+    for (i, file) in files.iter().enumerate() {
+        zip.start_file(file.as_str(), options)?;
+        io::copy(&mut File::open(world_dir.join(file))?, &mut zip).context(format!("Failed to back up '{}'.", file))?;
+        progress(format!("Backing up world '{}': {}/{} files", world, i + 1, files.len()), (i + 1) as f64 / files.len() as f64);
+    }
+    zip.finish()?;
+    fs::rename(&partial, &path)?;
+    let size = fs::metadata(&path)?.len();
+    Ok(WorldBackup { world, created, path, size })
+}
+
+fn prune(instance_path: &Path, world: &str, keep: usize) -> Result<()> {
+    for backup in list(instance_path).into_iter().filter(|b| b.world == world).skip(keep.max(1)) {
+        fs::remove_file(&backup.path)?;
+    }
+    Ok(())
+}